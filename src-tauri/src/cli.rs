@@ -0,0 +1,640 @@
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use timelapse_creator::convert::{
+    convert_batch, convert_jobs, ConversionRequest, ConversionResult, ProgressEvent,
+};
+use timelapse_creator::formats::{AnimationOptions, Dither, OutputFormat, VideoCodec};
+use timelapse_creator::geometry::{AspectRatio, CropRect, Fit, Focus, Geometry};
+use timelapse_creator::manifest::{import_manifest, InvalidRow};
+use timelapse_creator::renditions::Rendition;
+
+/// Every file was converted
+pub const EXIT_SUCCESS: i32 = 0;
+/// Nothing was converted, or the batch could not start
+pub const EXIT_FAILURE: i32 = 1;
+/// Invalid command-line arguments
+pub const EXIT_USAGE: i32 = 2;
+/// Some files were converted and some failed
+pub const EXIT_PARTIAL: i32 = 3;
+
+/// Subcommands that select the command-line interface instead of the app window
+#[cfg(feature = "tauri")]
+const CLI_COMMANDS: &[&str] = &["convert", "help", "--help", "-h", "--version", "-V"];
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "timelapse-creator",
+    version,
+    about = "Create timelapses from videos",
+    long_about = "Create timelapses from videos. Run without arguments to open the app."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: CliCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum CliCommand {
+    /// Convert videos to timelapses without opening the app window
+    Convert(ConvertArgs),
+}
+
+#[derive(Debug, Args)]
+struct ConvertArgs {
+    /// Speed multiplier (2-1000); with --manifest, used for rows without one
+    #[arg(short, long, required_unless_present = "manifest")]
+    speed: Option<u32>,
+
+    /// Output directory [default: an `outputs` folder next to each input]
+    #[arg(short, long)]
+    out: Option<String>,
+
+    /// Clockwise rotation that replaces the detected one (0, 90, 180 or 270)
+    #[arg(long)]
+    rotate: Option<u32>,
+
+    /// Output format: mp4, mp4_faststart, fragmented_mp4 or hls for video;
+    /// gif, apng or webp for animations; png_sequence, jpeg_sequence or
+    /// tiff_sequence for a folder of numbered frames
+    #[arg(long, default_value = "mp4")]
+    format: OutputFormat,
+
+    /// Scale down to at most this many lines, keeping the aspect ratio
+    #[arg(long)]
+    max_height: Option<u32>,
+
+    /// Keep only this part of the source, as width:height[:x:y] in pixels;
+    /// centered without a position
+    #[arg(long)]
+    crop: Option<CropRect>,
+
+    /// Convert to this shape: 16:9, 9:16, 1:1 or 4:5
+    #[arg(long)]
+    aspect: Option<AspectRatio>,
+
+    /// How --aspect changes the shape: crop the edges, pad, or blur to
+    /// place the picture over a blurred, enlarged copy of itself
+    #[arg(long, default_value = "crop")]
+    fit: Fit,
+
+    /// Point to keep in view when the shape changes, as x,y fractions of
+    /// the picture, e.g. 0.3,0.5 [default: the center]
+    #[arg(long)]
+    focus: Option<Focus>,
+
+    /// Scale to this width, keeping the aspect ratio
+    #[arg(long)]
+    width: Option<u32>,
+
+    /// Scale to this height, or with --width to fit within both
+    #[arg(long)]
+    height: Option<u32>,
+
+    /// With --width and --height, pad to exactly that size, over a blurred
+    /// copy with --fit blur
+    #[arg(long, requires_all = ["width", "height"])]
+    letterbox: bool,
+
+    /// Color of padded areas, e.g. black, white or #202020
+    #[arg(long)]
+    pad_color: Option<String>,
+
+    /// Video codec: h264 or h265
+    #[arg(long, default_value = "h264")]
+    codec: VideoCodec,
+
+    /// One output made from the same decode, as name:format[:height[:codec]],
+    /// e.g. web:mp4_faststart:720; repeat for more. With any --rendition,
+    /// --format is ignored. Height and codec fall back to --max-height and
+    /// --codec.
+    #[arg(long = "rendition", value_name = "RENDITION")]
+    renditions: Vec<Rendition>,
+
+    /// Animations: frames per second [default: 15]
+    #[arg(long)]
+    fps: Option<f64>,
+
+    /// Animations: maximum width in pixels [default: 480]
+    #[arg(long)]
+    max_width: Option<u32>,
+
+    /// Animations: lower the frame rate to stay within this many frames
+    #[arg(long)]
+    max_frames: Option<u32>,
+
+    /// Animations: scale down until the file is at most this many bytes
+    #[arg(long)]
+    target_bytes: Option<u64>,
+
+    /// Animations: times to play [default: forever]
+    #[arg(long)]
+    loops: Option<u32>,
+
+    /// GIF dithering: none, bayer, floyd_steinberg or sierra2_4a
+    #[arg(long, default_value = "sierra2_4a")]
+    dither: Dither,
+
+    /// Image sequences: write the source position of every frame to
+    /// timestamps.csv in the frame folder
+    #[arg(long)]
+    frame_timestamps: bool,
+
+    /// Print progress events and the final result as JSON lines on stdout
+    #[arg(long)]
+    json: bool,
+
+    /// JSON or CSV list of clips with per-clip speed and trim settings
+    #[arg(long, conflicts_with = "inputs")]
+    manifest: Option<String>,
+
+    /// Input videos; glob patterns are expanded when the shell didn't
+    #[arg(required_unless_present = "manifest")]
+    inputs: Vec<String>,
+}
+
+/// One line of `--json` output
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonLine<'a> {
+    Progress(&'a ProgressEvent),
+    Invalid(&'a InvalidRow),
+    Result(&'a ConversionResult),
+}
+
+/// True when the arguments ask for the command-line interface
+#[cfg(feature = "tauri")]
+pub fn is_cli_invocation(args: &[String]) -> bool {
+    args.get(1)
+        .is_some_and(|arg| CLI_COMMANDS.contains(&arg.as_str()))
+}
+
+/// Run the command-line interface and return the process exit code
+pub fn run(args: Vec<String>) -> i32 {
+    attach_parent_console();
+
+    let cli = match Cli::try_parse_from(args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() {
+                EXIT_USAGE
+            } else {
+                EXIT_SUCCESS
+            };
+        }
+    };
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start async runtime: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+
+    match cli.command {
+        CliCommand::Convert(args) => runtime.block_on(convert(args)),
+    }
+}
+
+async fn convert(args: ConvertArgs) -> i32 {
+    let request = ConversionRequest {
+        files: expand_inputs(&args.inputs),
+        // Without --speed this is zero, which manifest rows read as no default
+        speed_multiplier: args.speed.unwrap_or_default(),
+        rotation_override: args.rotate,
+        output_dir: args.out,
+        output_format: args.format,
+        animation: AnimationOptions {
+            fps: args.fps,
+            max_width: args.max_width.or(AnimationOptions::default().max_width),
+            max_frames: args.max_frames,
+            target_bytes: args.target_bytes,
+            loops: args.loops,
+            dither: args.dither,
+        },
+        frame_timestamps: args.frame_timestamps,
+        max_height: args.max_height,
+        geometry: Geometry {
+            crop: args.crop,
+            aspect: args.aspect,
+            fit: args.fit,
+            focus: args.focus,
+            width: args.width,
+            height: args.height,
+            pad: args.letterbox,
+            pad_color: args.pad_color,
+        },
+        video_codec: args.codec,
+        renditions: args.renditions,
+        ..Default::default()
+    };
+
+    let json = args.json;
+    let interactive = !json && std::io::stderr().is_terminal();
+    let on_progress = |event: ProgressEvent| {
+        if json {
+            print_json(&JsonLine::Progress(&event));
+        } else {
+            print_progress(&event, interactive);
+        }
+    };
+
+    let mut skipped = 0;
+    let result = match &args.manifest {
+        Some(manifest) => {
+            let import = match import_manifest(std::path::Path::new(manifest), &request).await {
+                Ok(import) => import,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return EXIT_FAILURE;
+                }
+            };
+            for row in &import.invalid {
+                if json {
+                    print_json(&JsonLine::Invalid(row));
+                } else {
+                    eprintln!("{}", invalid_row_message(manifest, row));
+                }
+            }
+            skipped = import.invalid.len();
+            convert_jobs(&import.jobs, on_progress).await
+        }
+        None => convert_batch(&request, on_progress).await,
+    };
+
+    if json {
+        print_json(&JsonLine::Result(&result));
+    } else {
+        println!("{}", result.message);
+        for output in &result.output_files {
+            println!("  {}", output);
+        }
+        if let Some(report) = &result.report_path {
+            println!("Report: {}", report);
+        }
+    }
+
+    exit_code(&result, skipped)
+}
+
+/// Map a batch result and the number of skipped manifest rows to an exit code
+fn exit_code(result: &ConversionResult, skipped: usize) -> i32 {
+    if result.failed_count == 0 && skipped == 0 {
+        EXIT_SUCCESS
+    } else if result.converted_count > 0 {
+        EXIT_PARTIAL
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+/// Expand glob patterns that reached us unexpanded (e.g. from cmd.exe);
+/// anything that doesn't match an existing file is passed through as is
+fn expand_inputs(inputs: &[String]) -> Vec<String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let is_pattern = input.contains(['*', '?', '[']);
+        let matches: Vec<String> = if is_pattern && !std::path::Path::new(input).exists() {
+            glob::glob(input)
+                .map(|paths| {
+                    paths
+                        .filter_map(Result::ok)
+                        .filter(|p| p.is_file())
+                        .map(|p| p.to_string_lossy().to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        if matches.is_empty() {
+            files.push(input.clone());
+        } else {
+            files.extend(matches);
+        }
+    }
+    files
+}
+
+/// Describe an invalid manifest row as `manifest:line: path: reason`
+fn invalid_row_message(manifest: &str, row: &InvalidRow) -> String {
+    match &row.path {
+        Some(path) => format!("{}:{}: {}: {}", manifest, row.line, path, row.reason),
+        None => format!("{}:{}: {}", manifest, row.line, row.reason),
+    }
+}
+
+fn print_json(line: &JsonLine) {
+    if let Ok(json) = serde_json::to_string(line) {
+        println!("{}", json);
+    }
+}
+
+/// Print progress to stderr, redrawing the current line on a terminal.
+/// Percentage updates are only shown on a terminal to keep logs short.
+fn print_progress(event: &ProgressEvent, interactive: bool) {
+    let is_update = event.status.starts_with("Converting");
+    let name = match &event.rendition {
+        Some(rendition) => format!("{} ({})", event.filename, rendition),
+        None => event.filename.clone(),
+    };
+    let line = format!(
+        "[{}/{}] {}: {}",
+        event.current_file, event.total_files, name, event.status
+    );
+
+    let mut stderr = std::io::stderr().lock();
+    if interactive {
+        let _ = write!(stderr, "\r\x1b[2K{}", line);
+        if !is_update && event.status != "Starting..." {
+            let _ = writeln!(stderr);
+        }
+        let _ = stderr.flush();
+    } else if !is_update {
+        let _ = writeln!(stderr, "{}", line);
+    }
+}
+
+/// Release builds on Windows use the GUI subsystem and have no console;
+/// reattach to the terminal that started us so output is visible
+#[cfg(windows)]
+fn attach_parent_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_parent_console() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    #[cfg(feature = "tauri")]
+    fn test_is_cli_invocation() {
+        assert!(is_cli_invocation(&args(&["timelapse-creator", "convert"])));
+        assert!(is_cli_invocation(&args(&["timelapse-creator", "--help"])));
+        assert!(!is_cli_invocation(&args(&["timelapse-creator"])));
+        // macOS passes a process serial number when launched from Finder
+        assert!(!is_cli_invocation(&args(&[
+            "timelapse-creator",
+            "-psn_0_12345"
+        ])));
+    }
+
+    #[test]
+    fn test_parse_convert_args() {
+        let cli = Cli::try_parse_from(args(&[
+            "timelapse-creator",
+            "convert",
+            "--speed",
+            "100",
+            "--out",
+            "dir/",
+            "--json",
+            "a.mp4",
+            "b.mp4",
+        ]))
+        .unwrap();
+        let CliCommand::Convert(convert) = cli.command;
+        assert_eq!(convert.speed, Some(100));
+        assert_eq!(convert.out.as_deref(), Some("dir/"));
+        assert!(convert.json);
+        assert_eq!(convert.inputs, vec!["a.mp4", "b.mp4"]);
+        assert_eq!(convert.format, OutputFormat::Mp4);
+    }
+
+    #[test]
+    fn test_parse_animation_args() {
+        let cli = Cli::try_parse_from(args(&[
+            "timelapse-creator",
+            "convert",
+            "--speed",
+            "100",
+            "--format",
+            "gif",
+            "--max-width",
+            "320",
+            "--target-bytes",
+            "2000000",
+            "--dither",
+            "bayer",
+            "a.mp4",
+        ]))
+        .unwrap();
+        let CliCommand::Convert(convert) = cli.command;
+        assert_eq!(convert.format, OutputFormat::Gif);
+        assert_eq!(convert.max_width, Some(320));
+        assert_eq!(convert.target_bytes, Some(2_000_000));
+        assert_eq!(convert.dither, Dither::Bayer);
+
+        assert!(Cli::try_parse_from(args(&[
+            "timelapse-creator",
+            "convert",
+            "--speed",
+            "100",
+            "--format",
+            "avi",
+            "a.mp4",
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_image_sequence_args() {
+        let cli = Cli::try_parse_from(args(&[
+            "timelapse-creator",
+            "convert",
+            "--speed",
+            "100",
+            "--format",
+            "tiff-sequence",
+            "--frame-timestamps",
+            "a.mp4",
+        ]))
+        .unwrap();
+        let CliCommand::Convert(convert) = cli.command;
+        assert_eq!(convert.format, OutputFormat::TiffSequence);
+        assert!(convert.frame_timestamps);
+    }
+
+    #[test]
+    fn test_parse_rendition_args() {
+        let cli = Cli::try_parse_from(args(&[
+            "timelapse-creator",
+            "convert",
+            "--speed",
+            "100",
+            "--rendition",
+            "master:mp4",
+            "--rendition",
+            "web:mp4_faststart:720",
+            "--rendition",
+            "teaser:gif",
+            "a.mp4",
+        ]))
+        .unwrap();
+        let CliCommand::Convert(convert) = cli.command;
+        let names: Vec<&str> = convert.renditions.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["master", "web", "teaser"]);
+        assert_eq!(convert.renditions[1].max_height, Some(720));
+        assert_eq!(convert.codec, VideoCodec::H264);
+
+        assert!(Cli::try_parse_from(args(&[
+            "timelapse-creator",
+            "convert",
+            "--speed",
+            "100",
+            "--rendition",
+            "web:mp4:big",
+            "a.mp4",
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_geometry_args() {
+        let cli = Cli::try_parse_from(args(&[
+            "timelapse-creator",
+            "convert",
+            "--speed",
+            "100",
+            "--aspect",
+            "9:16",
+            "--fit",
+            "blur",
+            "--focus",
+            "0.3,0.5",
+            "--width",
+            "1080",
+            "--height",
+            "1920",
+            "--letterbox",
+            "a.mp4",
+        ]))
+        .unwrap();
+        let CliCommand::Convert(convert) = cli.command;
+        assert_eq!(convert.aspect, Some(AspectRatio::Vertical));
+        assert_eq!(convert.fit, Fit::Blur);
+        assert_eq!(convert.focus, Some(Focus { x: 0.3, y: 0.5 }));
+        assert!(convert.letterbox);
+        assert_eq!(convert.crop, None);
+
+        // Letterboxing needs the box size
+        assert!(Cli::try_parse_from(args(&[
+            "timelapse-creator",
+            "convert",
+            "--speed",
+            "100",
+            "--width",
+            "1080",
+            "--letterbox",
+            "a.mp4",
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_convert_requires_speed_and_inputs() {
+        assert!(Cli::try_parse_from(args(&["timelapse-creator", "convert", "a.mp4"])).is_err());
+        assert!(
+            Cli::try_parse_from(args(&["timelapse-creator", "convert", "--speed", "10"])).is_err()
+        );
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let result = |converted_count, failed_count| ConversionResult {
+            success: converted_count > 0,
+            message: String::new(),
+            converted_count,
+            failed_count,
+            verification_failed_count: 0,
+            output_files: vec![],
+            files: vec![],
+            report_path: None,
+        };
+        assert_eq!(exit_code(&result(2, 0), 0), EXIT_SUCCESS);
+        assert_eq!(exit_code(&result(1, 1), 0), EXIT_PARTIAL);
+        assert_eq!(exit_code(&result(0, 2), 0), EXIT_FAILURE);
+        assert_eq!(exit_code(&result(2, 0), 1), EXIT_PARTIAL);
+        assert_eq!(exit_code(&result(0, 0), 1), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_parse_manifest_args() {
+        let cli = Cli::try_parse_from(args(&[
+            "timelapse-creator",
+            "convert",
+            "--manifest",
+            "clips.csv",
+        ]))
+        .unwrap();
+        let CliCommand::Convert(convert) = cli.command;
+        assert_eq!(convert.manifest.as_deref(), Some("clips.csv"));
+        assert_eq!(convert.speed, None);
+        assert!(convert.inputs.is_empty());
+
+        assert!(Cli::try_parse_from(args(&[
+            "timelapse-creator",
+            "convert",
+            "--manifest",
+            "clips.csv",
+            "a.mp4",
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_invalid_row_message() {
+        let row = InvalidRow {
+            line: 7,
+            path: Some("a.txt".to_string()),
+            reason: "Unsupported format".to_string(),
+        };
+        assert_eq!(
+            invalid_row_message("clips.csv", &row),
+            "clips.csv:7: a.txt: Unsupported format"
+        );
+    }
+
+    #[test]
+    fn test_expand_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.mp4"), b"").unwrap();
+        std::fs::write(dir.path().join("b.mp4"), b"").unwrap();
+
+        let pattern = dir.path().join("*.mp4").to_string_lossy().to_string();
+        let files = expand_inputs(&[pattern, "missing.mp4".to_string()]);
+        assert_eq!(files.len(), 3);
+        assert!(files[0].ends_with("a.mp4"));
+        assert!(files[1].ends_with("b.mp4"));
+        assert_eq!(files[2], "missing.mp4");
+    }
+
+    #[test]
+    fn test_json_line_tagging() {
+        let event = ProgressEvent {
+            current_file: 1,
+            total_files: 1,
+            filename: "a.mp4".to_string(),
+            status: "Completed".to_string(),
+            output_path: None,
+            progress: Some(1.0),
+            rendition: None,
+        };
+        let json = serde_json::to_string(&JsonLine::Progress(&event)).unwrap();
+        assert!(json.starts_with("{\"type\":\"progress\""));
+        assert!(json.contains("\"filename\":\"a.mp4\""));
+    }
+}
@@ -1,9 +1,26 @@
-use crate::video::{get_info, get_output_path, is_supported_format, VideoInfo, SUPPORTED_FORMATS};
 use serde::{Deserialize, Serialize};
-use std::process::Stdio;
-use tauri::{Emitter, Window};
+use tauri::{AppHandle, Emitter, Manager, Window};
 use tauri_plugin_dialog::DialogExt;
-use tokio::process::Command as TokioCommand;
+use timelapse_creator::convert::{convert_batch, ConversionRequest, ConversionResult};
+use timelapse_creator::estimate::{estimate_batch, BatchEstimate, ThroughputStore};
+use timelapse_creator::frames::{attach_posters, extract_frame as extract, ImageFormat};
+use timelapse_creator::import::{import_folders, FolderImportResult, ImportFilter};
+use timelapse_creator::manifest::{import_manifest, ManifestImport};
+use timelapse_creator::preflight::{preflight as check_batch, PreflightReport};
+use timelapse_creator::presets::{Preset, PresetImportResult, PresetStore};
+use timelapse_creator::preview::{render_preview as render, Preview, PreviewOptions};
+use timelapse_creator::project::{
+    load_project as read_project, save_project as write_project, LoadedProject, Project,
+    RelinkCandidate,
+};
+use timelapse_creator::settings::{RecentFile, Settings, SettingsStore};
+use timelapse_creator::sheets::{
+    contact_sheet, sprite_sheet, ContactSheet, ContactSheetOptions, SpriteSheet, SpriteSheetOptions,
+};
+use timelapse_creator::video::{
+    cached_frame_count, count_frames, get_info_cached, get_info_many, is_supported_format,
+    FrameCountMode, VideoInfo, SUPPORTED_FORMATS,
+};
 
 /// Result of video selection
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,40 +29,39 @@ pub struct SelectionResult {
     pub count: usize,
 }
 
-/// Conversion request
+/// A single probe result, emitted as soon as it is ready
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ConversionRequest {
-    pub files: Vec<String>,
-    pub speed_multiplier: u32,
+pub struct VideoInfoEvent {
+    pub index: usize,
+    pub total: usize,
+    pub info: VideoInfo,
 }
 
-/// Conversion result
+/// Result of a background frame count
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ConversionResult {
-    pub success: bool,
-    pub message: String,
-    pub converted_count: usize,
-    pub failed_count: usize,
-    pub output_files: Vec<String>,
+pub struct FrameCountEvent {
+    pub path: String,
+    pub total_frames: Option<u64>,
+    pub error: Option<String>,
 }
 
-/// Progress event for frontend
+/// Progress of a preview render
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProgressEvent {
-    pub current_file: usize,
-    pub total_files: usize,
-    pub filename: String,
-    pub status: String,
-    pub output_path: Option<String>,
+pub struct PreviewProgressEvent {
+    pub path: String,
+    pub progress: f64,
 }
 
+/// Files larger than this are counted in the background
+const BACKGROUND_COUNT_THRESHOLD_BYTES: u64 = 256 * 1024 * 1024;
+
 /// Open file dialog to select videos
 #[tauri::command]
 pub async fn select_videos(window: Window) -> Result<SelectionResult, String> {
     let result = window
         .dialog()
         .file()
-        .add_filter("Video Files", &SUPPORTED_FORMATS.to_vec())
+        .add_filter("Video Files", SUPPORTED_FORMATS)
         .set_title("Select Videos for Timelapse")
         .blocking_pick_files();
 
@@ -70,12 +86,230 @@ pub async fn select_videos(window: Window) -> Result<SelectionResult, String> {
 }
 
 /// Get information about selected videos
+///
+/// Files are probed concurrently and each result is emitted as a `video-info`
+/// event when ready; the full list is returned in input order. Poster
+/// thumbnails are extracted afterwards in the background, each emitted as a
+/// `video-poster` event.
 #[tauri::command]
-pub async fn get_video_info(paths: Vec<String>) -> Result<Vec<VideoInfo>, String> {
-    let infos: Vec<VideoInfo> = paths.iter().map(|p| get_info(p)).collect();
+pub async fn get_video_info(window: Window, paths: Vec<String>) -> Result<Vec<VideoInfo>, String> {
+    let total = paths.len();
+    let emit = move |window: &Window, event: &str, index: usize, info: &VideoInfo| {
+        let _ = window.emit(
+            event,
+            VideoInfoEvent {
+                index,
+                total,
+                info: info.clone(),
+            },
+        );
+    };
+    let infos = get_info_many(paths, |index, info| {
+        emit(&window, "video-info", index, info)
+    })
+    .await;
+
+    let mut with_posters = infos.clone();
+    tokio::spawn(async move {
+        attach_posters(&mut with_posters, |index, info| {
+            emit(&window, "video-poster", index, info)
+        })
+        .await;
+    });
     Ok(infos)
 }
 
+/// Extract the frame at a timestamp, scaled down to `max_width`, and return
+/// the path of the cached image
+#[tauri::command]
+pub async fn extract_frame(
+    path: String,
+    timestamp: f64,
+    max_width: u32,
+    format: Option<ImageFormat>,
+) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || {
+        extract(&path, timestamp, max_width, format.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| format!("Frame extraction failed: {}", e))?
+}
+
+/// Count the frames of a video exactly
+///
+/// Small files are counted right away. For large files the count runs in the
+/// background, `None` is returned and a `frame-count` event is emitted when
+/// it finishes. Counts are cached, so repeated calls return immediately.
+#[tauri::command]
+pub async fn count_video_frames(
+    window: Window,
+    path: String,
+    mode: FrameCountMode,
+) -> Result<Option<u64>, String> {
+    if let Some(count) = cached_frame_count(&path, mode) {
+        return Ok(Some(count));
+    }
+
+    let size = std::fs::metadata(&path)
+        .map_err(|e| format!("Cannot read file '{}': {}", path, e))?
+        .len();
+
+    if size < BACKGROUND_COUNT_THRESHOLD_BYTES {
+        let count = tokio::task::spawn_blocking(move || count_frames(&path, mode))
+            .await
+            .map_err(|e| format!("Frame count task failed: {}", e))??;
+        return Ok(Some(count));
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let result = count_frames(&path, mode);
+        let _ = window.emit(
+            "frame-count",
+            FrameCountEvent {
+                path,
+                total_frames: result.as_ref().ok().copied(),
+                error: result.err(),
+            },
+        );
+    });
+
+    Ok(None)
+}
+
+/// Recursively import videos from one or more folders
+#[tauri::command]
+pub async fn import_video_folders(
+    directories: Vec<String>,
+    filter: Option<ImportFilter>,
+) -> Result<FolderImportResult, String> {
+    import_folders(&directories, &filter.unwrap_or_default()).await
+}
+
+/// Read a JSON or CSV manifest into a job list
+///
+/// `defaults` fills in settings the manifest leaves out. Invalid rows are
+/// returned with their line numbers instead of failing the import.
+#[tauri::command]
+pub async fn import_batch_manifest(
+    path: String,
+    defaults: Option<ConversionRequest>,
+) -> Result<ManifestImport, String> {
+    import_manifest(std::path::Path::new(&path), &defaults.unwrap_or_default()).await
+}
+
+/// App config directory holding presets and settings
+fn config_dir(app: &AppHandle) -> Result<std::path::PathBuf, String> {
+    app.path()
+        .app_config_dir()
+        .map_err(|e| format!("Cannot locate config directory: {}", e))
+}
+
+/// Preset store in the app config directory
+fn preset_store(app: &AppHandle) -> Result<PresetStore, String> {
+    Ok(PresetStore::new(config_dir(app)?))
+}
+
+/// Settings store in the app config directory
+fn settings_store(app: &AppHandle) -> Result<SettingsStore, String> {
+    Ok(SettingsStore::new(config_dir(app)?))
+}
+
+/// Load saved settings and make them take effect; called once at startup
+pub fn apply_saved_settings(app: &AppHandle) {
+    match settings_store(app).and_then(|store| store.load()) {
+        Ok(settings) => settings.apply(),
+        Err(e) => eprintln!("Using default settings: {}", e),
+    }
+}
+
+/// Get the persisted application settings
+#[tauri::command]
+pub async fn get_settings(app: AppHandle) -> Result<Settings, String> {
+    settings_store(&app)?.load()
+}
+
+/// Save application settings and apply them right away
+#[tauri::command]
+pub async fn update_settings(app: AppHandle, settings: Settings) -> Result<Settings, String> {
+    let settings = settings_store(&app)?.update(settings)?;
+    settings.apply();
+    Ok(settings)
+}
+
+/// List built-in and saved presets
+#[tauri::command]
+pub async fn list_presets(app: AppHandle) -> Result<Vec<Preset>, String> {
+    preset_store(&app)?.list()
+}
+
+/// Save a new preset
+#[tauri::command]
+pub async fn create_preset(app: AppHandle, preset: Preset) -> Result<Preset, String> {
+    preset_store(&app)?.create(preset)
+}
+
+/// Replace a saved preset, possibly under a new name
+#[tauri::command]
+pub async fn update_preset(app: AppHandle, name: String, preset: Preset) -> Result<Preset, String> {
+    preset_store(&app)?.update(&name, preset)
+}
+
+/// Delete a saved preset
+#[tauri::command]
+pub async fn delete_preset(app: AppHandle, name: String) -> Result<(), String> {
+    preset_store(&app)?.delete(&name)
+}
+
+/// Import presets from a JSON file
+#[tauri::command]
+pub async fn import_presets(app: AppHandle, path: String) -> Result<PresetImportResult, String> {
+    preset_store(&app)?.import(std::path::Path::new(&path))
+}
+
+/// Export presets to a JSON file
+#[tauri::command]
+pub async fn export_presets(
+    app: AppHandle,
+    names: Vec<String>,
+    path: String,
+) -> Result<usize, String> {
+    preset_store(&app)?.export(&names, std::path::Path::new(&path))
+}
+
+/// Save a project file
+#[tauri::command]
+pub async fn save_project(path: String, project: Project) -> Result<(), String> {
+    write_project(std::path::Path::new(&path), &project)
+}
+
+/// Load a project file and report sources that can't be found
+#[tauri::command]
+pub async fn load_project(path: String) -> Result<LoadedProject, String> {
+    read_project(std::path::Path::new(&path))
+}
+
+/// Point a missing project source at a new file
+#[tauri::command]
+pub async fn relink_project_source(
+    mut project: Project,
+    old_path: String,
+    new_path: String,
+) -> Result<Project, String> {
+    project.relink(&old_path, &new_path)?;
+    Ok(project)
+}
+
+/// Search a folder for files that look like the missing sources of a project
+#[tauri::command]
+pub async fn find_project_relinks(
+    project: Project,
+    directory: String,
+) -> Result<Vec<RelinkCandidate>, String> {
+    tokio::task::spawn_blocking(move || project.find_relink_candidates(&directory))
+        .await
+        .map_err(|e| format!("Relink search failed: {}", e))
+}
+
 /// Open file explorer at the specified path
 #[tauri::command]
 pub async fn open_file_explorer(path: String) -> Result<(), String> {
@@ -113,197 +347,145 @@ pub async fn open_file_explorer(path: String) -> Result<(), String> {
     Ok(())
 }
 
-/// Convert videos to timelapse
+/// Estimate output duration, frames, size and encode time for a request,
+/// based on the throughput of earlier conversions on this machine
 #[tauri::command]
-pub async fn convert_videos(
+pub async fn estimate_conversion(
+    app: AppHandle,
+    request: ConversionRequest,
+) -> Result<BatchEstimate, String> {
+    let pixel_rate = ThroughputStore::new(config_dir(&app)?)
+        .pixel_rate()
+        .unwrap_or(None);
+    Ok(estimate_batch(&request, pixel_rate).await)
+}
+
+/// Check a batch before converting it and list everything that would stop it
+///
+/// Uses the same default output directory as `convert_videos`.
+#[tauri::command]
+pub async fn preflight(
+    app: AppHandle,
+    mut jobs: Vec<ConversionRequest>,
+) -> Result<PreflightReport, String> {
+    let default_output_dir = settings_store(&app)?
+        .load()
+        .ok()
+        .and_then(|s| s.default_output_dir);
+    for job in jobs.iter_mut().filter(|job| job.output_dir.is_none()) {
+        job.output_dir = default_output_dir.clone();
+    }
+    Ok(check_batch(&jobs).await)
+}
+
+/// Render a fast, low-resolution preview of one file with the settings of
+/// `request`, emitting `preview-progress` events
+#[tauri::command]
+pub async fn render_preview(
     window: Window,
+    path: String,
     request: ConversionRequest,
-) -> Result<ConversionResult, String> {
-    let total_files = request.files.len();
-    let mut converted_count = 0;
-    let mut failed_count = 0;
-    let mut output_files = Vec::new();
-
-    for (index, input_path) in request.files.iter().enumerate() {
-        let filename = std::path::Path::new(input_path)
-            .file_name()
-            .map(|f| f.to_string_lossy().to_string())
-            .unwrap_or_else(|| "Unknown".to_string());
-
-        // Emit starting progress
+    options: Option<PreviewOptions>,
+) -> Result<Preview, String> {
+    render(&path, &request, &options.unwrap_or_default(), |progress| {
         let _ = window.emit(
-            "conversion-progress",
-            ProgressEvent {
-                current_file: index + 1,
-                total_files,
-                filename: filename.clone(),
-                status: "Starting...".to_string(),
-                output_path: None,
+            "preview-progress",
+            PreviewProgressEvent {
+                path: path.clone(),
+                progress,
             },
         );
+    })
+    .await
+}
 
-        let output_path = match get_output_path(input_path) {
-            Ok(path) => path,
-            Err(e) => {
-                failed_count += 1;
-                let _ = window.emit(
-                    "conversion-progress",
-                    ProgressEvent {
-                        current_file: index + 1,
-                        total_files,
-                        filename: filename.clone(),
-                        status: format!("Failed: {}", e),
-                        output_path: None,
-                    },
-                );
-                continue;
-            }
-        };
-
-        // Run FFmpeg conversion
-        let result = run_ffmpeg_conversion(
-            &window,
-            input_path,
-            &output_path,
-            request.speed_multiplier,
-            index + 1,
-            total_files,
-            &filename,
-        )
-        .await;
-
-        match result {
-            Ok(_) => {
-                converted_count += 1;
-                output_files.push(output_path.clone());
-
-                // Emit completion progress
-                let _ = window.emit(
-                    "conversion-progress",
-                    ProgressEvent {
-                        current_file: index + 1,
-                        total_files,
-                        filename: filename.clone(),
-                        status: "Completed".to_string(),
-                        output_path: Some(output_path),
-                    },
-                );
-            }
-            Err(e) => {
-                failed_count += 1;
-                let _ = window.emit(
-                    "conversion-progress",
-                    ProgressEvent {
-                        current_file: index + 1,
-                        total_files,
-                        filename: filename.clone(),
-                        status: format!("Failed: {}", e),
-                        output_path: None,
-                    },
-                );
-            }
-        }
+/// Output directory for sheets: the one given, or the default from the
+/// settings
+fn sheet_output_dir(app: &AppHandle, output_dir: Option<String>) -> Result<Option<String>, String> {
+    match output_dir {
+        Some(dir) => Ok(Some(dir)),
+        None => Ok(settings_store(app)?
+            .load()
+            .ok()
+            .and_then(|s| s.default_output_dir)),
     }
+}
 
-    if failed_count == 0 {
-        Ok(ConversionResult {
-            success: true,
-            message: format!(
-                "Successfully converted {} video{}!",
-                converted_count,
-                if converted_count == 1 { "" } else { "s" }
-            ),
-            converted_count,
-            failed_count,
-            output_files,
-        })
-    } else if converted_count > 0 {
-        Ok(ConversionResult {
-            success: true,
-            message: format!(
-                "Converted {} video{}, {} failed",
-                converted_count,
-                if converted_count == 1 { "" } else { "s" },
-                failed_count
-            ),
-            converted_count,
-            failed_count,
-            output_files,
-        })
-    } else {
-        Err("All conversions failed".to_string())
-    }
+/// Write a grid of evenly spaced, timestamped frames of a video
+#[tauri::command]
+pub async fn create_contact_sheet(
+    app: AppHandle,
+    path: String,
+    output_dir: Option<String>,
+    options: Option<ContactSheetOptions>,
+) -> Result<ContactSheet, String> {
+    let output_dir = sheet_output_dir(&app, output_dir)?;
+    tokio::task::spawn_blocking(move || {
+        let info = get_info_cached(&path);
+        contact_sheet(&info, output_dir.as_deref(), &options.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| format!("Contact sheet failed: {}", e))?
 }
 
-/// Run FFmpeg to convert a single video
-async fn run_ffmpeg_conversion(
-    _window: &Window,
-    input_path: &str,
-    output_path: &str,
-    speed_multiplier: u32,
-    _current_file: usize,
-    _total_files: usize,
-    _filename: &str,
-) -> Result<(), String> {
-    // Validate speed multiplier (must be between 2 and 1000 to match UI options)
-    if speed_multiplier < 2 {
-        return Err("Speed multiplier must be at least 2".to_string());
-    }
-    if speed_multiplier > 1000 {
-        return Err("Speed multiplier cannot exceed 1000".to_string());
-    }
+/// Write a thumbnail sprite sheet and its WebVTT track for web players
+#[tauri::command]
+pub async fn create_sprite_sheet(
+    app: AppHandle,
+    path: String,
+    output_dir: Option<String>,
+    options: Option<SpriteSheetOptions>,
+) -> Result<SpriteSheet, String> {
+    let output_dir = sheet_output_dir(&app, output_dir)?;
+    tokio::task::spawn_blocking(move || {
+        let info = get_info_cached(&path);
+        sprite_sheet(&info, output_dir.as_deref(), &options.unwrap_or_default())
+    })
+    .await
+    .map_err(|e| format!("Sprite sheet failed: {}", e))?
+}
 
-    // Get video info for progress calculation
-    let info = get_info(input_path);
-    if !info.valid {
-        return Err(info.error.unwrap_or_else(|| "Invalid video".to_string()));
+/// Convert videos to timelapse
+///
+/// Uses the default output directory from the settings when the request
+/// doesn't name one, and adds converted files to the recent-files history.
+#[tauri::command]
+pub async fn convert_videos(
+    window: Window,
+    mut request: ConversionRequest,
+) -> Result<ConversionResult, String> {
+    let store = settings_store(window.app_handle())?;
+    if request.output_dir.is_none() {
+        request.output_dir = store.load().ok().and_then(|s| s.default_output_dir);
     }
 
-    // Calculate PTS (presentation timestamp) divisor for speed
-    // To speed up by Nx, we use setpts=PTS/N
-    let pts_divisor = speed_multiplier as f64;
-
-    // Build FFmpeg command with reduced log verbosity
-    // Using setpts filter to change playback speed
-    let args = vec![
-        "-y".to_string(),              // Overwrite output
-        "-loglevel".to_string(),       // Reduce log verbosity
-        "error".to_string(),
-        "-i".to_string(),              // Input file
-        input_path.to_string(),
-        "-filter_complex".to_string(),
-        format!("[0:v]setpts=PTS/{:.2}[v]", pts_divisor),
-        "-map".to_string(),
-        "[v]".to_string(),
-        "-an".to_string(),             // Remove audio (timelapse typically has no audio)
-        "-c:v".to_string(),
-        "libx264".to_string(),
-        "-preset".to_string(),
-        "fast".to_string(),
-        "-crf".to_string(),
-        "23".to_string(),
-        output_path.to_string(),
-    ];
-
-    let status = TokioCommand::new("ffmpeg")
-        .args(&args)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()
-        .map_err(|e| {
-            format!(
-                "Failed to start FFmpeg: {}. Please ensure FFmpeg is installed.",
-                e
-            )
-        })?
-        .wait()
-        .await
-        .map_err(|e| format!("FFmpeg process error: {}", e))?;
+    let mut recent = Vec::new();
+    let result = convert_batch(&request, |event| {
+        if let (Some(output_path), Some(input)) = (
+            &event.output_path,
+            request.files.get(event.current_file - 1),
+        ) {
+            recent.push(RecentFile::now(input.clone(), Some(output_path.clone())));
+        }
+        let _ = window.emit("conversion-progress", event);
+    })
+    .await;
+
+    if !recent.is_empty() {
+        if let Err(e) = store.record_recent(recent) {
+            eprintln!("Failed to update recent files: {}", e);
+        }
+    }
+    // Measured speed makes later estimates match this machine
+    let throughput = config_dir(window.app_handle()).map(ThroughputStore::new);
+    if let Err(e) = throughput.and_then(|store| store.record(&result.files)) {
+        eprintln!("Failed to update throughput history: {}", e);
+    }
 
-    if status.success() {
-        Ok(())
+    if result.success {
+        Ok(result)
     } else {
-        Err("FFmpeg conversion failed".to_string())
+        Err(result.message)
     }
 }
 
@@ -334,106 +516,6 @@ mod tests {
         assert_eq!(result.files.len(), 2);
     }
 
-    #[test]
-    fn test_conversion_request_structure() {
-        let request = ConversionRequest {
-            files: vec!["/test/video.mp4".to_string()],
-            speed_multiplier: 10,
-        };
-        assert_eq!(request.files.len(), 1);
-        assert_eq!(request.speed_multiplier, 10);
-    }
-
-    #[test]
-    fn test_conversion_request_various_speeds() {
-        for speed in [2, 5, 10, 20, 30, 50, 100, 200, 300, 500, 1000] {
-            let request = ConversionRequest {
-                files: vec![],
-                speed_multiplier: speed,
-            };
-            assert_eq!(request.speed_multiplier, speed);
-        }
-    }
-
-    #[test]
-    fn test_conversion_result_success() {
-        let result = ConversionResult {
-            success: true,
-            message: "Successfully converted 2 videos!".to_string(),
-            converted_count: 2,
-            failed_count: 0,
-            output_files: vec![
-                "/output/video1_timelapse.mp4".to_string(),
-                "/output/video2_timelapse.mp4".to_string(),
-            ],
-        };
-        assert!(result.success);
-        assert_eq!(result.converted_count, 2);
-        assert_eq!(result.failed_count, 0);
-        assert_eq!(result.output_files.len(), 2);
-    }
-
-    #[test]
-    fn test_conversion_result_partial_failure() {
-        let result = ConversionResult {
-            success: true,
-            message: "Converted 1 video, 1 failed".to_string(),
-            converted_count: 1,
-            failed_count: 1,
-            output_files: vec!["/output/video1_timelapse.mp4".to_string()],
-        };
-        assert!(result.success);
-        assert_eq!(result.converted_count, 1);
-        assert_eq!(result.failed_count, 1);
-    }
-
-    #[test]
-    fn test_progress_event_structure() {
-        let event = ProgressEvent {
-            current_file: 1,
-            total_files: 3,
-            filename: "video.mp4".to_string(),
-            status: "Converting...".to_string(),
-            output_path: None,
-        };
-        assert_eq!(event.current_file, 1);
-        assert_eq!(event.total_files, 3);
-        assert!(event.output_path.is_none());
-    }
-
-    #[test]
-    fn test_progress_event_completed() {
-        let event = ProgressEvent {
-            current_file: 1,
-            total_files: 1,
-            filename: "video.mp4".to_string(),
-            status: "Completed".to_string(),
-            output_path: Some("/output/video_timelapse.mp4".to_string()),
-        };
-        assert!(event.output_path.is_some());
-    }
-
-    #[test]
-    fn test_progress_event_serialization() {
-        let event = ProgressEvent {
-            current_file: 1,
-            total_files: 2,
-            filename: "test.mp4".to_string(),
-            status: "Converting...".to_string(),
-            output_path: None,
-        };
-
-        // Test that it can be serialized to JSON
-        let json = serde_json::to_string(&event);
-        assert!(json.is_ok());
-
-        // Test that it contains expected fields
-        let json_str = json.unwrap();
-        assert!(json_str.contains("current_file"));
-        assert!(json_str.contains("total_files"));
-        assert!(json_str.contains("filename"));
-    }
-
     #[test]
     fn test_selection_result_serialization() {
         let result = SelectionResult {
@@ -445,20 +527,6 @@ mod tests {
         assert!(json.is_ok());
     }
 
-    #[test]
-    fn test_conversion_result_serialization() {
-        let result = ConversionResult {
-            success: true,
-            message: "Done".to_string(),
-            converted_count: 1,
-            failed_count: 0,
-            output_files: vec!["output.mp4".to_string()],
-        };
-
-        let json = serde_json::to_string(&result);
-        assert!(json.is_ok());
-    }
-
     #[test]
     fn test_selection_result_roundtrip() {
         let original = SelectionResult {
@@ -472,50 +540,23 @@ mod tests {
     }
 
     #[test]
-    fn test_conversion_request_roundtrip() {
-        let original = ConversionRequest {
-            files: vec!["input.mp4".to_string()],
-            speed_multiplier: 100,
+    fn test_frame_count_event_serialization() {
+        let event = FrameCountEvent {
+            path: "/test/video.mp4".to_string(),
+            total_frames: Some(1234),
+            error: None,
         };
-        let json = serde_json::to_string(&original).unwrap();
-        let decoded: ConversionRequest = serde_json::from_str(&json).unwrap();
-        assert_eq!(original.files, decoded.files);
-        assert_eq!(original.speed_multiplier, decoded.speed_multiplier);
-    }
-
-    #[test]
-    fn test_conversion_result_roundtrip() {
-        let original = ConversionResult {
-            success: false,
-            message: "Failed".to_string(),
-            converted_count: 0,
-            failed_count: 1,
-            output_files: vec![],
-        };
-        let json = serde_json::to_string(&original).unwrap();
-        let decoded: ConversionResult = serde_json::from_str(&json).unwrap();
-        assert_eq!(original.success, decoded.success);
-        assert_eq!(original.message, decoded.message);
-        assert_eq!(original.converted_count, decoded.converted_count);
-        assert_eq!(original.failed_count, decoded.failed_count);
-        assert_eq!(original.output_files, decoded.output_files);
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.contains("\"total_frames\":1234"));
     }
 
     #[test]
-    fn test_progress_event_roundtrip() {
-        let original = ProgressEvent {
-            current_file: 5,
-            total_files: 10,
-            filename: "video.mp4".to_string(),
-            status: "Processing".to_string(),
-            output_path: Some("/out/video.mp4".to_string()),
-        };
-        let json = serde_json::to_string(&original).unwrap();
-        let decoded: ProgressEvent = serde_json::from_str(&json).unwrap();
-        assert_eq!(original.current_file, decoded.current_file);
-        assert_eq!(original.total_files, decoded.total_files);
-        assert_eq!(original.filename, decoded.filename);
-        assert_eq!(original.status, decoded.status);
-        assert_eq!(original.output_path, decoded.output_path);
+    fn test_frame_count_mode_serialization() {
+        let mode: FrameCountMode = serde_json::from_str("\"packets\"").unwrap();
+        assert_eq!(mode, FrameCountMode::Packets);
+        assert_eq!(
+            serde_json::to_string(&FrameCountMode::Decode).unwrap(),
+            "\"decode\""
+        );
     }
 }
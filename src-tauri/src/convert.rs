@@ -0,0 +1,2427 @@
+use crate::formats::{
+    animation_filters, animation_output_args, hls_output_args, mp4_movflags, paletteuse_filter,
+    AnimationOptions, OutputFormat, VideoCodec, MAX_SIZE_ATTEMPTS, MIN_ANIMATION_WIDTH,
+    PALETTEGEN_FILTER,
+};
+use crate::geometry::Geometry;
+use crate::renditions::{output_requests, validate_renditions, Rendition};
+use crate::report::{
+    report_dir, stderr_excerpt, write_report, BatchReport, ErrorCategory, FileError, FileReport,
+};
+use crate::sequence::{
+    clear_sequence, frame_paths, frame_pattern, sequence_output_args, write_timestamps,
+};
+use crate::tools;
+use crate::video::{
+    get_info, get_output_path_in, get_rendition_output_path, is_supported_format,
+    normalize_rotation, Provenance, VideoInfo,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command as TokioCommand;
+
+/// Version written into the provenance tags of outputs
+const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Allowed difference between the expected and actual output duration, as a
+/// fraction of the expected duration
+const DURATION_TOLERANCE: f64 = 0.02;
+
+/// Status prefix of files whose output failed verification
+pub const VERIFICATION_FAILED_STATUS: &str = "Verification failed";
+
+/// Conversion request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConversionRequest {
+    pub files: Vec<String>,
+    pub speed_multiplier: u32,
+    /// Clockwise rotation that replaces the one detected from the source
+    #[serde(default)]
+    pub rotation_override: Option<u32>,
+    /// Write all outputs to this directory instead of `outputs/` next to each input
+    #[serde(default)]
+    pub output_dir: Option<String>,
+    /// Skip the source before this position, in seconds
+    #[serde(default)]
+    pub trim_start_secs: Option<f64>,
+    /// Stop reading the source at this position, in seconds
+    #[serde(default)]
+    pub trim_end_secs: Option<f64>,
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    /// Size, frame rate and loop settings for animated formats
+    #[serde(default)]
+    pub animation: AnimationOptions,
+    /// Image sequences only: write the source position of every frame to a
+    /// CSV beside the frames
+    #[serde(default)]
+    pub frame_timestamps: bool,
+    /// Scale down to at most this many lines, keeping the aspect ratio
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    /// Crop, aspect ratio and target size, applied before `max_height`
+    #[serde(default)]
+    pub geometry: Geometry,
+    #[serde(default)]
+    pub video_codec: VideoCodec,
+    /// Outputs made together from one decode of each input; when empty, each
+    /// input makes a single output with the settings above
+    #[serde(default)]
+    pub renditions: Vec<Rendition>,
+}
+
+impl ConversionRequest {
+    /// Check the settings without touching any files
+    pub fn validate(&self) -> Result<(), String> {
+        // Speed multiplier must be between 2 and 1000 to match UI options
+        if self.speed_multiplier < 2 {
+            return Err("Speed multiplier must be at least 2".to_string());
+        }
+        if self.speed_multiplier > 1000 {
+            return Err("Speed multiplier cannot exceed 1000".to_string());
+        }
+        if let Some(rotation) = self.rotation_override {
+            normalize_rotation(rotation)?;
+        }
+        if self.trim_start_secs.is_some_and(|start| start < 0.0) {
+            return Err("Trim start cannot be negative".to_string());
+        }
+        if let Some(end) = self.trim_end_secs {
+            if end <= self.trim_start_secs.unwrap_or(0.0) {
+                return Err("Trim end must be after trim start".to_string());
+            }
+        }
+        // Animated renditions without settings of their own use these too
+        let inherited = self
+            .renditions
+            .iter()
+            .any(|r| r.output_format.is_animation() && r.animation.is_none());
+        if self.output_format.is_animation() || inherited {
+            self.animation.validate()?;
+        }
+        if self.max_height.is_some_and(|h| h < 2) {
+            return Err("Output must be at least 2 lines high".to_string());
+        }
+        self.geometry.validate()?;
+        validate_renditions(&self.renditions)?;
+        Ok(())
+    }
+
+    /// Length of the part of a source that gets converted
+    pub fn trimmed_duration(&self, source_duration_secs: f64) -> f64 {
+        let start = self.trim_start_secs.unwrap_or(0.0);
+        let end = self
+            .trim_end_secs
+            .unwrap_or(source_duration_secs)
+            .min(source_duration_secs);
+        (end - start).max(0.0)
+    }
+
+    /// FFmpeg input options that apply the trim
+    fn trim_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(start) = self.trim_start_secs.filter(|&s| s > 0.0) {
+            args.push("-ss".to_string());
+            args.push(format!("{:.3}", start));
+        }
+        if let Some(end) = self.trim_end_secs {
+            args.push("-t".to_string());
+            args.push(format!("{:.3}", end - self.trim_start_secs.unwrap_or(0.0)));
+        }
+        args
+    }
+}
+
+/// Conversion result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversionResult {
+    pub success: bool,
+    pub message: String,
+    pub converted_count: usize,
+    pub failed_count: usize,
+    /// Files counted in `failed_count` because their output failed verification
+    #[serde(default)]
+    pub verification_failed_count: usize,
+    pub output_files: Vec<String>,
+    /// What happened to each input, in order
+    #[serde(default)]
+    pub files: Vec<FileReport>,
+    /// JSON batch report written next to the outputs; a CSV with the same
+    /// name sits beside it
+    #[serde(default)]
+    pub report_path: Option<String>,
+}
+
+/// Progress event for frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgressEvent {
+    pub current_file: usize,
+    pub total_files: usize,
+    pub filename: String,
+    pub status: String,
+    pub output_path: Option<String>,
+    /// Fraction of the current file that has been encoded (0.0 to 1.0)
+    #[serde(default)]
+    pub progress: Option<f64>,
+    /// Rendition the event is about, for jobs with several
+    #[serde(default)]
+    pub rendition: Option<String>,
+}
+
+/// Convert every file of a request in order
+///
+/// `on_progress` receives the same events the app shows in its progress view.
+/// The result reports `success: false` only when every conversion failed.
+/// A batch report is written next to the outputs.
+pub async fn convert_batch<F>(request: &ConversionRequest, on_progress: F) -> ConversionResult
+where
+    F: FnMut(ProgressEvent),
+{
+    let files = convert_files(request, 0, request.files.len(), on_progress).await;
+    finish_batch(files)
+}
+
+/// Convert a list of jobs, each with its own settings, as one batch
+///
+/// Progress events are numbered across all jobs so the batch reads as a
+/// single run, and a single report covers every job.
+pub async fn convert_jobs<F>(jobs: &[ConversionRequest], mut on_progress: F) -> ConversionResult
+where
+    F: FnMut(ProgressEvent),
+{
+    let total_files = jobs.iter().map(|job| job.files.len()).sum();
+    let mut files = Vec::new();
+    for job in jobs {
+        let offset = files.len();
+        files.extend(convert_files(job, offset, total_files, &mut on_progress).await);
+    }
+    finish_batch(files)
+}
+
+/// Convert the files of one request, numbering progress events from
+/// `offset + 1` out of `total_files`
+async fn convert_files<F>(
+    request: &ConversionRequest,
+    offset: usize,
+    total_files: usize,
+    mut on_progress: F,
+) -> Vec<FileReport>
+where
+    F: FnMut(ProgressEvent),
+{
+    let mut reports = Vec::new();
+
+    for (index, input_path) in request.files.iter().enumerate() {
+        let filename = Path::new(input_path)
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Unknown".to_string());
+        let event =
+            |status: String, output_path: Option<String>, progress: Option<f64>| ProgressEvent {
+                current_file: offset + index + 1,
+                total_files,
+                filename: filename.clone(),
+                status,
+                output_path,
+                progress,
+                rendition: None,
+            };
+
+        // Emit starting progress
+        on_progress(event("Starting...".to_string(), None, Some(0.0)));
+        if !request.renditions.is_empty() {
+            let renditions = convert_renditions(
+                input_path,
+                request,
+                |rendition, status, output_path, progress| {
+                    on_progress(ProgressEvent {
+                        rendition: Some(rendition.to_string()),
+                        ..event(status, output_path, progress)
+                    })
+                },
+            )
+            .await;
+            reports.extend(renditions);
+            continue;
+        }
+        let started = Instant::now();
+        let mut report = FileReport::new(input_path, request);
+
+        let output_path = if !is_supported_format(input_path) {
+            Err(FileError::new(
+                ErrorCategory::UnsupportedFormat,
+                "Unsupported format",
+            ))
+        } else {
+            get_output_path_in(
+                input_path,
+                request.output_dir.as_deref(),
+                request.output_format,
+            )
+            .map_err(|e| FileError::new(ErrorCategory::OutputUnavailable, e))
+        };
+
+        let result = match output_path {
+            Ok(output_path) => {
+                report.output_path = Some(output_path.clone());
+
+                // Run FFmpeg conversion, reporting each whole percent
+                let mut last_percent = None;
+                let encoded = encode_file(
+                    input_path,
+                    &output_path,
+                    request,
+                    EncodeMode::Full,
+                    |fraction| {
+                        let percent = (fraction * 100.0).floor() as u32;
+                        if last_percent != Some(percent) {
+                            last_percent = Some(percent);
+                            on_progress(event(
+                                format!("Converting... {}%", percent),
+                                None,
+                                Some(fraction),
+                            ));
+                        }
+                    },
+                )
+                .await;
+                report.info = encoded.info;
+                report.settings = encoded.settings;
+                match (encoded.result, &report.info) {
+                    (Ok(()), Some(info)) => {
+                        // A clean exit doesn't prove the file is usable
+                        on_progress(event("Verifying...".to_string(), None, Some(1.0)));
+                        verify_output(&output_path, info, &report.settings)
+                            .await
+                            .map(|_| output_path)
+                            .map_err(|e| FileError::new(ErrorCategory::VerificationFailed, e))
+                    }
+                    (result, _) => result.map(|_| output_path),
+                }
+            }
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(output_path) => {
+                report.finish(started.elapsed());
+                // Emit completion progress
+                on_progress(event("Completed".to_string(), Some(output_path), Some(1.0)));
+            }
+            Err(e) => {
+                on_progress(event(failure_status(&e), None, None));
+                report.error = Some(e);
+                report.finish(started.elapsed());
+            }
+        }
+        reports.push(report);
+    }
+
+    reports
+}
+
+/// Convert one input to every rendition of `request` with a single FFmpeg
+/// run, returning a report per rendition
+///
+/// `emit` receives the rendition name, status, output path and progress of
+/// each event. All renditions advance together since they share the decode,
+/// so every progress update is sent for each of them.
+async fn convert_renditions<F>(
+    input_path: &str,
+    request: &ConversionRequest,
+    mut emit: F,
+) -> Vec<FileReport>
+where
+    F: FnMut(&str, String, Option<String>, Option<f64>),
+{
+    let started = Instant::now();
+    let mut reports: Vec<FileReport> = output_requests(request)
+        .into_iter()
+        .map(|(rendition, settings)| {
+            let mut report = FileReport::new(input_path, &settings);
+            report.rendition = rendition.map(str::to_string);
+            report
+        })
+        .collect();
+
+    let (info, checked) = probe_for_renditions(input_path, request);
+    for report in &mut reports {
+        report.info = info.clone();
+    }
+    match (checked, &info) {
+        (Ok(()), Some(info)) => {
+            encode_renditions(input_path, request, info, &mut reports, &mut emit).await;
+        }
+        // Nothing was encoded, so every rendition fails the same way
+        (result, _) => {
+            let e = result
+                .err()
+                .unwrap_or_else(|| FileError::new(ErrorCategory::ProbeFailed, "Invalid video"));
+            for report in &mut reports {
+                report.error = Some(e.clone());
+            }
+        }
+    }
+
+    for report in &mut reports {
+        report.finish(started.elapsed());
+        let rendition = report.rendition.clone().unwrap_or_default();
+        match &report.error {
+            None => emit(
+                &rendition,
+                "Completed".to_string(),
+                report.output_path.clone(),
+                Some(1.0),
+            ),
+            Some(e) => emit(&rendition, failure_status(e), None, None),
+        }
+    }
+    reports
+}
+
+/// Checks shared by every rendition of an input, ending with its probe,
+/// which is returned whenever it got that far
+fn probe_for_renditions(
+    input_path: &str,
+    request: &ConversionRequest,
+) -> (Option<VideoInfo>, Result<(), FileError>) {
+    if !is_supported_format(input_path) {
+        let error = FileError::new(ErrorCategory::UnsupportedFormat, "Unsupported format");
+        return (None, Err(error));
+    }
+    if let Err(e) = request.validate() {
+        return (None, Err(FileError::new(ErrorCategory::InvalidSettings, e)));
+    }
+    let info = get_info(input_path);
+    if !info.valid {
+        let message = info
+            .error
+            .clone()
+            .unwrap_or_else(|| "Invalid video".to_string());
+        return (
+            Some(info),
+            Err(FileError::new(ErrorCategory::ProbeFailed, message)),
+        );
+    }
+    (Some(info), Ok(()))
+}
+
+/// Encode and verify the renditions of one probed input, recording output
+/// paths and errors in `reports`
+async fn encode_renditions<F>(
+    input_path: &str,
+    request: &ConversionRequest,
+    info: &VideoInfo,
+    reports: &mut [FileReport],
+    emit: &mut F,
+) where
+    F: FnMut(&str, String, Option<String>, Option<f64>),
+{
+    // A rendition whose output can't be created is left out of the run
+    let mut outputs = Vec::new();
+    for (index, report) in reports.iter_mut().enumerate() {
+        let settings = &report.settings;
+        match get_rendition_output_path(
+            input_path,
+            settings.output_dir.as_deref(),
+            settings.output_format,
+            report.rendition.as_deref(),
+        ) {
+            Ok(output_path) => {
+                report.output_path = Some(output_path.clone());
+                outputs.push((index, output_path));
+            }
+            Err(e) => report.error = Some(FileError::new(ErrorCategory::OutputUnavailable, e)),
+        }
+    }
+    if outputs.is_empty() {
+        return;
+    }
+
+    if let Err(e) = check_rotation(request) {
+        for (index, _) in &outputs {
+            reports[*index].error = Some(e.clone());
+        }
+        return;
+    }
+    let settings: Vec<(String, ConversionRequest)> = outputs
+        .iter()
+        .map(|(index, path)| (path.clone(), reports[*index].settings.clone()))
+        .collect();
+    for (output_path, settings) in &settings {
+        prepare_output(output_path, settings);
+    }
+    let args = build_rendition_args(input_path, &settings, request, info);
+    let expected_secs =
+        request.trimmed_duration(info.duration_secs) / request.speed_multiplier as f64;
+
+    let names: Vec<String> = outputs
+        .iter()
+        .map(|(index, _)| reports[*index].rendition.clone().unwrap_or_default())
+        .collect();
+    let mut last_percent = None;
+    let result = run_pass(&args, expected_secs, |fraction| {
+        let percent = (fraction * 100.0).floor() as u32;
+        if last_percent != Some(percent) {
+            last_percent = Some(percent);
+            for name in &names {
+                emit(
+                    name,
+                    format!("Converting... {}%", percent),
+                    None,
+                    Some(fraction),
+                );
+            }
+        }
+    })
+    .await;
+
+    for ((index, output_path), name) in outputs.iter().zip(&names) {
+        let report = &mut reports[*index];
+        if let Err(e) = &result {
+            report.error = Some(e.clone());
+            continue;
+        }
+        // A clean exit doesn't prove the file is usable
+        emit(name, "Verifying...".to_string(), None, Some(1.0));
+        let verified = match finish_output(output_path, &report.settings, info) {
+            Ok(()) => verify_output(output_path, info, &report.settings)
+                .await
+                .map_err(|e| FileError::new(ErrorCategory::VerificationFailed, e)),
+            Err(e) => Err(e),
+        };
+        report.error = verified.err();
+    }
+}
+
+/// Status shown for a file that failed
+fn failure_status(error: &FileError) -> String {
+    if error.category == ErrorCategory::VerificationFailed {
+        format!("{}: {}", VERIFICATION_FAILED_STATUS, error.message)
+    } else {
+        format!("Failed: {}", error.message)
+    }
+}
+
+/// Summarize a batch and write its report
+fn finish_batch(files: Vec<FileReport>) -> ConversionResult {
+    let output_files = files
+        .iter()
+        .filter(|f| f.succeeded())
+        .filter_map(|f| f.output_path.clone())
+        .collect();
+    let converted_count = files.iter().filter(|f| f.succeeded()).count();
+    let verification_failed_count = files
+        .iter()
+        .filter_map(|f| f.error.as_ref())
+        .filter(|e| e.category == ErrorCategory::VerificationFailed)
+        .count();
+    let mut result = summarize(
+        converted_count,
+        files.len() - converted_count,
+        verification_failed_count,
+        output_files,
+    );
+
+    // Nothing to write next to when no output location was ever resolved
+    if let Some(dir) = report_dir(&files) {
+        let report = BatchReport::new(files.clone());
+        match write_report(&dir, &report) {
+            Ok(path) => result.report_path = Some(path.to_string_lossy().to_string()),
+            Err(e) => result.message = format!("{} (report not written: {})", result.message, e),
+        }
+    }
+    result.files = files;
+    result
+}
+
+fn summarize(
+    converted_count: usize,
+    failed_count: usize,
+    verification_failed_count: usize,
+    output_files: Vec<String>,
+) -> ConversionResult {
+    let plural = if converted_count == 1 { "" } else { "s" };
+    let (success, mut message) = if failed_count == 0 {
+        (
+            true,
+            format!(
+                "Successfully converted {} video{}!",
+                converted_count, plural
+            ),
+        )
+    } else if converted_count > 0 {
+        (
+            true,
+            format!(
+                "Converted {} video{}, {} failed",
+                converted_count, plural, failed_count
+            ),
+        )
+    } else {
+        (false, "All conversions failed".to_string())
+    };
+    if verification_failed_count > 0 {
+        message = format!(
+            "{} ({} failed verification)",
+            message, verification_failed_count
+        );
+    }
+
+    ConversionResult {
+        success,
+        message,
+        converted_count,
+        failed_count,
+        verification_failed_count,
+        output_files,
+        files: Vec::new(),
+        report_path: None,
+    }
+}
+
+/// Run FFmpeg to convert a single video
+///
+/// `on_progress` is called with the encoded fraction of the expected output
+/// duration as FFmpeg reports it.
+pub async fn run_ffmpeg_conversion<F>(
+    input_path: &str,
+    output_path: &str,
+    request: &ConversionRequest,
+    on_progress: F,
+) -> Result<(), String>
+where
+    F: FnMut(f64),
+{
+    encode_file(
+        input_path,
+        output_path,
+        request,
+        EncodeMode::Full,
+        on_progress,
+    )
+    .await
+    .result
+    .map_err(|e| e.message)
+}
+
+/// Run the conversion pipeline for a quick preview: same trim, speed and
+/// filters, but scaled down to at most `max_height` lines and encoded with
+/// the fastest preset, without provenance tags
+pub async fn run_ffmpeg_preview<F>(
+    input_path: &str,
+    output_path: &str,
+    request: &ConversionRequest,
+    max_height: u32,
+    on_progress: F,
+) -> Result<(), String>
+where
+    F: FnMut(f64),
+{
+    let mode = EncodeMode::Preview { max_height };
+    encode_file(input_path, output_path, request, mode, on_progress)
+        .await
+        .result
+        .map_err(|e| e.message)
+}
+
+/// What an encode is for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EncodeMode {
+    Full,
+    /// First pass of a GIF, writing the palette the second pass uses
+    Palette,
+    Preview {
+        max_height: u32,
+    },
+}
+
+/// Outcome of encoding one file, with the probe of the source if it got
+/// that far
+struct Encoded {
+    info: Option<VideoInfo>,
+    /// Settings the output was made with; an animation scaled down to fit
+    /// its target size has a smaller `max_width` than requested
+    settings: ConversionRequest,
+    result: Result<(), FileError>,
+}
+
+async fn encode_file<F>(
+    input_path: &str,
+    output_path: &str,
+    request: &ConversionRequest,
+    mode: EncodeMode,
+    mut on_progress: F,
+) -> Encoded
+where
+    F: FnMut(f64),
+{
+    let mut settings = ConversionRequest {
+        files: vec![input_path.to_string()],
+        ..request.clone()
+    };
+    if let Err(e) = request.validate() {
+        return Encoded {
+            info: None,
+            settings,
+            result: Err(FileError::new(ErrorCategory::InvalidSettings, e)),
+        };
+    }
+
+    // Probe the source; its metadata decides filters and output settings
+    let info = get_info(input_path);
+    if !info.valid {
+        let message = info
+            .error
+            .clone()
+            .unwrap_or_else(|| "Invalid video".to_string());
+        return Encoded {
+            info: Some(info),
+            settings,
+            result: Err(FileError::new(ErrorCategory::ProbeFailed, message)),
+        };
+    }
+
+    let mut result = run_ffmpeg(
+        input_path,
+        output_path,
+        &settings,
+        &info,
+        mode,
+        &mut on_progress,
+    )
+    .await;
+    // Animations over their target size are encoded again, narrower
+    let mut attempts = 1;
+    while result.is_ok() && mode == EncodeMode::Full && settings.output_format.is_animation() {
+        let bytes = std::fs::metadata(output_path).map_or(0, |m| m.len());
+        let (width, _) = output_dimensions(&info, &settings);
+        let Some(next_width) = settings.animation.next_width(width, bytes) else {
+            break;
+        };
+        if next_width < MIN_ANIMATION_WIDTH || attempts >= MAX_SIZE_ATTEMPTS {
+            result = Err(FileError::new(
+                ErrorCategory::EncodeFailed,
+                format!(
+                    "Output is {} bytes at {}px wide, above the target of {} bytes",
+                    bytes,
+                    width,
+                    settings.animation.target_bytes.unwrap_or_default()
+                ),
+            ));
+            break;
+        }
+        settings.animation.max_width = Some(next_width);
+        attempts += 1;
+        result = run_ffmpeg(
+            input_path,
+            output_path,
+            &settings,
+            &info,
+            mode,
+            &mut on_progress,
+        )
+        .await;
+    }
+
+    Encoded {
+        info: Some(info),
+        settings,
+        result,
+    }
+}
+
+async fn run_ffmpeg<F>(
+    input_path: &str,
+    output_path: &str,
+    request: &ConversionRequest,
+    info: &VideoInfo,
+    mode: EncodeMode,
+    mut on_progress: F,
+) -> Result<(), FileError>
+where
+    F: FnMut(f64),
+{
+    check_rotation(request)?;
+    let expected_secs =
+        request.trimmed_duration(info.duration_secs) / request.speed_multiplier as f64;
+
+    // GIFs are made in two passes: a palette from every frame, then the frames
+    // mapped onto it
+    let passes = if mode == EncodeMode::Full && request.output_format == OutputFormat::Gif {
+        vec![EncodeMode::Palette, EncodeMode::Full]
+    } else {
+        vec![mode]
+    };
+    if mode == EncodeMode::Full {
+        prepare_output(output_path, request);
+    }
+    let palette = palette_path(output_path);
+    let share = 1.0 / passes.len() as f64;
+    let mut result = Ok(());
+    for (index, pass) in passes.into_iter().enumerate() {
+        let pass_output = if pass == EncodeMode::Palette {
+            palette.as_str()
+        } else {
+            output_path
+        };
+        let args = build_ffmpeg_args(input_path, pass_output, request, info, pass);
+        result = run_pass(&args, expected_secs, |fraction| {
+            on_progress((index as f64 + fraction) * share)
+        })
+        .await;
+        if result.is_err() {
+            break;
+        }
+    }
+    if request.output_format == OutputFormat::Gif {
+        let _ = std::fs::remove_file(&palette);
+    }
+    if result.is_ok() && mode == EncodeMode::Full {
+        finish_output(output_path, request, info)?;
+    }
+    result
+}
+
+/// Reject a rotation override that isn't a quarter turn
+fn check_rotation(request: &ConversionRequest) -> Result<(), FileError> {
+    match request.rotation_override {
+        Some(rotation) => normalize_rotation(rotation)
+            .map(|_| ())
+            .map_err(|e| FileError::new(ErrorCategory::InvalidSettings, e)),
+        None => Ok(()),
+    }
+}
+
+/// Clockwise turn the filter graph adds to a source
+///
+/// FFmpeg already turns rotated sources upright, and drops their rotation
+/// metadata so players don't turn them again, so only an override that
+/// differs from the detected rotation needs filters.
+fn graph_rotation(info: &VideoInfo, request: &ConversionRequest) -> u32 {
+    match request
+        .rotation_override
+        .and_then(|r| normalize_rotation(r).ok())
+    {
+        Some(rotation) => (rotation + 360 - info.rotation) % 360,
+        None => 0,
+    }
+}
+
+/// Remove what an earlier export to the same image sequence or HLS folder
+/// left behind
+fn prepare_output(output_path: &str, request: &ConversionRequest) {
+    if request.output_format.is_sequence() {
+        clear_sequence(Path::new(output_path), request.output_format);
+    } else if request.output_format == OutputFormat::Hls {
+        clear_hls_segments(output_path);
+    }
+}
+
+/// Write the files that accompany a finished output
+fn finish_output(
+    output_path: &str,
+    request: &ConversionRequest,
+    info: &VideoInfo,
+) -> Result<(), FileError> {
+    if request.output_format.is_sequence() && request.frame_timestamps {
+        write_timestamps(
+            Path::new(output_path),
+            request.output_format,
+            output_frame_rate(info),
+            request,
+        )
+        .map_err(|e| FileError::new(ErrorCategory::OutputUnavailable, e))?;
+    }
+    Ok(())
+}
+
+/// Run one FFmpeg invocation, reporting progress towards `expected_secs` of
+/// output
+async fn run_pass<F>(
+    args: &[String],
+    expected_secs: f64,
+    mut on_progress: F,
+) -> Result<(), FileError>
+where
+    F: FnMut(f64),
+{
+    let mut child = TokioCommand::new(tools::ffmpeg())
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            FileError::new(
+                ErrorCategory::FfmpegUnavailable,
+                format!(
+                    "Failed to start FFmpeg: {}. Please ensure FFmpeg is installed.",
+                    e
+                ),
+            )
+        })?;
+
+    // Errors are kept for the batch report; -loglevel error keeps this small
+    let stderr = child.stderr.take().map(|mut stderr| {
+        tokio::spawn(async move {
+            let mut text = String::new();
+            let _ = stderr.read_to_string(&mut text).await;
+            text
+        })
+    });
+
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(fraction) = parse_progress_line(&line, expected_secs) {
+                on_progress(fraction);
+            }
+        }
+    }
+
+    let status = child.wait().await.map_err(|e| {
+        FileError::new(
+            ErrorCategory::EncodeFailed,
+            format!("FFmpeg process error: {}", e),
+        )
+    })?;
+    let stderr = match stderr {
+        Some(task) => task.await.unwrap_or_default(),
+        None => String::new(),
+    };
+
+    if status.success() {
+        Ok(())
+    } else {
+        let mut error = FileError::new(ErrorCategory::EncodeFailed, "FFmpeg conversion failed");
+        error.stderr = stderr_excerpt(&stderr);
+        Err(error)
+    }
+}
+
+/// Trim, filter and encoder arguments for one conversion
+fn build_ffmpeg_args(
+    input_path: &str,
+    output_path: &str,
+    request: &ConversionRequest,
+    info: &VideoInfo,
+    mode: EncodeMode,
+) -> Vec<String> {
+    let mut filter_graph = build_filter_graph(
+        info,
+        request.speed_multiplier,
+        graph_rotation(info, request),
+    );
+    if let Some(filters) = geometry_filters(info, request) {
+        filter_graph = append_filters(&filter_graph, &filters);
+    }
+    match mode {
+        EncodeMode::Preview { max_height } => {
+            filter_graph = add_preview_scale(&filter_graph, max_height);
+        }
+        EncodeMode::Full | EncodeMode::Palette => {
+            if let Some(filters) = output_filters(info, request) {
+                filter_graph = append_filters(&filter_graph, &filters);
+            }
+        }
+    }
+    let paletted = mode == EncodeMode::Full && request.output_format == OutputFormat::Gif;
+    if mode == EncodeMode::Palette {
+        filter_graph = append_filters(&filter_graph, PALETTEGEN_FILTER);
+    } else if paletted {
+        // The palette from the first pass is the second input
+        let chain = filter_graph.strip_suffix("[v]").unwrap_or(&filter_graph);
+        filter_graph = format!(
+            "{}[frames];[frames][1:v]{}[v]",
+            chain,
+            paletteuse_filter(request.animation.dither)
+        );
+    }
+
+    let mut args = build_input_args(input_path, request);
+    if paletted {
+        args.extend(["-i".to_string(), palette_path(output_path)]);
+    }
+    args.extend([
+        "-filter_complex".to_string(),
+        filter_graph,
+        "-map".to_string(),
+        "[v]".to_string(),
+        "-an".to_string(),             // Remove audio (timelapse typically has no audio)
+    ]);
+    match mode {
+        EncodeMode::Full => {
+            args.extend(build_full_output_args(output_path, request, info));
+            return args;
+        }
+        EncodeMode::Palette => args.extend(["-update".to_string(), "1".to_string()]),
+        EncodeMode::Preview { .. } => args.extend(build_preview_output_args()),
+    }
+    args.push(output_path.to_string());
+    args
+}
+
+/// Arguments for encoding every rendition of `outputs` in one run: the
+/// source is decoded and filtered once, then split to one branch per output
+///
+/// GIF branches make their palette in the same graph, which holds the GIF's
+/// frames in memory until the palette is complete.
+fn build_rendition_args(
+    input_path: &str,
+    outputs: &[(String, ConversionRequest)],
+    request: &ConversionRequest,
+    info: &VideoInfo,
+) -> Vec<String> {
+    // Renditions share the geometry, so it is applied once before splitting
+    let mut base = build_filter_graph(
+        info,
+        request.speed_multiplier,
+        graph_rotation(info, request),
+    );
+    if let Some(filters) = geometry_filters(info, request) {
+        base = append_filters(&base, &filters);
+    }
+    let chain = base.strip_suffix("[v]").unwrap_or(&base);
+    let branches: String = (0..outputs.len()).map(|i| format!("[r{}]", i)).collect();
+    let mut filter_graph = format!("{},split={}{}", chain, outputs.len(), branches);
+    for (index, (_, settings)) in outputs.iter().enumerate() {
+        let filters = output_filters(info, settings).unwrap_or_else(|| "null".to_string());
+        if settings.output_format == OutputFormat::Gif {
+            filter_graph.push_str(&format!(
+                ";[r{i}]{},split[f{i}][g{i}];[g{i}]{}[p{i}];[f{i}][p{i}]{}[o{i}]",
+                filters,
+                PALETTEGEN_FILTER,
+                paletteuse_filter(settings.animation.dither),
+                i = index
+            ));
+        } else {
+            filter_graph.push_str(&format!(";[r{i}]{}[o{i}]", filters, i = index));
+        }
+    }
+
+    let mut args = build_input_args(input_path, request);
+    args.extend(["-filter_complex".to_string(), filter_graph]);
+    for (index, (output_path, settings)) in outputs.iter().enumerate() {
+        args.extend([
+            "-map".to_string(),
+            format!("[o{}]", index),
+            "-an".to_string(),
+        ]);
+        args.extend(build_full_output_args(output_path, settings, info));
+    }
+    args
+}
+
+/// Global options, trim and input of a conversion
+fn build_input_args(input_path: &str, request: &ConversionRequest) -> Vec<String> {
+    // Build FFmpeg command with reduced log verbosity
+    let mut args = vec![
+        "-y".to_string(),              // Overwrite output
+        "-loglevel".to_string(),       // Reduce log verbosity
+        "error".to_string(),
+        "-nostats".to_string(),        // Progress is read from -progress instead
+        "-progress".to_string(),
+        "pipe:1".to_string(),
+    ];
+    // Trim with input options so FFmpeg seeks instead of decoding the skipped part
+    args.extend(request.trim_args());
+    args.extend([
+        "-i".to_string(),              // Input file
+        input_path.to_string(),
+    ]);
+    args
+}
+
+/// Crop, reshape and resize filters of the request's geometry
+fn geometry_filters(info: &VideoInfo, request: &ConversionRequest) -> Option<String> {
+    let (width, height) = upright_dimensions(info, request);
+    let filters = request.geometry.plan(width, height).filters;
+    (!filters.is_empty()).then(|| filters.join(","))
+}
+
+/// Filters appended after the geometry for the output format and size
+fn output_filters(info: &VideoInfo, request: &ConversionRequest) -> Option<String> {
+    let (width, height) = output_dimensions(info, request);
+    if request.output_format.is_animation() {
+        let fps = animation_frame_rate(info, request);
+        return Some(animation_filters(fps, width, height));
+    }
+
+    let mut filters = Vec::new();
+    // Video muxers drop frames to keep the output rate; image2 writes every
+    // frame, so the same sampling is done in the graph unless the source was
+    // already resampled to a constant rate
+    if request.output_format.is_sequence() && !info.is_vfr {
+        filters.push(format!("fps={}", output_frame_rate(info)));
+    }
+    if (width, height) != geometry_dimensions(info, request) {
+        filters.push(format!("scale={}:{}", width, height));
+    }
+    (!filters.is_empty()).then(|| filters.join(","))
+}
+
+/// Encoder and muxer arguments of a full encode, ending with the output
+fn build_full_output_args(
+    output_path: &str,
+    request: &ConversionRequest,
+    info: &VideoInfo,
+) -> Vec<String> {
+    let mut args = Vec::new();
+    let format = request.output_format;
+    if format.is_animation() {
+        args.extend(animation_output_args(format, &request.animation));
+        if let Some(max_frames) = request.animation.max_frames {
+            args.extend(["-frames:v".to_string(), max_frames.to_string()]);
+        }
+    } else if format.is_sequence() {
+        args.extend(sequence_output_args(format));
+        args.push(frame_pattern(output_path, format));
+        return args;
+    } else if format == OutputFormat::Hls {
+        args.extend(build_output_args(info, request.video_codec));
+        args.extend(hls_output_args(output_path));
+    } else {
+        args.extend(build_output_args(info, request.video_codec));
+        args.extend(build_metadata_args(info, request));
+    }
+    args.push(output_path.to_string());
+    args
+}
+
+/// Check a finished output against its source: it must probe as a video,
+/// last as long as the trimmed source divided by the speed multiplier, have
+/// the expected resolution and decode without errors
+///
+/// Every failed check is listed in the error. FFmpeg can't read animated
+/// WebP, so those only get their header checked. Image sequences are checked
+/// by frame count instead of duration.
+pub async fn verify_output(
+    output_path: &str,
+    source: &VideoInfo,
+    request: &ConversionRequest,
+) -> Result<(), String> {
+    if request.output_format == OutputFormat::Webp {
+        return verify_webp(output_path);
+    }
+    if request.output_format.is_sequence() {
+        return verify_sequence(output_path, source, request).await;
+    }
+    let output = get_info(output_path);
+    if !output.valid {
+        return Err(format!(
+            "output is not readable ({})",
+            output.error.unwrap_or_else(|| "invalid video".to_string())
+        ));
+    }
+
+    let mut problems = Vec::new();
+
+    let expected_secs =
+        request.trimmed_duration(source.duration_secs) / request.speed_multiplier as f64;
+    if (output.duration_secs - expected_secs).abs() > duration_tolerance(expected_secs, output.fps)
+    {
+        problems.push(format!(
+            "duration is {:.2}s, expected {:.2}s",
+            output.duration_secs, expected_secs
+        ));
+    }
+
+    let (width, height) = output_dimensions(source, request);
+    if (output.width, output.height) != (width, height) {
+        problems.push(format!(
+            "resolution is {}x{}, expected {}x{}",
+            output.width, output.height, width, height
+        ));
+    }
+
+    if let Err(e) = decode_check(output_path).await {
+        problems.push(e);
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+/// Largest acceptable duration difference: a small fraction of the expected
+/// duration, but never less than two frames since outputs are whole frames
+fn duration_tolerance(expected_secs: f64, fps: f64) -> f64 {
+    let frames = if fps > 0.0 { 2.0 / fps } else { 0.1 };
+    (expected_secs * DURATION_TOLERANCE).max(frames)
+}
+
+/// Output size for a source: the source as it is meant to be viewed, with
+/// the request's geometry, scaled down to the maximum height and, for
+/// animations, width
+pub fn output_dimensions(source: &VideoInfo, request: &ConversionRequest) -> (u32, u32) {
+    let (mut width, mut height) = geometry_dimensions(source, request);
+    if let Some(max_height) = request.max_height.filter(|&h| h < height) {
+        // Even sizes, since H.264 and H.265 need them
+        let even_height = max_height / 2 * 2;
+        let scaled = width as f64 * even_height as f64 / height as f64;
+        width = ((scaled / 2.0).round() as u32 * 2).max(2);
+        height = even_height;
+    }
+    if request.output_format.is_animation() {
+        request.animation.dimensions(width, height)
+    } else {
+        (width, height)
+    }
+}
+
+/// Size of the source after the request's geometry
+fn geometry_dimensions(source: &VideoInfo, request: &ConversionRequest) -> (u32, u32) {
+    let (width, height) = upright_dimensions(source, request);
+    let plan = request.geometry.plan(width, height);
+    (plan.width, plan.height)
+}
+
+/// Size of the source as it is meant to be viewed, with the rotation the
+/// request applies
+fn upright_dimensions(source: &VideoInfo, request: &ConversionRequest) -> (u32, u32) {
+    let rotation = request
+        .rotation_override
+        .and_then(|r| normalize_rotation(r).ok())
+        .unwrap_or(source.rotation);
+    let upright = source.clone().with_rotation(rotation);
+    (upright.display_width, upright.display_height)
+}
+
+/// Frame rate of the animation made from `info` with `request`
+pub fn animation_frame_rate(info: &VideoInfo, request: &ConversionRequest) -> f64 {
+    let duration_secs =
+        request.trimmed_duration(info.duration_secs) / request.speed_multiplier.max(1) as f64;
+    request
+        .animation
+        .frame_rate(output_frame_rate(info), duration_secs)
+}
+
+/// Check a folder of frames: roughly one frame per output frame period, the
+/// expected resolution, and a last frame that decodes
+async fn verify_sequence(
+    output_path: &str,
+    source: &VideoInfo,
+    request: &ConversionRequest,
+) -> Result<(), String> {
+    let frames = frame_paths(Path::new(output_path), request.output_format);
+    let (Some(first), Some(last)) = (frames.first(), frames.last()) else {
+        return Err("no frames were written".to_string());
+    };
+
+    let mut problems = Vec::new();
+
+    let expected = expected_frame_count(source, request);
+    let tolerance = (expected as f64 * DURATION_TOLERANCE).max(2.0);
+    if (frames.len() as f64 - expected as f64).abs() > tolerance {
+        problems.push(format!(
+            "{} frames written, expected {}",
+            frames.len(),
+            expected
+        ));
+    }
+
+    // A single image has no duration, so only its size is read
+    let frame = get_info(&first.to_string_lossy());
+    let (width, height) = output_dimensions(source, request);
+    if (frame.width, frame.height) != (width, height) {
+        problems.push(format!(
+            "resolution is {}x{}, expected {}x{}",
+            frame.width, frame.height, width, height
+        ));
+    }
+
+    if let Err(e) = decode_check(&last.to_string_lossy()).await {
+        problems.push(e);
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+/// Frames in the output made from `source` with `request`
+fn expected_frame_count(source: &VideoInfo, request: &ConversionRequest) -> usize {
+    let expected_secs =
+        request.trimmed_duration(source.duration_secs) / request.speed_multiplier.max(1) as f64;
+    (expected_secs * output_frame_rate(source)).round() as usize
+}
+
+/// Check that a file is a complete-looking animated WebP
+fn verify_webp(path: &str) -> Result<(), String> {
+    let data = std::fs::read(path).map_err(|e| format!("output is not readable ({})", e))?;
+    let riff_size = data
+        .get(4..8)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize + 8);
+    if data.get(0..4) != Some(b"RIFF") || data.get(8..12) != Some(b"WEBP") {
+        return Err("output is not a WebP file".to_string());
+    }
+    if riff_size != Some(data.len()) {
+        return Err("output is truncated".to_string());
+    }
+    Ok(())
+}
+
+/// Second input of the final GIF pass, written next to the output
+fn palette_path(output_path: &str) -> String {
+    format!("{}.palette.png", output_path)
+}
+
+/// Delete the segments of an earlier HLS package beside `playlist_path`, so a
+/// shorter package doesn't leave unused segments behind
+fn clear_hls_segments(playlist_path: &str) {
+    let dir = Path::new(playlist_path).parent().unwrap_or(Path::new("."));
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        let is_segment = path.extension().is_some_and(|ext| ext == "ts")
+            && path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("segment_"));
+        if is_segment {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
+
+/// Decode the whole file and fail on any error FFmpeg reports
+async fn decode_check(path: &str) -> Result<(), String> {
+    let output = TokioCommand::new(tools::ffmpeg())
+        .args(["-v", "error", "-i", path, "-f", "null", "-"])
+        .stdout(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| format!("cannot run decode check: {}", e))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() && stderr.trim().is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "decoding reported errors: {}",
+            stderr_excerpt(&stderr).unwrap_or_else(|| "FFmpeg exited with an error".to_string())
+        ))
+    }
+}
+
+/// Turn an `out_time_us=` line of FFmpeg's `-progress` output into a fraction
+/// of the expected output duration
+fn parse_progress_line(line: &str, expected_secs: f64) -> Option<f64> {
+    let micros: f64 = line.strip_prefix("out_time_us=")?.trim().parse().ok()?;
+    if expected_secs <= 0.0 {
+        return None;
+    }
+    Some((micros / 1_000_000.0 / expected_secs).clamp(0.0, 1.0))
+}
+
+/// Build the video filter graph for a timelapse conversion
+///
+/// The source metadata decides which extra filters are needed: interlaced
+/// footage is deinterlaced before retiming, variable-frame-rate footage is
+/// resampled to a constant rate, the picture is turned clockwise by
+/// `rotation` on top of FFmpeg's autorotation, HDR footage is tone-mapped to
+/// SDR and anything that is not 8-bit 4:2:0 is converted so that the H.264
+/// output plays everywhere.
+fn build_filter_graph(info: &VideoInfo, speed_multiplier: u32, rotation: u32) -> String {
+    let mut filters = Vec::new();
+
+    if info.interlaced {
+        filters.push("yadif".to_string());
+    }
+
+    // Calculate PTS (presentation timestamp) divisor for speed
+    // To speed up by Nx, we use setpts=PTS/N
+    filters.push(format!("setpts=PTS/{:.2}", speed_multiplier as f64));
+
+    if info.is_vfr {
+        filters.push(format!("fps={}", constant_frame_rate(info)));
+    }
+
+    match rotation {
+        90 => filters.push("transpose=clock".to_string()),
+        180 => filters.push("hflip,vflip".to_string()),
+        270 => filters.push("transpose=cclock".to_string()),
+        _ => {}
+    }
+
+    if info.is_hdr {
+        filters.push(
+            "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,\
+             tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv"
+                .to_string(),
+        );
+    }
+
+    if info.is_hdr || info.needs_pixel_format_conversion() {
+        filters.push("format=yuv420p".to_string());
+    }
+
+    format!("[0:v]{}[v]", filters.join(","))
+}
+
+/// Frame rate of the output made from `info`
+pub fn output_frame_rate(info: &VideoInfo) -> f64 {
+    if info.is_vfr {
+        constant_frame_rate(info) as f64
+    } else {
+        info.fps
+    }
+}
+
+/// Constant output rate for a variable-frame-rate source: its average rate
+/// rounded to whole frames per second
+fn constant_frame_rate(info: &VideoInfo) -> u32 {
+    let rate = if info.avg_fps > 0.0 {
+        info.avg_fps
+    } else {
+        info.fps
+    };
+    (rate.round() as u32).max(1)
+}
+
+/// Scale the output of a filter graph down to at most `max_height` lines,
+/// keeping the aspect ratio and an even width
+fn add_preview_scale(filter_graph: &str, max_height: u32) -> String {
+    append_filters(
+        filter_graph,
+        &format!("scale=-2:'min({},ih)'", max_height.max(2) / 2 * 2),
+    )
+}
+
+/// Add filters to the end of the chain producing `[v]`
+fn append_filters(filter_graph: &str, filters: &str) -> String {
+    let chain = filter_graph.strip_suffix("[v]").unwrap_or(filter_graph);
+    format!("{},{}[v]", chain, filters)
+}
+
+/// Encoder arguments for previews: speed over quality and size
+fn build_preview_output_args() -> Vec<String> {
+    [
+        "-c:v",
+        "libx264",
+        "-preset",
+        "ultrafast",
+        "-crf",
+        "28",
+        "-pix_fmt",
+        "yuv420p",
+        // Lets the preview start playing before it is fully read
+        "-movflags",
+        "+faststart",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+/// Build the encoder and container arguments for a timelapse conversion
+fn build_output_args(info: &VideoInfo, codec: VideoCodec) -> Vec<String> {
+    let mut args = codec.encoder_args();
+
+    if info.is_hdr {
+        // Tag the tone-mapped output so players don't treat it as HDR
+        args.extend(
+            [
+                "-color_primaries",
+                "bt709",
+                "-color_trc",
+                "bt709",
+                "-colorspace",
+                "bt709",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        );
+    }
+
+    if let Some(creation_time) = &info.creation_time {
+        args.push("-metadata".to_string());
+        args.push(format!("creation_time={}", creation_time));
+    }
+
+    args
+}
+
+/// Provenance of an output made from `info` with `request`
+fn provenance(info: &VideoInfo, request: &ConversionRequest) -> Provenance {
+    Provenance {
+        source: Some(info.filename.clone()),
+        speed_multiplier: Some(request.speed_multiplier),
+        trim_start_secs: request.trim_start_secs,
+        trim_end_secs: request.trim_end_secs,
+        app_version: Some(APP_VERSION.to_string()),
+        source_creation_time: info.creation_time.clone(),
+    }
+}
+
+/// Title, comment and provenance tags describing how the output was made
+fn build_metadata_args(info: &VideoInfo, request: &ConversionRequest) -> Vec<String> {
+    let stem = Path::new(&info.filename)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| info.filename.clone());
+    let trim = match (request.trim_start_secs, request.trim_end_secs) {
+        (None, None) => String::new(),
+        (start, end) => format!(
+            ", {:.1}s to {}",
+            start.unwrap_or(0.0),
+            end.map_or("end".to_string(), |e| format!("{:.1}s", e))
+        ),
+    };
+    let comment = format!(
+        "Timelapse of {} at {}x{}, made with Timelapse Creator {}",
+        info.filename, request.speed_multiplier, trim, APP_VERSION
+    );
+
+    let mut args = Vec::new();
+    // Container layout and custom keys share one -movflags, since only the
+    // last one given counts
+    if let Some(movflags) = mp4_movflags(request.output_format) {
+        args.extend(["-movflags".to_string(), movflags.to_string()]);
+    }
+    args.extend([
+        "-metadata".to_string(),
+        format!("title={} timelapse", stem),
+        "-metadata".to_string(),
+        format!("comment={}", comment),
+    ]);
+    for (key, value) in provenance(info, request).tags() {
+        args.push("-metadata".to_string());
+        args.push(format!("{}={}", key, value));
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{AspectRatio, Fit};
+
+    #[test]
+    fn test_conversion_request_structure() {
+        let request = ConversionRequest {
+            files: vec!["/test/video.mp4".to_string()],
+            speed_multiplier: 10,
+            ..Default::default()
+        };
+        assert_eq!(request.files.len(), 1);
+        assert_eq!(request.speed_multiplier, 10);
+    }
+
+    #[test]
+    fn test_conversion_request_various_speeds() {
+        for speed in [2, 5, 10, 20, 30, 50, 100, 200, 300, 500, 1000] {
+            let request = ConversionRequest {
+                files: vec![],
+                speed_multiplier: speed,
+                ..Default::default()
+            };
+            assert_eq!(request.speed_multiplier, speed);
+        }
+    }
+
+    #[test]
+    fn test_conversion_result_success() {
+        let result = ConversionResult {
+            success: true,
+            message: "Successfully converted 2 videos!".to_string(),
+            converted_count: 2,
+            failed_count: 0,
+            verification_failed_count: 0,
+            output_files: vec![
+                "/output/video1_timelapse.mp4".to_string(),
+                "/output/video2_timelapse.mp4".to_string(),
+            ],
+            files: vec![],
+            report_path: None,
+        };
+        assert!(result.success);
+        assert_eq!(result.converted_count, 2);
+        assert_eq!(result.failed_count, 0);
+        assert_eq!(result.output_files.len(), 2);
+    }
+
+    #[test]
+    fn test_conversion_result_partial_failure() {
+        let result = ConversionResult {
+            success: true,
+            message: "Converted 1 video, 1 failed".to_string(),
+            converted_count: 1,
+            failed_count: 1,
+            verification_failed_count: 0,
+            output_files: vec!["/output/video1_timelapse.mp4".to_string()],
+            files: vec![],
+            report_path: None,
+        };
+        assert!(result.success);
+        assert_eq!(result.converted_count, 1);
+        assert_eq!(result.failed_count, 1);
+    }
+
+    #[test]
+    fn test_progress_event_structure() {
+        let event = ProgressEvent {
+            current_file: 1,
+            total_files: 3,
+            filename: "video.mp4".to_string(),
+            status: "Converting...".to_string(),
+            output_path: None,
+            progress: None,
+            rendition: None,
+        };
+        assert_eq!(event.current_file, 1);
+        assert_eq!(event.total_files, 3);
+        assert!(event.output_path.is_none());
+    }
+
+    #[test]
+    fn test_progress_event_completed() {
+        let event = ProgressEvent {
+            current_file: 1,
+            total_files: 1,
+            filename: "video.mp4".to_string(),
+            status: "Completed".to_string(),
+            output_path: Some("/output/video_timelapse.mp4".to_string()),
+            progress: Some(1.0),
+            rendition: None,
+        };
+        assert!(event.output_path.is_some());
+    }
+
+    #[test]
+    fn test_progress_event_serialization() {
+        let event = ProgressEvent {
+            current_file: 1,
+            total_files: 2,
+            filename: "test.mp4".to_string(),
+            status: "Converting...".to_string(),
+            output_path: None,
+            progress: None,
+            rendition: None,
+        };
+
+        // Test that it can be serialized to JSON
+        let json = serde_json::to_string(&event);
+        assert!(json.is_ok());
+
+        // Test that it contains expected fields
+        let json_str = json.unwrap();
+        assert!(json_str.contains("current_file"));
+        assert!(json_str.contains("total_files"));
+        assert!(json_str.contains("filename"));
+    }
+
+    #[test]
+    fn test_conversion_result_serialization() {
+        let result = ConversionResult {
+            success: true,
+            message: "Done".to_string(),
+            converted_count: 1,
+            failed_count: 0,
+            verification_failed_count: 0,
+            output_files: vec!["output.mp4".to_string()],
+            files: vec![],
+            report_path: None,
+        };
+
+        let json = serde_json::to_string(&result);
+        assert!(json.is_ok());
+    }
+
+    #[test]
+    fn test_conversion_request_roundtrip() {
+        let original = ConversionRequest {
+            files: vec!["input.mp4".to_string()],
+            speed_multiplier: 100,
+            rotation_override: Some(90),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&original).unwrap();
+        let decoded: ConversionRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(original.files, decoded.files);
+        assert_eq!(original.speed_multiplier, decoded.speed_multiplier);
+        assert_eq!(original.rotation_override, decoded.rotation_override);
+    }
+
+    #[test]
+    fn test_conversion_result_roundtrip() {
+        let original = ConversionResult {
+            success: false,
+            message: "Failed".to_string(),
+            converted_count: 0,
+            failed_count: 1,
+            verification_failed_count: 0,
+            output_files: vec![],
+            files: vec![],
+            report_path: None,
+        };
+        let json = serde_json::to_string(&original).unwrap();
+        let decoded: ConversionResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(original.success, decoded.success);
+        assert_eq!(original.message, decoded.message);
+        assert_eq!(original.converted_count, decoded.converted_count);
+        assert_eq!(original.failed_count, decoded.failed_count);
+        assert_eq!(original.output_files, decoded.output_files);
+    }
+
+    #[test]
+    fn test_progress_event_roundtrip() {
+        let original = ProgressEvent {
+            current_file: 5,
+            total_files: 10,
+            filename: "video.mp4".to_string(),
+            status: "Processing".to_string(),
+            output_path: Some("/out/video.mp4".to_string()),
+            progress: Some(0.5),
+            rendition: None,
+        };
+        let json = serde_json::to_string(&original).unwrap();
+        let decoded: ProgressEvent = serde_json::from_str(&json).unwrap();
+        assert_eq!(original.current_file, decoded.current_file);
+        assert_eq!(original.total_files, decoded.total_files);
+        assert_eq!(original.filename, decoded.filename);
+        assert_eq!(original.status, decoded.status);
+        assert_eq!(original.output_path, decoded.output_path);
+        assert_eq!(original.progress, decoded.progress);
+    }
+
+    fn sample_info() -> VideoInfo {
+        VideoInfo {
+            path: "/test/video.mp4".to_string(),
+            filename: "video.mp4".to_string(),
+            duration_secs: 60.0,
+            width: 1920,
+            height: 1080,
+            fps: 30.0,
+            total_frames: 1800,
+            valid: true,
+            pix_fmt: Some("yuv420p".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_build_filter_graph_plain() {
+        let graph = build_filter_graph(&sample_info(), 10, 0);
+        assert_eq!(graph, "[0:v]setpts=PTS/10.00[v]");
+    }
+
+    #[test]
+    fn test_build_filter_graph_interlaced_high_bit_depth() {
+        let info = VideoInfo {
+            interlaced: true,
+            pix_fmt: Some("yuv422p10le".to_string()),
+            ..sample_info()
+        };
+        let graph = build_filter_graph(&info, 100, 0);
+        assert!(graph.starts_with("[0:v]yadif,setpts=PTS/100.00"));
+        assert!(graph.ends_with("format=yuv420p[v]"));
+        assert!(!graph.contains("tonemap"));
+    }
+
+    #[test]
+    fn test_build_filter_graph_hdr_tonemaps() {
+        let info = VideoInfo {
+            is_hdr: true,
+            pix_fmt: Some("yuv420p10le".to_string()),
+            ..sample_info()
+        };
+        let graph = build_filter_graph(&info, 2, 0);
+        assert!(graph.contains("tonemap=tonemap=hable"));
+        assert!(graph.ends_with("format=yuv420p[v]"));
+        assert!(build_output_args(&info, VideoCodec::H264).contains(&"-color_trc".to_string()));
+    }
+
+    #[test]
+    fn test_build_ffmpeg_args_preview() {
+        let request = ConversionRequest {
+            speed_multiplier: 50,
+            trim_start_secs: Some(10.0),
+            ..Default::default()
+        };
+        let full = build_ffmpeg_args(
+            "in.mp4",
+            "out.mp4",
+            &request,
+            &sample_info(),
+            EncodeMode::Full,
+        );
+        assert!(full.contains(&"fast".to_string()));
+        assert!(full.contains(&"title=video timelapse".to_string()));
+
+        let preview = build_ffmpeg_args(
+            "in.mp4",
+            "preview.mp4",
+            &request,
+            &sample_info(),
+            EncodeMode::Preview { max_height: 360 },
+        );
+        assert!(preview.contains(&"ultrafast".to_string()));
+        assert!(preview.contains(&"[0:v]setpts=PTS/50.00,scale=-2:'min(360,ih)'[v]".to_string()));
+        assert!(!preview.iter().any(|a| a.starts_with("title=")));
+        // Trim is applied the same way as for the full encode
+        assert_eq!(preview[6..8], ["-ss".to_string(), "10.000".to_string()]);
+        assert_eq!(preview.last().unwrap(), "preview.mp4");
+    }
+
+    #[test]
+    fn test_build_ffmpeg_args_gif_passes() {
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            output_format: OutputFormat::Gif,
+            animation: AnimationOptions {
+                max_frames: Some(60),
+                loops: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // 6 seconds of output, so 60 frames means 10 fps
+        let frames = "[0:v]setpts=PTS/10.00,fps=10.000,scale=480:270:flags=lanczos";
+
+        let palette = build_ffmpeg_args(
+            "in.mp4",
+            "out.gif.palette.png",
+            &request,
+            &sample_info(),
+            EncodeMode::Palette,
+        );
+        assert!(palette.contains(&format!("{},palettegen=stats_mode=diff[v]", frames)));
+        assert!(palette.ends_with(&["-update".into(), "1".into(), "out.gif.palette.png".into()]));
+
+        let gif = build_ffmpeg_args(
+            "in.mp4",
+            "out.gif",
+            &request,
+            &sample_info(),
+            EncodeMode::Full,
+        );
+        let inputs: Vec<&String> = gif
+            .iter()
+            .zip(gif.iter().skip(1))
+            .filter(|(flag, _)| *flag == "-i")
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(inputs, ["in.mp4", "out.gif.palette.png"]);
+        assert!(gif.contains(&format!(
+            "{}[frames];[frames][1:v]paletteuse=dither=sierra2_4a:diff_mode=rectangle[v]",
+            frames
+        )));
+        assert!(gif.ends_with(&[
+            "-f".into(),
+            "gif".into(),
+            "-loop".into(),
+            "-1".into(),
+            "-frames:v".into(),
+            "60".into(),
+            "out.gif".into()
+        ]));
+        // Neither libx264 nor MP4 tags apply
+        assert!(!gif.contains(&"libx264".to_string()));
+        assert!(!gif.iter().any(|a| a.starts_with("title=")));
+    }
+
+    #[test]
+    fn test_build_ffmpeg_args_single_pass_animation() {
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            output_format: OutputFormat::Webp,
+            ..Default::default()
+        };
+        let webp = build_ffmpeg_args(
+            "in.mp4",
+            "out.webp",
+            &request,
+            &sample_info(),
+            EncodeMode::Full,
+        );
+        assert!(webp.contains(
+            &"[0:v]setpts=PTS/10.00,fps=15.000,scale=480:270:flags=lanczos[v]".to_string()
+        ));
+        assert!(webp.contains(&"libwebp".to_string()));
+        assert_eq!(webp.iter().filter(|a| *a == "-i").count(), 1);
+    }
+
+    #[test]
+    fn test_build_ffmpeg_args_image_sequence() {
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            output_format: OutputFormat::JpegSequence,
+            ..Default::default()
+        };
+        let args = build_ffmpeg_args(
+            "in.mp4",
+            "/out/clip_timelapse_frames",
+            &request,
+            &sample_info(),
+            EncodeMode::Full,
+        );
+        // Sampled at the rate the MP4 would play at, at full size
+        assert!(args.contains(&"[0:v]setpts=PTS/10.00,fps=30[v]".to_string()));
+        assert!(args.ends_with(&[
+            "-f".into(),
+            "image2".into(),
+            "-q:v".into(),
+            "2".into(),
+            "/out/clip_timelapse_frames/frame_%06d.jpg".into()
+        ]));
+        assert!(!args.contains(&"libx264".to_string()));
+        assert!(!args.iter().any(|a| a.starts_with("title=")));
+
+        // 6 seconds of output at 30 fps
+        assert_eq!(expected_frame_count(&sample_info(), &request), 180);
+    }
+
+    #[test]
+    fn test_verify_webp() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.webp");
+        let path_str = path.to_string_lossy().to_string();
+
+        let mut data = b"RIFF\x0c\0\0\0WEBPANIM\0\0\0\0".to_vec();
+        std::fs::write(&path, &data).unwrap();
+        assert!(verify_webp(&path_str).is_ok());
+
+        data.truncate(14);
+        std::fs::write(&path, &data).unwrap();
+        assert_eq!(
+            verify_webp(&path_str),
+            Err("output is truncated".to_string())
+        );
+
+        std::fs::write(&path, b"GIF89a").unwrap();
+        assert!(verify_webp(&path_str).is_err());
+    }
+
+    #[test]
+    fn test_add_preview_scale() {
+        assert_eq!(
+            add_preview_scale("[0:v]setpts=PTS/10.00[v]", 241),
+            "[0:v]setpts=PTS/10.00,scale=-2:'min(240,ih)'[v]"
+        );
+    }
+
+    #[test]
+    fn test_build_metadata_args() {
+        let info = VideoInfo {
+            filename: "site.mov".to_string(),
+            creation_time: Some("2024-05-01T08:00:00Z".to_string()),
+            ..sample_info()
+        };
+        let request = ConversionRequest {
+            speed_multiplier: 100,
+            trim_start_secs: Some(5.0),
+            ..Default::default()
+        };
+        let args = build_metadata_args(&info, &request);
+        assert!(args.contains(&"+use_metadata_tags".to_string()));
+        assert!(args.contains(&"title=site timelapse".to_string()));
+        assert!(args.contains(&format!(
+            "comment=Timelapse of site.mov at 100x, 5.0s to end, made with Timelapse Creator {}",
+            APP_VERSION
+        )));
+        assert!(args.contains(&"timelapse_source=site.mov".to_string()));
+        assert!(args.contains(&"timelapse_speed=100".to_string()));
+        assert!(args.contains(&"timelapse_trim_start=5.000".to_string()));
+        assert!(args.contains(&"timelapse_source_creation_time=2024-05-01T08:00:00Z".to_string()));
+        assert!(!args.iter().any(|a| a.starts_with("timelapse_trim_end")));
+    }
+
+    #[test]
+    fn test_build_ffmpeg_args_streaming_formats() {
+        let request = |output_format| ConversionRequest {
+            speed_multiplier: 10,
+            output_format,
+            ..Default::default()
+        };
+        let faststart = build_ffmpeg_args(
+            "in.mp4",
+            "out.mp4",
+            &request(OutputFormat::Mp4Faststart),
+            &sample_info(),
+            EncodeMode::Full,
+        );
+        // One -movflags carries both the layout and the metadata flag
+        assert_eq!(faststart.iter().filter(|a| *a == "-movflags").count(), 1);
+        assert!(faststart.contains(&"+faststart+use_metadata_tags".to_string()));
+        assert!(faststart.iter().any(|a| a.starts_with("title=")));
+
+        let hls = build_ffmpeg_args(
+            "in.mp4",
+            "/out/clip_timelapse_hls/index.m3u8",
+            &request(OutputFormat::Hls),
+            &sample_info(),
+            EncodeMode::Full,
+        );
+        assert!(hls.contains(&"libx264".to_string()));
+        assert!(hls.contains(&"hls".to_string()));
+        assert!(!hls.contains(&"-movflags".to_string()));
+        assert_eq!(hls.last().unwrap(), "/out/clip_timelapse_hls/index.m3u8");
+    }
+
+    #[test]
+    fn test_build_ffmpeg_args_max_height() {
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            max_height: Some(720),
+            video_codec: VideoCodec::H265,
+            ..Default::default()
+        };
+        let args = build_ffmpeg_args(
+            "in.mp4",
+            "out.mp4",
+            &request,
+            &sample_info(),
+            EncodeMode::Full,
+        );
+        assert!(args.contains(&"[0:v]setpts=PTS/10.00,scale=1280:720[v]".to_string()));
+        assert!(args.contains(&"libx265".to_string()));
+    }
+
+    #[test]
+    fn test_build_ffmpeg_args_geometry() {
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            geometry: Geometry {
+                aspect: Some(AspectRatio::Vertical),
+                width: Some(1080),
+                height: Some(1920),
+                ..Default::default()
+            },
+            max_height: Some(1280),
+            ..Default::default()
+        };
+        let args = build_ffmpeg_args(
+            "in.mp4",
+            "out.mp4",
+            &request,
+            &sample_info(),
+            EncodeMode::Full,
+        );
+        // Crop to the shape, scale to fit the target, then to the maximum height
+        assert!(args.contains(
+            &"[0:v]setpts=PTS/10.00,crop=606:1080,scale=1080:1920,scale=720:1280[v]".to_string()
+        ));
+
+        // Previews show the same framing
+        let args = build_ffmpeg_args(
+            "in.mp4",
+            "preview.mp4",
+            &request,
+            &sample_info(),
+            EncodeMode::Preview { max_height: 360 },
+        );
+        assert!(args
+            .iter()
+            .any(|a| a.contains("crop=606:1080,scale=1080:1920,scale=-2")));
+    }
+
+    #[test]
+    fn test_build_rendition_args_share_geometry() {
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            geometry: Geometry {
+                aspect: Some(AspectRatio::Vertical),
+                fit: Fit::Blur,
+                width: Some(1080),
+                height: Some(1920),
+                ..Default::default()
+            },
+            renditions: vec![
+                "master:mp4".parse().unwrap(),
+                "web:mp4_faststart:720".parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        let outputs: Vec<(String, ConversionRequest)> = output_requests(&request)
+            .into_iter()
+            .zip(["master.mp4", "web.mp4"])
+            .map(|((_, settings), path)| (path.to_string(), settings))
+            .collect();
+        let args = build_rendition_args("in.mp4", &outputs, &request, &sample_info());
+        let graph = &args[args.iter().position(|a| a == "-filter_complex").unwrap() + 1];
+
+        // The blurred fill is drawn once, before the outputs split
+        assert_eq!(graph.matches("overlay=").count(), 1);
+        assert!(graph.contains("overlay=0:656,setsar=1,split=2[r0][r1]"));
+        assert!(graph.ends_with(";[r0]null[o0];[r1]scale=406:720[o1]"));
+    }
+
+    #[test]
+    fn test_build_rendition_args() {
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            trim_start_secs: Some(5.0),
+            renditions: vec![
+                "master:mp4".parse().unwrap(),
+                "web:mp4_faststart:720".parse().unwrap(),
+                "teaser:gif".parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        let outputs: Vec<(String, ConversionRequest)> = output_requests(&request)
+            .into_iter()
+            .zip(["master.mp4", "web.mp4", "teaser.gif"])
+            .map(|((_, settings), path)| (path.to_string(), settings))
+            .collect();
+        let args = build_rendition_args("in.mp4", &outputs, &request, &sample_info());
+
+        // One input, decoded once and split three ways
+        assert_eq!(args.iter().filter(|a| *a == "-i").count(), 1);
+        assert_eq!(args.iter().filter(|a| *a == "-ss").count(), 1);
+        let graph = &args[args.iter().position(|a| a == "-filter_complex").unwrap() + 1];
+        assert_eq!(
+            graph,
+            "[0:v]setpts=PTS/10.00,split=3[r0][r1][r2];\
+             [r0]null[o0];\
+             [r1]scale=1280:720[o1];\
+             [r2]fps=15.000,scale=480:270:flags=lanczos,split[f2][g2];\
+             [g2]palettegen=stats_mode=diff[p2];\
+             [f2][p2]paletteuse=dither=sierra2_4a:diff_mode=rectangle[o2]"
+        );
+
+        // Each output maps its own branch, with its own encoder and container
+        let maps: Vec<&String> = args
+            .iter()
+            .zip(args.iter().skip(1))
+            .filter(|(flag, _)| *flag == "-map")
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(maps, ["[o0]", "[o1]", "[o2]"]);
+        let outputs: Vec<usize> = ["master.mp4", "web.mp4", "teaser.gif"]
+            .iter()
+            .map(|path| args.iter().position(|a| a == path).unwrap())
+            .collect();
+        assert!(outputs.windows(2).all(|w| w[0] < w[1]));
+        let web = &args[outputs[0]..outputs[1]];
+        assert!(web.contains(&"+faststart+use_metadata_tags".to_string()));
+        let teaser = &args[outputs[1]..outputs[2]];
+        assert!(teaser.contains(&"gif".to_string()));
+        assert!(!teaser.contains(&"libx264".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_convert_renditions_reports_each_rendition() {
+        let request = ConversionRequest {
+            files: vec!["/tmp/notes.txt".to_string()],
+            speed_multiplier: 10,
+            renditions: vec!["master:mp4".parse().unwrap(), "teaser:gif".parse().unwrap()],
+            ..Default::default()
+        };
+        let mut events = Vec::new();
+        let result = convert_batch(&request, |event| events.push(event)).await;
+
+        assert_eq!(result.files.len(), 2);
+        assert_eq!(result.failed_count, 2);
+        let renditions: Vec<Option<&str>> = result
+            .files
+            .iter()
+            .map(|f| f.rendition.as_deref())
+            .collect();
+        assert_eq!(renditions, [Some("master"), Some("teaser")]);
+        assert_eq!(result.files[1].settings.output_format, OutputFormat::Gif);
+        // Starting once for the input, then a failure for each rendition
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2].rendition.as_deref(), Some("teaser"));
+        assert!(events[2].status.starts_with("Failed: Unsupported format"));
+    }
+
+    #[test]
+    fn test_clear_hls_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "index.m3u8",
+            "segment_00000.ts",
+            "segment_00001.ts",
+            "notes.ts",
+        ] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        clear_hls_segments(&dir.path().join("index.m3u8").to_string_lossy());
+        assert!(!dir.path().join("segment_00000.ts").exists());
+        assert!(!dir.path().join("segment_00001.ts").exists());
+        assert!(dir.path().join("index.m3u8").exists());
+        assert!(dir.path().join("notes.ts").exists());
+    }
+
+    #[test]
+    fn test_build_output_args_keeps_creation_time() {
+        let info = VideoInfo {
+            creation_time: Some("2024-05-01T12:00:00Z".to_string()),
+            ..sample_info()
+        };
+        let args = build_output_args(&info, VideoCodec::H264);
+        assert!(args.contains(&"creation_time=2024-05-01T12:00:00Z".to_string()));
+        assert!(
+            !build_output_args(&sample_info(), VideoCodec::H264).contains(&"-metadata".to_string())
+        );
+    }
+
+    #[test]
+    fn test_build_filter_graph_rotation() {
+        assert_eq!(
+            build_filter_graph(&sample_info(), 10, 90),
+            "[0:v]setpts=PTS/10.00,transpose=clock[v]"
+        );
+        assert!(build_filter_graph(&sample_info(), 10, 270).contains("transpose=cclock"));
+        assert!(build_filter_graph(&sample_info(), 10, 180).contains("hflip,vflip"));
+    }
+
+    #[test]
+    fn test_build_ffmpeg_args_rotated_source() {
+        // FFmpeg's autorotation turns the source upright and drops its
+        // rotation metadata, so the graph doesn't turn it a second time
+        let info = sample_info().with_rotation(90);
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            ..Default::default()
+        };
+        let args = build_ffmpeg_args("in.mp4", "out.mp4", &request, &info, EncodeMode::Full);
+        assert!(!args.contains(&"-noautorotate".to_string()));
+        assert!(!args.iter().any(|a| a.contains("rotate=")));
+        assert!(args.contains(&"[0:v]setpts=PTS/10.00[v]".to_string()));
+
+        // An override only adds the difference to the detected rotation
+        let upright = ConversionRequest {
+            rotation_override: Some(0),
+            ..request.clone()
+        };
+        let args = build_ffmpeg_args("in.mp4", "out.mp4", &upright, &info, EncodeMode::Full);
+        assert!(args.contains(&"[0:v]setpts=PTS/10.00,transpose=cclock[v]".to_string()));
+        let same = ConversionRequest {
+            rotation_override: Some(90),
+            ..request
+        };
+        let args = build_ffmpeg_args("in.mp4", "out.mp4", &same, &info, EncodeMode::Full);
+        assert!(args.contains(&"[0:v]setpts=PTS/10.00[v]".to_string()));
+    }
+
+    #[test]
+    fn test_conversion_request_without_rotation_override() {
+        let json = r#"{"files":["a.mp4"],"speed_multiplier":10}"#;
+        let request: ConversionRequest = serde_json::from_str(json).unwrap();
+        assert!(request.rotation_override.is_none());
+    }
+
+    #[test]
+    fn test_build_filter_graph_vfr_normalizes_rate() {
+        let info = VideoInfo {
+            fps: 90000.0,
+            avg_fps: 29.6,
+            is_vfr: true,
+            ..sample_info()
+        };
+        assert_eq!(
+            build_filter_graph(&info, 10, 0),
+            "[0:v]setpts=PTS/10.00,fps=30[v]"
+        );
+        assert!(!build_filter_graph(&sample_info(), 10, 0).contains("fps="));
+    }
+
+    #[test]
+    fn test_conversion_request_output_dir_default() {
+        let json = r#"{"files":["a.mp4"],"speed_multiplier":10}"#;
+        let request: ConversionRequest = serde_json::from_str(json).unwrap();
+        assert!(request.output_dir.is_none());
+    }
+
+    #[test]
+    fn test_parse_progress_line() {
+        assert_eq!(parse_progress_line("out_time_us=5000000", 10.0), Some(0.5));
+        assert_eq!(parse_progress_line("out_time_us=20000000", 10.0), Some(1.0));
+        assert_eq!(parse_progress_line("out_time_us=N/A", 10.0), None);
+        assert_eq!(parse_progress_line("frame=42", 10.0), None);
+        assert_eq!(parse_progress_line("out_time_us=100", 0.0), None);
+    }
+
+    #[test]
+    fn test_summarize_counts() {
+        let result = summarize(2, 0, 0, vec!["a".to_string(), "b".to_string()]);
+        assert!(result.success);
+        assert_eq!(result.message, "Successfully converted 2 videos!");
+
+        let result = summarize(1, 1, 0, vec!["a".to_string()]);
+        assert!(result.success);
+        assert_eq!(result.message, "Converted 1 video, 1 failed");
+
+        let result = summarize(0, 2, 0, vec![]);
+        assert!(!result.success);
+        assert_eq!(result.message, "All conversions failed");
+
+        let result = summarize(1, 2, 1, vec!["a".to_string()]);
+        assert_eq!(result.verification_failed_count, 1);
+        assert_eq!(
+            result.message,
+            "Converted 1 video, 2 failed (1 failed verification)"
+        );
+    }
+
+    #[test]
+    fn test_duration_tolerance() {
+        // Long outputs get a proportional tolerance
+        assert!((duration_tolerance(60.0, 30.0) - 1.2).abs() < 1e-9);
+        // Short ones are allowed to be two frames off
+        assert!((duration_tolerance(0.5, 25.0) - 0.08).abs() < 1e-9);
+        assert!((duration_tolerance(0.5, 0.0) - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_output_dimensions() {
+        let source = VideoInfo {
+            width: 1920,
+            height: 1080,
+            ..Default::default()
+        }
+        .with_rotation(90);
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            ..Default::default()
+        };
+        assert_eq!(output_dimensions(&source, &request), (1080, 1920));
+
+        let upright = ConversionRequest {
+            rotation_override: Some(0),
+            ..request.clone()
+        };
+        assert_eq!(output_dimensions(&source, &upright), (1920, 1080));
+
+        // Animations are scaled down after turning upright
+        let gif = ConversionRequest {
+            output_format: OutputFormat::Gif,
+            ..request.clone()
+        };
+        assert_eq!(output_dimensions(&source, &gif), (480, 853));
+
+        // A maximum height keeps the aspect ratio with even sizes
+        let small = ConversionRequest {
+            max_height: Some(721),
+            ..upright.clone()
+        };
+        assert_eq!(output_dimensions(&source, &small), (1280, 720));
+        let tall = ConversionRequest {
+            max_height: Some(2160),
+            ..upright
+        };
+        assert_eq!(output_dimensions(&source, &tall), (1920, 1080));
+
+        // Geometry comes before the maximum height
+        let square = ConversionRequest {
+            geometry: Geometry {
+                aspect: Some(AspectRatio::Square),
+                ..Default::default()
+            },
+            max_height: Some(720),
+            ..request
+        };
+        assert_eq!(output_dimensions(&source, &square), (720, 720));
+    }
+
+    #[tokio::test]
+    async fn test_convert_batch_reports_unsupported_files() {
+        let request = ConversionRequest {
+            files: vec!["/tmp/notes.txt".to_string()],
+            speed_multiplier: 10,
+            ..Default::default()
+        };
+        let mut events = Vec::new();
+        let result = convert_batch(&request, |event| events.push(event)).await;
+
+        assert!(!result.success);
+        assert_eq!(result.failed_count, 1);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].status, "Failed: Unsupported format");
+    }
+
+    #[tokio::test]
+    async fn test_convert_jobs_numbers_files_across_jobs() {
+        let job = |file: &str| ConversionRequest {
+            files: vec![file.to_string()],
+            speed_multiplier: 10,
+            ..Default::default()
+        };
+        let jobs = vec![job("/tmp/a.txt"), job("/tmp/b.txt")];
+        let mut events = Vec::new();
+        let result = convert_jobs(&jobs, |event| events.push(event)).await;
+
+        assert_eq!(result.failed_count, 2);
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[3].current_file, 2);
+        assert!(events.iter().all(|e| e.total_files == 2));
+    }
+
+    #[test]
+    fn test_conversion_request_validate() {
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            ..Default::default()
+        };
+        assert!(request.validate().is_ok());
+
+        let too_slow = ConversionRequest {
+            speed_multiplier: 1,
+            ..Default::default()
+        };
+        assert!(too_slow.validate().unwrap_err().contains("at least 2"));
+
+        let too_fast = ConversionRequest {
+            speed_multiplier: 1001,
+            ..Default::default()
+        };
+        assert!(too_fast
+            .validate()
+            .unwrap_err()
+            .contains("cannot exceed 1000"));
+
+        let bad_rotation = ConversionRequest {
+            speed_multiplier: 10,
+            rotation_override: Some(45),
+            ..Default::default()
+        };
+        assert!(bad_rotation.validate().is_err());
+    }
+
+    #[test]
+    fn test_trim_validation() {
+        let trimmed = |start, end| ConversionRequest {
+            speed_multiplier: 10,
+            trim_start_secs: start,
+            trim_end_secs: end,
+            ..Default::default()
+        };
+        assert!(trimmed(Some(5.0), Some(10.0)).validate().is_ok());
+        assert!(trimmed(None, Some(10.0)).validate().is_ok());
+        assert!(trimmed(Some(-1.0), None).validate().is_err());
+        assert!(trimmed(Some(10.0), Some(10.0)).validate().is_err());
+    }
+
+    #[test]
+    fn test_trimmed_duration() {
+        let request = ConversionRequest {
+            trim_start_secs: Some(10.0),
+            trim_end_secs: Some(70.0),
+            ..Default::default()
+        };
+        assert_eq!(request.trimmed_duration(100.0), 60.0);
+        assert_eq!(request.trimmed_duration(40.0), 30.0);
+        assert_eq!(ConversionRequest::default().trimmed_duration(100.0), 100.0);
+    }
+
+    #[test]
+    fn test_trim_args() {
+        let request = ConversionRequest {
+            trim_start_secs: Some(10.0),
+            trim_end_secs: Some(70.5),
+            ..Default::default()
+        };
+        assert_eq!(request.trim_args(), vec!["-ss", "10.000", "-t", "60.500"]);
+        assert!(ConversionRequest::default().trim_args().is_empty());
+    }
+}
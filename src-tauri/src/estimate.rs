@@ -0,0 +1,439 @@
+use crate::convert::{
+    animation_frame_rate, output_dimensions, output_frame_rate, ConversionRequest,
+};
+use crate::formats::{OutputFormat, VideoCodec};
+use crate::renditions::output_requests;
+use crate::report::FileReport;
+use crate::storage::{read_json, write_json};
+use crate::video::{get_info_many, VideoInfo};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bits per pixel per frame that libx264 at CRF 23 typically needs for
+/// timelapse footage, where every frame differs a lot from the previous one
+pub const H264_BITS_PER_PIXEL: f64 = 0.15;
+
+/// Bits per pixel per frame of libx265 at CRF 28, roughly a third less
+pub const H265_BITS_PER_PIXEL: f64 = 0.1;
+
+/// Bits per pixel per frame of the animated formats: paletted GIF frames
+/// only compress where little moves, APNG is lossless and WebP is lossy
+pub const GIF_BITS_PER_PIXEL: f64 = 3.0;
+pub const APNG_BITS_PER_PIXEL: f64 = 6.0;
+pub const WEBP_BITS_PER_PIXEL: f64 = 0.3;
+
+/// Bits per pixel of each frame of an image sequence: compressed PNG, JPEG
+/// at high quality and LZW-compressed TIFF
+pub const PNG_FRAME_BITS_PER_PIXEL: f64 = 12.0;
+pub const JPEG_FRAME_BITS_PER_PIXEL: f64 = 1.5;
+pub const TIFF_FRAME_BITS_PER_PIXEL: f64 = 16.0;
+
+/// Space taken by the MP4 container and its index
+const CONTAINER_OVERHEAD_BYTES: u64 = 64 * 1024;
+
+/// File name of the throughput history inside the config directory
+pub const THROUGHPUT_FILE: &str = "throughput.json";
+
+/// Current version of the throughput history format
+pub const THROUGHPUT_VERSION: u32 = 1;
+
+/// Number of recent conversions the throughput estimate is based on
+pub const MAX_THROUGHPUT_SAMPLES: usize = 50;
+
+/// Source pixels processed per second assumed before anything was converted
+/// on this machine; roughly 1080p30 at two times real time
+pub const DEFAULT_PIXEL_RATE: f64 = 125_000_000.0;
+
+/// Serializes read-modify-write cycles on the throughput file
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Expected result of converting one file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileEstimate {
+    pub input: String,
+    pub valid: bool,
+    pub error: Option<String>,
+    pub output_duration_secs: f64,
+    pub output_frames: u64,
+    pub estimated_bytes: u64,
+    pub estimated_encode_secs: f64,
+}
+
+/// Expected result of converting a batch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchEstimate {
+    pub files: Vec<FileEstimate>,
+    /// Totals over the valid files
+    pub output_duration_secs: f64,
+    pub output_frames: u64,
+    pub estimated_bytes: u64,
+    pub estimated_encode_secs: f64,
+    /// Source pixels per second the encode times are based on
+    pub pixel_rate: f64,
+    /// True when `pixel_rate` comes from conversions on this machine
+    pub from_history: bool,
+}
+
+/// Throughput of one finished conversion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThroughputSample {
+    /// Source pixels decoded and processed per second
+    pub pixel_rate: f64,
+    /// Seconds since the Unix epoch
+    pub recorded_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ThroughputHistory {
+    version: u32,
+    samples: Vec<ThroughputSample>,
+}
+
+/// Duration of the output made from `info` with `request`, in seconds
+pub fn output_duration_secs(info: &VideoInfo, request: &ConversionRequest) -> f64 {
+    if request.speed_multiplier == 0 {
+        return 0.0;
+    }
+    request.trimmed_duration(info.duration_secs) / request.speed_multiplier as f64
+}
+
+/// Number of frames in the output made from `info` with `request`
+pub fn output_frames(info: &VideoInfo, request: &ConversionRequest) -> f64 {
+    let fps = if request.output_format.is_animation() {
+        animation_frame_rate(info, request)
+    } else {
+        output_frame_rate(info)
+    };
+    output_duration_secs(info, request) * fps
+}
+
+/// Approximate size of the outputs made from `info` with `request`, in
+/// bytes: the sum over its renditions when it has any
+pub fn estimate_output_bytes(info: &VideoInfo, request: &ConversionRequest) -> u64 {
+    output_requests(request)
+        .iter()
+        .map(|(_, settings)| estimate_single_output_bytes(info, settings))
+        .sum()
+}
+
+fn estimate_single_output_bytes(info: &VideoInfo, request: &ConversionRequest) -> u64 {
+    let (width, height) = output_dimensions(info, request);
+    let pixels = width as f64 * height as f64 * output_frames(info, request);
+    let bits_per_pixel = match request.output_format {
+        OutputFormat::Mp4
+        | OutputFormat::Mp4Faststart
+        | OutputFormat::FragmentedMp4
+        | OutputFormat::Hls => match request.video_codec {
+            VideoCodec::H264 => H264_BITS_PER_PIXEL,
+            VideoCodec::H265 => H265_BITS_PER_PIXEL,
+        },
+        OutputFormat::Gif => GIF_BITS_PER_PIXEL,
+        OutputFormat::Apng => APNG_BITS_PER_PIXEL,
+        OutputFormat::Webp => WEBP_BITS_PER_PIXEL,
+        OutputFormat::PngSequence => PNG_FRAME_BITS_PER_PIXEL,
+        OutputFormat::JpegSequence => JPEG_FRAME_BITS_PER_PIXEL,
+        OutputFormat::TiffSequence => TIFF_FRAME_BITS_PER_PIXEL,
+    };
+    let bytes = (pixels * bits_per_pixel / 8.0).ceil() as u64;
+    if request.output_format.is_animation() {
+        // Animations over their target are scaled down until they fit
+        request
+            .animation
+            .target_bytes
+            .map_or(bytes, |target| bytes.min(target))
+    } else if request.output_format.is_sequence() {
+        bytes
+    } else {
+        bytes + CONTAINER_OVERHEAD_BYTES
+    }
+}
+
+/// Source frames decoded to make the output
+pub fn source_frames(info: &VideoInfo, request: &ConversionRequest) -> f64 {
+    let fps = if info.is_vfr && info.avg_fps > 0.0 {
+        info.avg_fps
+    } else {
+        info.fps
+    };
+    request.trimmed_duration(info.duration_secs) * fps
+}
+
+/// Estimate one file from its probe. `pixel_rate` is the expected number of
+/// source pixels processed per second.
+pub fn estimate_file(
+    info: &VideoInfo,
+    request: &ConversionRequest,
+    pixel_rate: f64,
+) -> FileEstimate {
+    if !info.valid {
+        return FileEstimate {
+            input: info.path.clone(),
+            error: info.error.clone(),
+            ..Default::default()
+        };
+    }
+
+    let output_duration_secs = output_duration_secs(info, request);
+    let source_pixels = source_frames(info, request) * info.width as f64 * info.height as f64;
+    FileEstimate {
+        input: info.path.clone(),
+        valid: true,
+        error: None,
+        output_duration_secs,
+        output_frames: output_frames(info, request).round() as u64,
+        estimated_bytes: estimate_output_bytes(info, request),
+        estimated_encode_secs: source_pixels / pixel_rate,
+    }
+}
+
+/// Probe every file of a request and estimate the batch, using the measured
+/// `pixel_rate` of this machine when there is one
+pub async fn estimate_batch(request: &ConversionRequest, pixel_rate: Option<f64>) -> BatchEstimate {
+    let infos = get_info_many(request.files.clone(), |_, _| {}).await;
+    let mut estimate = BatchEstimate {
+        pixel_rate: pixel_rate.unwrap_or(DEFAULT_PIXEL_RATE),
+        from_history: pixel_rate.is_some(),
+        ..Default::default()
+    };
+
+    for info in &infos {
+        let file = estimate_file(info, request, estimate.pixel_rate);
+        if file.valid {
+            estimate.output_duration_secs += file.output_duration_secs;
+            estimate.output_frames += file.output_frames;
+            estimate.estimated_bytes += file.estimated_bytes;
+            estimate.estimated_encode_secs += file.estimated_encode_secs;
+        }
+        estimate.files.push(file);
+    }
+    estimate
+}
+
+/// Pixel rate achieved by a finished conversion
+pub fn measured_pixel_rate(report: &FileReport) -> Option<f64> {
+    // Renditions share one encode, so its time isn't that of a single output
+    let info = report
+        .info
+        .as_ref()
+        .filter(|_| report.succeeded() && report.rendition.is_none())?;
+    let pixels = source_frames(info, &report.settings) * info.width as f64 * info.height as f64;
+    (report.encode_secs > 0.0 && pixels > 0.0).then(|| pixels / report.encode_secs)
+}
+
+/// History of conversion throughput on this machine, persisted as JSON
+pub struct ThroughputStore {
+    path: PathBuf,
+}
+
+impl ThroughputStore {
+    /// Store history in `dir`/throughput.json
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        ThroughputStore {
+            path: dir.as_ref().join(THROUGHPUT_FILE),
+        }
+    }
+
+    /// Recorded samples, oldest first
+    pub fn samples(&self) -> Result<Vec<ThroughputSample>, String> {
+        Ok(read_json::<ThroughputHistory>(&self.path)?
+            .map(|history| history.samples)
+            .unwrap_or_default())
+    }
+
+    /// Median pixel rate of the recorded conversions, `None` without history
+    pub fn pixel_rate(&self) -> Result<Option<f64>, String> {
+        let mut rates: Vec<f64> = self.samples()?.iter().map(|s| s.pixel_rate).collect();
+        if rates.is_empty() {
+            return Ok(None);
+        }
+        rates.sort_by(|a, b| a.total_cmp(b));
+        Ok(Some(rates[rates.len() / 2]))
+    }
+
+    /// Add the throughput of the successful files of a batch, keeping the
+    /// most recent `MAX_THROUGHPUT_SAMPLES`. Returns the number added.
+    pub fn record(&self, reports: &[FileReport]) -> Result<usize, String> {
+        let recorded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let new: Vec<ThroughputSample> = reports
+            .iter()
+            .filter_map(measured_pixel_rate)
+            .map(|pixel_rate| ThroughputSample {
+                pixel_rate,
+                recorded_at,
+            })
+            .collect();
+        if new.is_empty() {
+            return Ok(0);
+        }
+
+        let _guard = STORE_LOCK.lock().map_err(|e| e.to_string())?;
+        let mut samples = self.samples()?;
+        let added = new.len();
+        samples.extend(new);
+        let excess = samples.len().saturating_sub(MAX_THROUGHPUT_SAMPLES);
+        samples.drain(..excess);
+        write_json(
+            &self.path,
+            &ThroughputHistory {
+                version: THROUGHPUT_VERSION,
+                samples,
+            },
+        )?;
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> VideoInfo {
+        VideoInfo {
+            duration_secs: 600.0,
+            width: 1920,
+            height: 1080,
+            fps: 30.0,
+            valid: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_output_duration_secs() {
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            trim_start_secs: Some(100.0),
+            ..Default::default()
+        };
+        assert_eq!(output_duration_secs(&info(), &request), 50.0);
+        assert_eq!(
+            output_duration_secs(&info(), &ConversionRequest::default()),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_estimate_output_bytes() {
+        let request = ConversionRequest {
+            speed_multiplier: 100,
+            ..Default::default()
+        };
+        // 6 seconds of 1080p30: 180 frames of 2073600 pixels
+        let expected =
+            (1920.0 * 1080.0 * 180.0 * H264_BITS_PER_PIXEL / 8.0) as u64 + CONTAINER_OVERHEAD_BYTES;
+        assert_eq!(estimate_output_bytes(&info(), &request), expected);
+
+        let faster = ConversionRequest {
+            speed_multiplier: 1000,
+            ..Default::default()
+        };
+        assert!(estimate_output_bytes(&info(), &faster) < expected);
+    }
+
+    #[test]
+    fn test_estimate_animation() {
+        let mut request = ConversionRequest {
+            speed_multiplier: 100,
+            output_format: OutputFormat::Gif,
+            ..Default::default()
+        };
+        // 6 seconds at 15 fps, scaled down to 480x270
+        assert_eq!(output_frames(&info(), &request), 90.0);
+        let expected = (480.0 * 270.0 * 90.0 * GIF_BITS_PER_PIXEL / 8.0) as u64;
+        assert_eq!(estimate_output_bytes(&info(), &request), expected);
+
+        request.animation.target_bytes = Some(1_000_000);
+        assert_eq!(estimate_output_bytes(&info(), &request), 1_000_000);
+    }
+
+    #[test]
+    fn test_estimate_renditions() {
+        let request = ConversionRequest {
+            speed_multiplier: 100,
+            renditions: vec![
+                "master:mp4".parse().unwrap(),
+                "web:mp4:540:h265".parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        // Full size with H.264 plus half size with H.265
+        let master = (1920.0 * 1080.0 * 180.0 * H264_BITS_PER_PIXEL / 8.0) as u64;
+        let web = (960.0 * 540.0 * 180.0 * H265_BITS_PER_PIXEL / 8.0).ceil() as u64;
+        assert_eq!(
+            estimate_output_bytes(&info(), &request),
+            master + web + 2 * CONTAINER_OVERHEAD_BYTES
+        );
+    }
+
+    #[test]
+    fn test_estimate_image_sequence() {
+        let request = ConversionRequest {
+            speed_multiplier: 100,
+            output_format: OutputFormat::JpegSequence,
+            ..Default::default()
+        };
+        // Every output frame at full size, without a container
+        assert_eq!(output_frames(&info(), &request), 180.0);
+        let expected = (1920.0 * 1080.0 * 180.0 * JPEG_FRAME_BITS_PER_PIXEL / 8.0) as u64;
+        assert_eq!(estimate_output_bytes(&info(), &request), expected);
+    }
+
+    #[test]
+    fn test_estimate_file() {
+        let request = ConversionRequest {
+            speed_multiplier: 100,
+            ..Default::default()
+        };
+        // 18000 source frames of 1080p at 2 frames per second
+        let estimate = estimate_file(&info(), &request, 1920.0 * 1080.0 * 2.0);
+        assert!(estimate.valid);
+        assert_eq!(estimate.output_duration_secs, 6.0);
+        assert_eq!(estimate.output_frames, 180);
+        assert_eq!(estimate.estimated_encode_secs, 9000.0);
+
+        let invalid = VideoInfo::invalid("x.mp4", "x.mp4", "Cannot open".to_string());
+        let estimate = estimate_file(&invalid, &request, DEFAULT_PIXEL_RATE);
+        assert!(!estimate.valid);
+        assert_eq!(estimate.error.as_deref(), Some("Cannot open"));
+    }
+
+    #[test]
+    fn test_throughput_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ThroughputStore::new(dir.path());
+        assert_eq!(store.pixel_rate().unwrap(), None);
+
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            ..Default::default()
+        };
+        let report = |encode_secs: f64| {
+            let mut report = FileReport::new("a.mp4", &request);
+            report.info = Some(info());
+            report.encode_secs = encode_secs;
+            report
+        };
+        let mut failed = report(1.0);
+        failed.error = Some(crate::report::FileError::new(
+            crate::report::ErrorCategory::EncodeFailed,
+            "x",
+        ));
+
+        let added = store
+            .record(&[report(100.0), report(200.0), report(400.0), failed])
+            .unwrap();
+        assert_eq!(added, 3);
+        // 600s at 30fps of 1080p in 200s
+        let median = 1920.0 * 1080.0 * 18000.0 / 200.0;
+        assert_eq!(store.pixel_rate().unwrap(), Some(median));
+
+        let many: Vec<FileReport> = (0..MAX_THROUGHPUT_SAMPLES).map(|_| report(50.0)).collect();
+        store.record(&many).unwrap();
+        assert_eq!(store.samples().unwrap().len(), MAX_THROUGHPUT_SAMPLES);
+    }
+}
@@ -0,0 +1,567 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Frame rate of animated images unless the options ask for another
+pub const DEFAULT_ANIMATION_FPS: f64 = 15.0;
+
+/// Width animated images are scaled down to unless the options ask for another
+pub const DEFAULT_ANIMATION_WIDTH: u32 = 480;
+
+/// Narrowest an animation may be scaled down to while fitting a target size
+pub const MIN_ANIMATION_WIDTH: u32 = 32;
+
+/// Encodes tried while fitting an animation into its target size
+pub const MAX_SIZE_ATTEMPTS: usize = 4;
+
+/// Filter turning the frames into a GIF palette, the first of the two GIF
+/// passes; it weighs what moves, since static parts of a timelapse need few
+/// colors
+pub const PALETTEGEN_FILTER: &str = "palettegen=stats_mode=diff";
+
+/// Playlist of an HLS package, inside the package folder
+pub const HLS_PLAYLIST_NAME: &str = "index.m3u8";
+
+/// Segments of an HLS package, numbered from 0 beside the playlist
+pub const HLS_SEGMENT_PATTERN: &str = "segment_%05d.ts";
+
+/// Target length of each HLS segment; a keyframe is forced at every boundary
+pub const HLS_SEGMENT_SECS: u32 = 4;
+
+/// Kind of file a conversion writes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// H.264 video in an MP4 container
+    #[default]
+    Mp4,
+    /// MP4 with its index at the start, so playback begins while downloading
+    Mp4Faststart,
+    /// MP4 written as self-contained fragments, for streaming players
+    FragmentedMp4,
+    /// HLS package: a playlist and MPEG-TS segments in a folder
+    Hls,
+    /// Animated GIF with a palette generated from the whole output
+    Gif,
+    /// Animated PNG
+    Apng,
+    /// Animated WebP
+    Webp,
+    /// Numbered PNG frames in a folder
+    PngSequence,
+    /// Numbered JPEG frames in a folder
+    JpegSequence,
+    /// Numbered TIFF frames in a folder
+    TiffSequence,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Mp4 | OutputFormat::Mp4Faststart | OutputFormat::FragmentedMp4 => "mp4",
+            OutputFormat::Hls => "m3u8",
+            OutputFormat::Gif => "gif",
+            OutputFormat::Apng => "png",
+            OutputFormat::Webp => "webp",
+            OutputFormat::PngSequence => "png",
+            OutputFormat::JpegSequence => "jpg",
+            OutputFormat::TiffSequence => "tif",
+        }
+    }
+
+    /// True for the animated image formats
+    pub fn is_animation(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Gif | OutputFormat::Apng | OutputFormat::Webp
+        )
+    }
+
+    /// True for the H.264 video formats
+    pub fn is_video(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Mp4
+                | OutputFormat::Mp4Faststart
+                | OutputFormat::FragmentedMp4
+                | OutputFormat::Hls
+        )
+    }
+
+    /// True for the formats written as a folder of numbered frames
+    pub fn is_sequence(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::PngSequence | OutputFormat::JpegSequence | OutputFormat::TiffSequence
+        )
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "mp4" => Ok(OutputFormat::Mp4),
+            "mp4_faststart" | "faststart" => Ok(OutputFormat::Mp4Faststart),
+            "fragmented_mp4" | "fmp4" => Ok(OutputFormat::FragmentedMp4),
+            "hls" => Ok(OutputFormat::Hls),
+            "gif" => Ok(OutputFormat::Gif),
+            "apng" | "png" => Ok(OutputFormat::Apng),
+            "webp" => Ok(OutputFormat::Webp),
+            "png_sequence" => Ok(OutputFormat::PngSequence),
+            "jpeg_sequence" | "jpg_sequence" => Ok(OutputFormat::JpegSequence),
+            "tiff_sequence" | "tif_sequence" => Ok(OutputFormat::TiffSequence),
+            other => Err(format!(
+                "Unknown output format '{}' (expected mp4, mp4_faststart, fragmented_mp4, \
+                 hls, gif, apng, webp, png_sequence, jpeg_sequence or tiff_sequence)",
+                other
+            )),
+        }
+    }
+}
+
+/// Encoder of the video formats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    /// Plays everywhere
+    #[default]
+    H264,
+    /// About a third smaller at the same quality, but slower to encode and
+    /// not supported by every browser
+    H265,
+}
+
+impl VideoCodec {
+    /// Encoder, rate control and tagging arguments
+    pub fn encoder_args(self) -> Vec<String> {
+        let args: &[&str] = match self {
+            VideoCodec::H264 => &["-c:v", "libx264", "-preset", "fast", "-crf", "23"],
+            // hvc1 is the tag Apple players require; x265 logs every frame
+            // unless told otherwise
+            VideoCodec::H265 => &[
+                "-c:v",
+                "libx265",
+                "-preset",
+                "fast",
+                "-crf",
+                "28",
+                "-tag:v",
+                "hvc1",
+                "-x265-params",
+                "log-level=error",
+            ],
+        };
+        args.iter().map(|s| s.to_string()).collect()
+    }
+}
+
+impl FromStr for VideoCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('.', "").as_str() {
+            "h264" | "avc" | "x264" => Ok(VideoCodec::H264),
+            "h265" | "hevc" | "x265" => Ok(VideoCodec::H265),
+            other => Err(format!(
+                "Unknown video codec '{}' (expected h264 or h265)",
+                other
+            )),
+        }
+    }
+}
+
+/// How GIF colors outside the palette are approximated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dither {
+    /// Flat color bands, smallest files
+    None,
+    /// Ordered pattern; compresses well and doesn't shimmer between frames
+    Bayer,
+    FloydSteinberg,
+    /// Error diffusion that keeps gradients smooth
+    #[default]
+    Sierra2_4a,
+}
+
+impl Dither {
+    /// Value of the `paletteuse` filter's `dither` option
+    pub fn ffmpeg_name(self) -> &'static str {
+        match self {
+            Dither::None => "none",
+            Dither::Bayer => "bayer",
+            Dither::FloydSteinberg => "floyd_steinberg",
+            Dither::Sierra2_4a => "sierra2_4a",
+        }
+    }
+}
+
+impl FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "none" => Ok(Dither::None),
+            "bayer" => Ok(Dither::Bayer),
+            "floyd_steinberg" => Ok(Dither::FloydSteinberg),
+            "sierra2_4a" => Ok(Dither::Sierra2_4a),
+            other => Err(format!(
+                "Unknown dither '{}' (expected none, bayer, floyd_steinberg or sierra2_4a)",
+                other
+            )),
+        }
+    }
+}
+
+/// Settings of animated image outputs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationOptions {
+    /// Frames per second [default: `DEFAULT_ANIMATION_FPS`, or the video's
+    /// rate when that is lower]
+    pub fps: Option<f64>,
+    /// Scale down to at most this width, keeping the aspect ratio
+    pub max_width: Option<u32>,
+    /// Lower the frame rate so the whole output fits in this many frames
+    pub max_frames: Option<u32>,
+    /// Scale down further until the file is at most this many bytes
+    pub target_bytes: Option<u64>,
+    /// Times the animation plays [default: forever]
+    pub loops: Option<u32>,
+    /// GIF only
+    pub dither: Dither,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        AnimationOptions {
+            fps: None,
+            max_width: Some(DEFAULT_ANIMATION_WIDTH),
+            max_frames: None,
+            target_bytes: None,
+            loops: None,
+            dither: Dither::default(),
+        }
+    }
+}
+
+impl AnimationOptions {
+    /// Check the settings without touching any files
+    pub fn validate(&self) -> Result<(), String> {
+        if self.fps.is_some_and(|fps| !(fps > 0.0 && fps <= 100.0)) {
+            return Err("Animation frame rate must be between 0 and 100".to_string());
+        }
+        if self.max_width.is_some_and(|w| w < MIN_ANIMATION_WIDTH) {
+            return Err(format!(
+                "Animation width must be at least {}",
+                MIN_ANIMATION_WIDTH
+            ));
+        }
+        if self.max_frames == Some(0) {
+            return Err("Animation needs at least one frame".to_string());
+        }
+        if self.target_bytes == Some(0) {
+            return Err("Target size must be above zero".to_string());
+        }
+        if self.loops == Some(0) {
+            return Err("Animation must play at least once".to_string());
+        }
+        Ok(())
+    }
+
+    /// Frame rate of an animation lasting `duration_secs`, made from a
+    /// video running at `video_fps`
+    pub fn frame_rate(&self, video_fps: f64, duration_secs: f64) -> f64 {
+        let mut fps = self.fps.unwrap_or(DEFAULT_ANIMATION_FPS);
+        if video_fps > 0.0 {
+            fps = fps.min(video_fps);
+        }
+        if let Some(max_frames) = self.max_frames.filter(|_| duration_secs > 0.0) {
+            fps = fps.min(max_frames as f64 / duration_secs);
+        }
+        fps
+    }
+
+    /// Size of an animation made from upright `width`x`height` frames
+    pub fn dimensions(&self, width: u32, height: u32) -> (u32, u32) {
+        match self.max_width {
+            Some(max_width) if max_width < width => {
+                let scaled = (height as f64 * max_width as f64 / width as f64).round() as u32;
+                (max_width, scaled.max(1))
+            }
+            _ => (width, height),
+        }
+    }
+
+    /// Width for the next attempt at fitting `target_bytes` after an encode
+    /// `width` pixels wide came out at `bytes`, or `None` when it already fits
+    pub fn next_width(&self, width: u32, bytes: u64) -> Option<u32> {
+        let target = self.target_bytes.filter(|&target| bytes > target)?;
+        // Size grows with the pixel count, so scale each side by the square
+        // root, with some room since palettes and compression don't scale
+        let factor = (target as f64 / bytes as f64).sqrt() * 0.95;
+        Some(((width as f64 * factor).floor() as u32).min(width.saturating_sub(1)))
+    }
+}
+
+/// Filters appended to the timelapse graph for an animation: frame rate and
+/// size, with a high-quality scaler since every pixel shows in a small image
+pub fn animation_filters(fps: f64, width: u32, height: u32) -> String {
+    format!("fps={:.3},scale={}:{}:flags=lanczos", fps, width, height)
+}
+
+/// Filter mapping frames (first input) onto the palette (second input)
+pub fn paletteuse_filter(dither: Dither) -> String {
+    format!(
+        "paletteuse=dither={}:diff_mode=rectangle",
+        dither.ffmpeg_name()
+    )
+}
+
+/// Value of `-movflags` for the MP4 formats; custom metadata keys are only
+/// kept with `use_metadata_tags`
+pub fn mp4_movflags(format: OutputFormat) -> Option<&'static str> {
+    match format {
+        OutputFormat::Mp4 => Some("+use_metadata_tags"),
+        OutputFormat::Mp4Faststart => Some("+faststart+use_metadata_tags"),
+        OutputFormat::FragmentedMp4 => {
+            Some("+frag_keyframe+empty_moov+default_base_moof+use_metadata_tags")
+        }
+        _ => None,
+    }
+}
+
+/// Muxer arguments of an HLS package whose playlist is `playlist_path`
+///
+/// Keyframes are forced at segment boundaries, since segments can only
+/// start on one and the encoder's own interval is much longer.
+pub fn hls_output_args(playlist_path: &str) -> Vec<String> {
+    let dir = std::path::Path::new(playlist_path)
+        .parent()
+        .unwrap_or(std::path::Path::new("."));
+    vec![
+        "-force_key_frames".to_string(),
+        format!("expr:gte(t,n_forced*{})", HLS_SEGMENT_SECS),
+        "-f".to_string(),
+        "hls".to_string(),
+        "-hls_time".to_string(),
+        HLS_SEGMENT_SECS.to_string(),
+        "-hls_playlist_type".to_string(),
+        "vod".to_string(),
+        "-hls_segment_filename".to_string(),
+        dir.join(HLS_SEGMENT_PATTERN).to_string_lossy().to_string(),
+    ]
+}
+
+/// Encoder and muxer arguments of an animated format, including its loop
+/// count
+pub fn animation_output_args(format: OutputFormat, options: &AnimationOptions) -> Vec<String> {
+    let plays = options.loops;
+    match format {
+        OutputFormat::Gif => {
+            // GIF counts repeats after the first play, with -1 for none
+            let repeats = plays.map_or(0, |n| if n <= 1 { -1 } else { n as i64 - 1 });
+            vec![
+                "-f".to_string(),
+                "gif".to_string(),
+                "-loop".to_string(),
+                repeats.to_string(),
+            ]
+        }
+        OutputFormat::Apng => vec![
+            "-f".to_string(),
+            "apng".to_string(),
+            "-plays".to_string(),
+            plays.unwrap_or(0).to_string(),
+        ],
+        OutputFormat::Webp => [
+            "-c:v",
+            "libwebp",
+            "-lossless",
+            "0",
+            "-q:v",
+            "75",
+            "-f",
+            "webp",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .chain(["-loop".to_string(), plays.unwrap_or(0).to_string()])
+        .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_format_parsing() {
+        assert_eq!("GIF".parse::<OutputFormat>(), Ok(OutputFormat::Gif));
+        assert_eq!("png".parse::<OutputFormat>(), Ok(OutputFormat::Apng));
+        assert!("avi".parse::<OutputFormat>().is_err());
+        assert_eq!(OutputFormat::Apng.extension(), "png");
+        assert!(!OutputFormat::Mp4.is_animation());
+        assert_eq!(
+            "jpg-sequence".parse::<OutputFormat>(),
+            Ok(OutputFormat::JpegSequence)
+        );
+        assert!(OutputFormat::TiffSequence.is_sequence());
+        assert_eq!(
+            "fmp4".parse::<OutputFormat>(),
+            Ok(OutputFormat::FragmentedMp4)
+        );
+        assert!(OutputFormat::Hls.is_video());
+        assert!(!OutputFormat::Hls.is_animation());
+        assert!(!OutputFormat::TiffSequence.is_animation());
+        assert_eq!(
+            serde_json::to_string(&OutputFormat::PngSequence).unwrap(),
+            "\"png_sequence\""
+        );
+        assert_eq!(
+            serde_json::to_string(&OutputFormat::Webp).unwrap(),
+            "\"webp\""
+        );
+        assert_eq!(
+            "floyd-steinberg".parse::<Dither>(),
+            Ok(Dither::FloydSteinberg)
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(AnimationOptions::default().validate().is_ok());
+        for options in [
+            AnimationOptions {
+                fps: Some(0.0),
+                ..Default::default()
+            },
+            AnimationOptions {
+                max_width: Some(8),
+                ..Default::default()
+            },
+            AnimationOptions {
+                loops: Some(0),
+                ..Default::default()
+            },
+            AnimationOptions {
+                max_frames: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert!(options.validate().is_err(), "{:?}", options);
+        }
+    }
+
+    #[test]
+    fn test_frame_rate() {
+        let options = AnimationOptions::default();
+        assert_eq!(options.frame_rate(30.0, 10.0), 15.0);
+        assert_eq!(options.frame_rate(10.0, 10.0), 10.0);
+
+        let capped = AnimationOptions {
+            max_frames: Some(50),
+            ..Default::default()
+        };
+        assert_eq!(capped.frame_rate(30.0, 10.0), 5.0);
+        assert_eq!(capped.frame_rate(30.0, 1.0), 15.0);
+    }
+
+    #[test]
+    fn test_dimensions() {
+        let options = AnimationOptions::default();
+        assert_eq!(options.dimensions(1920, 1080), (480, 270));
+        assert_eq!(options.dimensions(320, 240), (320, 240));
+        let full = AnimationOptions {
+            max_width: None,
+            ..Default::default()
+        };
+        assert_eq!(full.dimensions(1921, 1081), (1921, 1081));
+    }
+
+    #[test]
+    fn test_next_width() {
+        let options = AnimationOptions {
+            target_bytes: Some(1_000_000),
+            ..Default::default()
+        };
+        assert_eq!(options.next_width(480, 900_000), None);
+        // Four times too big: half the width, less the margin
+        assert_eq!(options.next_width(480, 4_000_000), Some(228));
+        // Always shrinks, even when only just over
+        assert_eq!(options.next_width(480, 1_000_001), Some(455));
+        assert_eq!(options.next_width(100, 1_000_001), Some(94));
+        assert_eq!(AnimationOptions::default().next_width(480, u64::MAX), None);
+    }
+
+    #[test]
+    fn test_animation_output_args() {
+        let forever = AnimationOptions::default();
+        let once = AnimationOptions {
+            loops: Some(1),
+            ..Default::default()
+        };
+        let thrice = AnimationOptions {
+            loops: Some(3),
+            ..Default::default()
+        };
+
+        assert!(animation_output_args(OutputFormat::Gif, &forever)
+            .ends_with(&["-loop".into(), "0".into()]));
+        assert!(animation_output_args(OutputFormat::Gif, &once)
+            .ends_with(&["-loop".into(), "-1".into()]));
+        assert!(animation_output_args(OutputFormat::Gif, &thrice)
+            .ends_with(&["-loop".into(), "2".into()]));
+        assert!(animation_output_args(OutputFormat::Apng, &thrice)
+            .ends_with(&["-plays".into(), "3".into()]));
+        let webp = animation_output_args(OutputFormat::Webp, &forever);
+        assert!(webp.contains(&"libwebp".to_string()));
+        assert!(webp.ends_with(&["-loop".into(), "0".into()]));
+        assert!(animation_output_args(OutputFormat::Mp4, &forever).is_empty());
+    }
+
+    #[test]
+    fn test_video_codec() {
+        assert_eq!("HEVC".parse::<VideoCodec>(), Ok(VideoCodec::H265));
+        assert_eq!("h.264".parse::<VideoCodec>(), Ok(VideoCodec::H264));
+        assert!("vp9".parse::<VideoCodec>().is_err());
+        assert!(VideoCodec::H264
+            .encoder_args()
+            .contains(&"libx264".to_string()));
+        let h265 = VideoCodec::H265.encoder_args();
+        assert!(h265.contains(&"libx265".to_string()));
+        assert!(h265.contains(&"hvc1".to_string()));
+    }
+
+    #[test]
+    fn test_streaming_args() {
+        assert_eq!(mp4_movflags(OutputFormat::Mp4), Some("+use_metadata_tags"));
+        assert!(mp4_movflags(OutputFormat::Mp4Faststart)
+            .is_some_and(|flags| flags.contains("+faststart")));
+        assert!(mp4_movflags(OutputFormat::FragmentedMp4)
+            .is_some_and(|flags| flags.contains("+frag_keyframe+empty_moov")));
+        assert_eq!(mp4_movflags(OutputFormat::Hls), None);
+
+        let hls = hls_output_args("/out/clip_timelapse_hls/index.m3u8");
+        assert!(hls.contains(&"expr:gte(t,n_forced*4)".to_string()));
+        assert!(hls.contains(&"vod".to_string()));
+        assert_eq!(
+            hls.last().unwrap(),
+            "/out/clip_timelapse_hls/segment_%05d.ts"
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(
+            animation_filters(12.5, 480, 270),
+            "fps=12.500,scale=480:270:flags=lanczos"
+        );
+        assert_eq!(
+            paletteuse_filter(Dither::Bayer),
+            "paletteuse=dither=bayer:diff_mode=rectangle"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use crate::tools;
+use crate::video::{probe_concurrency, FileStamp, VideoInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+/// Folder inside the system temp directory that holds extracted frames
+pub const FRAME_CACHE_DIR_NAME: &str = "timelapse-creator-frames";
+
+/// Extracted frames kept on disk; the oldest are removed beyond this
+pub const MAX_CACHED_FRAMES: usize = 500;
+
+/// Width of the poster thumbnail attached to each probed video
+pub const POSTER_WIDTH: u32 = 320;
+
+/// Image format of an extracted frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Png,
+    #[default]
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+/// Folder extracted frames are cached in
+pub fn frame_cache_dir() -> PathBuf {
+    std::env::temp_dir().join(FRAME_CACHE_DIR_NAME)
+}
+
+/// Extract the frame shown at `timestamp_secs`, scaled down to at most
+/// `max_width`, and return the path of the image
+///
+/// FFmpeg seeks to the keyframe at or before the timestamp and returns it
+/// without decoding up to the exact position, which keeps scrubbing fast.
+/// Images are cached until the source's size or modification time changes.
+pub fn extract_frame(
+    path: &str,
+    timestamp_secs: f64,
+    max_width: u32,
+    format: ImageFormat,
+) -> Result<String, String> {
+    if !timestamp_secs.is_finite() || timestamp_secs < 0.0 {
+        return Err("Timestamp must be zero or positive".to_string());
+    }
+    if max_width < 2 {
+        return Err("Frame width must be at least 2".to_string());
+    }
+    let stamp = FileStamp::of(path).ok_or_else(|| format!("Cannot read file '{}'", path))?;
+
+    let dir = frame_cache_dir();
+    let output = dir.join(cache_file_name(&stamp, timestamp_secs, max_width, format));
+    if output.is_file() {
+        return Ok(output.to_string_lossy().to_string());
+    }
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+
+    // Write under a temporary name so a cached image is always complete
+    let partial = output.with_extension(format!("part.{}", format.extension()));
+    let result = Command::new(tools::ffmpeg())
+        .args(build_frame_args(
+            path,
+            &partial.to_string_lossy(),
+            timestamp_secs,
+            max_width,
+            format,
+        ))
+        .output()
+        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+
+    if !result.status.success() || !partial.is_file() {
+        let _ = std::fs::remove_file(&partial);
+        return Err(format!("No frame at {:.3}s in '{}'", timestamp_secs, path));
+    }
+    std::fs::rename(&partial, &output).map_err(|e| format!("Failed to cache frame: {}", e))?;
+
+    prune_cache(&dir, MAX_CACHED_FRAMES);
+    Ok(output.to_string_lossy().to_string())
+}
+
+/// Timestamp of the default poster: a little way in, since the first frames
+/// of a recording are often black or shaky
+pub fn poster_timestamp(info: &VideoInfo) -> f64 {
+    (info.duration_secs * 0.1).clamp(0.0, 10.0)
+}
+
+/// Extract poster thumbnails for valid videos concurrently and store them in
+/// `VideoInfo::poster_path`
+///
+/// `on_ready` is called with the index of each video that got a poster.
+/// Videos whose poster can't be extracted are left without one.
+pub async fn attach_posters<F>(infos: &mut [VideoInfo], mut on_ready: F)
+where
+    F: FnMut(usize, &VideoInfo),
+{
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(probe_concurrency()));
+    let mut tasks = tokio::task::JoinSet::new();
+
+    for (index, info) in infos.iter().enumerate().filter(|(_, info)| info.valid) {
+        let semaphore = semaphore.clone();
+        let path = info.path.clone();
+        let timestamp = poster_timestamp(info);
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let poster = tokio::task::spawn_blocking(move || {
+                extract_frame(&path, timestamp, POSTER_WIDTH, ImageFormat::Jpeg)
+            })
+            .await;
+            (index, poster)
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((index, Ok(Ok(poster)))) = joined {
+            infos[index].poster_path = Some(poster);
+            on_ready(index, &infos[index]);
+        }
+    }
+}
+
+fn build_frame_args(
+    input_path: &str,
+    output_path: &str,
+    timestamp_secs: f64,
+    max_width: u32,
+    format: ImageFormat,
+) -> Vec<String> {
+    let mut args = vec![
+        "-y".to_string(),
+        "-loglevel".to_string(),
+        "error".to_string(),
+        // Input seeking lands on a keyframe; skip decoding up to the exact time
+        "-ss".to_string(),
+        format!("{:.3}", timestamp_secs),
+        "-noaccurate_seek".to_string(),
+        "-i".to_string(),
+        input_path.to_string(),
+        "-frames:v".to_string(),
+        "1".to_string(),
+        "-an".to_string(),
+        "-vf".to_string(),
+        format!("scale='min({},iw)':-2", max_width / 2 * 2),
+    ];
+    if format == ImageFormat::Jpeg {
+        args.extend(["-q:v".to_string(), "3".to_string()]);
+    }
+    args.push(output_path.to_string());
+    args
+}
+
+/// Cache key covering the source's identity and every extraction setting
+fn cache_file_name(
+    stamp: &FileStamp,
+    timestamp_secs: f64,
+    max_width: u32,
+    format: ImageFormat,
+) -> String {
+    let mut hasher = DefaultHasher::new();
+    stamp.hash(&mut hasher);
+    let millis = (timestamp_secs * 1000.0).round() as u64;
+    (millis, max_width, format).hash(&mut hasher);
+    format!("{:016x}.{}", hasher.finish(), format.extension())
+}
+
+/// Remove the least recently written images beyond `keep`
+///
+/// Partial files of extractions still running are left alone.
+fn prune_cache(dir: &Path, keep: usize) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut images: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+            Some((modified, entry.path()))
+        })
+        .filter(|(_, path)| {
+            path.extension()
+                .is_some_and(|ext| ext == "png" || ext == "jpg")
+                && !path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().contains(".part."))
+        })
+        .collect();
+    if images.len() <= keep {
+        return 0;
+    }
+    images.sort();
+    let excess = images.len() - keep;
+    images
+        .into_iter()
+        .take(excess)
+        .filter(|(_, path)| std::fs::remove_file(path).is_ok())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_frame_args() {
+        let args = build_frame_args("in.mp4", "out.jpg", 12.5, 321, ImageFormat::Jpeg);
+        let seek = args.iter().position(|a| a == "-ss").unwrap();
+        let input = args.iter().position(|a| a == "-i").unwrap();
+        assert!(seek < input, "seek must be an input option");
+        assert_eq!(args[seek + 1], "12.500");
+        assert!(args.contains(&"-noaccurate_seek".to_string()));
+        assert!(args.contains(&"scale='min(320,iw)':-2".to_string()));
+        assert!(args.contains(&"-q:v".to_string()));
+        assert_eq!(args.last().unwrap(), "out.jpg");
+
+        let png = build_frame_args("in.mp4", "out.png", 0.0, 320, ImageFormat::Png);
+        assert!(!png.contains(&"-q:v".to_string()));
+    }
+
+    #[test]
+    fn test_cache_file_name() {
+        let stamp = FileStamp {
+            path: "/videos/a.mp4".to_string(),
+            size: 1000,
+            modified: None,
+        };
+        let name = cache_file_name(&stamp, 1.0, 320, ImageFormat::Png);
+        assert!(name.ends_with(".png"));
+        assert_eq!(name, cache_file_name(&stamp, 1.0004, 320, ImageFormat::Png));
+        assert_ne!(name, cache_file_name(&stamp, 2.0, 320, ImageFormat::Png));
+        assert_ne!(name, cache_file_name(&stamp, 1.0, 640, ImageFormat::Png));
+
+        let changed = FileStamp {
+            size: 2000,
+            ..stamp.clone()
+        };
+        assert_ne!(name, cache_file_name(&changed, 1.0, 320, ImageFormat::Png));
+    }
+
+    #[test]
+    fn test_poster_timestamp() {
+        let mut info = VideoInfo {
+            duration_secs: 30.0,
+            ..Default::default()
+        };
+        assert_eq!(poster_timestamp(&info), 3.0);
+        info.duration_secs = 3600.0;
+        assert_eq!(poster_timestamp(&info), 10.0);
+        info.duration_secs = 0.0;
+        assert_eq!(poster_timestamp(&info), 0.0);
+    }
+
+    #[test]
+    fn test_extract_frame_rejects_bad_input() {
+        assert!(extract_frame("/nonexistent/clip.mp4", 1.0, 320, ImageFormat::Png).is_err());
+        assert!(extract_frame("/nonexistent/clip.mp4", -1.0, 320, ImageFormat::Png).is_err());
+        assert!(extract_frame("/nonexistent/clip.mp4", 1.0, 0, ImageFormat::Png).is_err());
+    }
+
+    #[test]
+    fn test_prune_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.jpg", "b.png", "c.jpg", "notes.txt", "d.part.jpg"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        assert_eq!(prune_cache(dir.path(), 2), 1);
+        assert_eq!(prune_cache(dir.path(), 2), 0);
+        assert!(dir.path().join("notes.txt").exists());
+        assert!(dir.path().join("d.part.jpg").exists());
+    }
+}
@@ -0,0 +1,711 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Output shapes offered as presets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AspectRatio {
+    /// Landscape video
+    #[serde(rename = "16:9")]
+    Landscape,
+    /// Stories, reels and shorts
+    #[serde(rename = "9:16")]
+    Vertical,
+    #[serde(rename = "1:1")]
+    Square,
+    /// Portrait feed posts
+    #[serde(rename = "4:5")]
+    Portrait,
+}
+
+impl AspectRatio {
+    /// Width and height parts of the ratio
+    pub fn ratio(self) -> (u32, u32) {
+        match self {
+            AspectRatio::Landscape => (16, 9),
+            AspectRatio::Vertical => (9, 16),
+            AspectRatio::Square => (1, 1),
+            AspectRatio::Portrait => (4, 5),
+        }
+    }
+}
+
+impl FromStr for AspectRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().replace(['x', '/'], ":").as_str() {
+            "16:9" => Ok(AspectRatio::Landscape),
+            "9:16" => Ok(AspectRatio::Vertical),
+            "1:1" => Ok(AspectRatio::Square),
+            "4:5" => Ok(AspectRatio::Portrait),
+            other => Err(format!(
+                "Unknown aspect ratio '{}' (expected 16:9, 9:16, 1:1 or 4:5)",
+                other
+            )),
+        }
+    }
+}
+
+/// How the picture is made to match another shape
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// Cut off what sticks out, centered on the focus point
+    #[default]
+    Crop,
+    /// Keep everything and fill the rest with the pad color
+    Pad,
+    /// Keep everything over a blurred, enlarged copy of the picture
+    Blur,
+}
+
+impl FromStr for Fit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "crop" | "fill" => Ok(Fit::Crop),
+            "pad" | "letterbox" => Ok(Fit::Pad),
+            "blur" | "blurred" => Ok(Fit::Blur),
+            other => Err(format!(
+                "Unknown fit '{}' (expected crop, pad or blur)",
+                other
+            )),
+        }
+    }
+}
+
+/// Rectangle of the upright source, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Parses FFmpeg's `width:height[:x:y]`; without a position the rectangle
+/// is centered, which is stored as `u32::MAX`
+impl FromStr for CropRect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values: Vec<u32> = s
+            .split(':')
+            .map(|part| part.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("Invalid crop '{}' (expected width:height[:x:y])", s))?;
+        match values[..] {
+            [width, height] => Ok(CropRect {
+                x: CENTERED,
+                y: CENTERED,
+                width,
+                height,
+            }),
+            [width, height, x, y] => Ok(CropRect {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(format!(
+                "Invalid crop '{}' (expected width:height[:x:y])",
+                s
+            )),
+        }
+    }
+}
+
+/// Blur strength of filled backgrounds: the larger side of the frame
+/// divided by this
+const BLUR_SIGMA_DIVISOR: u32 = 48;
+
+/// Crop position meaning "centered"
+pub const CENTERED: u32 = u32::MAX;
+
+/// Point of the picture to keep in view, as fractions of its width and
+/// height from the top left corner
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Focus {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Focus {
+    pub const CENTER: Focus = Focus { x: 0.5, y: 0.5 };
+}
+
+/// Parses `x,y`, e.g. `0.5,0.3` for a point above the middle
+impl FromStr for Focus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid focus point '{}' (expected x,y from 0 to 1)", s);
+        let (x, y) = s.split_once(',').ok_or_else(invalid)?;
+        let focus = Focus {
+            x: x.trim().parse().map_err(|_| invalid())?,
+            y: y.trim().parse().map_err(|_| invalid())?,
+        };
+        if !(0.0..=1.0).contains(&focus.x) || !(0.0..=1.0).contains(&focus.y) {
+            return Err(invalid());
+        }
+        Ok(focus)
+    }
+}
+
+/// Crop, reshape and resize settings, applied in that order to the upright
+/// source
+///
+/// Every size that comes out is even, since H.264 and H.265 with 4:2:0
+/// chroma can't encode odd ones.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Geometry {
+    /// Part of the source to keep, before anything else
+    pub crop: Option<CropRect>,
+    /// Shape to convert to, by cropping, padding or filling with a blurred
+    /// background according to `fit`
+    pub aspect: Option<AspectRatio>,
+    pub fit: Fit,
+    /// Where shape changes keep the picture: crops center on this point as
+    /// far as the picture allows, and blurred fills move it towards the
+    /// middle of the frame [default: the center]
+    pub focus: Option<Focus>,
+    /// Scale to this width, or to fit within it when `height` is also set,
+    /// keeping the aspect ratio
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// With both `width` and `height`, letterbox to exactly that size, with
+    /// a blurred background when `fit` is `Blur`
+    pub pad: bool,
+    /// Color of padded areas, as FFmpeg understands it [default: black]
+    pub pad_color: Option<String>,
+}
+
+/// Filters that apply a geometry to one source size, and the size they
+/// produce
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeometryPlan {
+    pub filters: Vec<String>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Geometry {
+    /// Check the settings without a source
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(crop) = &self.crop {
+            if crop.width < 2 || crop.height < 2 {
+                return Err("Crop must be at least 2x2 pixels".to_string());
+            }
+        }
+        if self.width.is_some_and(|w| w < 2) || self.height.is_some_and(|h| h < 2) {
+            return Err("Target size must be at least 2 pixels".to_string());
+        }
+        if let Some(focus) = &self.focus {
+            if !(0.0..=1.0).contains(&focus.x) || !(0.0..=1.0).contains(&focus.y) {
+                return Err("Focus point must be between 0 and 1".to_string());
+            }
+        }
+        if self.pad && (self.width.is_none() || self.height.is_none()) {
+            return Err("Letterboxing needs both a target width and height".to_string());
+        }
+        if let Some(color) = &self.pad_color {
+            if color.is_empty()
+                || !color
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '#' || c == '@' || c == '.')
+            {
+                return Err(format!("Invalid pad color '{}'", color));
+            }
+        }
+        Ok(())
+    }
+
+    /// Filters turning an upright `width`x`height` picture into this
+    /// geometry
+    ///
+    /// A crop reaching past the picture is clipped to it.
+    pub fn plan(&self, width: u32, height: u32) -> GeometryPlan {
+        let mut plan = GeometryPlan {
+            filters: Vec::new(),
+            width,
+            height,
+        };
+        let color = self.pad_color.as_deref().unwrap_or("black");
+        let focus = self.focus.unwrap_or(Focus::CENTER);
+        // A blurred fill is drawn once, at the final size
+        let mut fill = None;
+
+        if let Some(crop) = &self.crop {
+            let crop_width = even(crop.width.min(width));
+            let crop_height = even(crop.height.min(height));
+            let x = position(crop.x, width, crop_width);
+            let y = position(crop.y, height, crop_height);
+            plan.filters
+                .push(format!("crop={}:{}:{}:{}", crop_width, crop_height, x, y));
+            (plan.width, plan.height) = (crop_width, crop_height);
+        }
+
+        if let Some(aspect) = self.aspect {
+            let (rw, rh) = aspect.ratio();
+            let (w, h) = (plan.width as u64, plan.height as u64);
+            let wider = w * rh as u64 > h * rw as u64;
+            let (target_width, target_height) = match (self.fit, wider) {
+                (Fit::Crop, true) => (even((h * rw as u64 / rh as u64) as u32), even(h as u32)),
+                (Fit::Crop, false) => (even(w as u32), even((w * rh as u64 / rw as u64) as u32)),
+                (_, true) => (even_up(w as u32), even_up(div_up(w * rh as u64, rw))),
+                (_, false) => (even_up(div_up(h * rw as u64, rh)), even_up(h as u32)),
+            };
+            if (target_width, target_height) != (plan.width, plan.height) {
+                match self.fit {
+                    // Crop centers by default
+                    Fit::Crop if self.focus.is_none() => plan
+                        .filters
+                        .push(format!("crop={}:{}", target_width, target_height)),
+                    Fit::Crop => plan.filters.push(format!(
+                        "crop={}:{}:{}:{}",
+                        target_width,
+                        target_height,
+                        around(focus.x, plan.width, target_width),
+                        around(focus.y, plan.height, target_height)
+                    )),
+                    Fit::Pad => plan
+                        .filters
+                        .push(pad_filter(target_width, target_height, color)),
+                    Fit::Blur => fill = Some((target_width, target_height)),
+                }
+                if fill.is_none() {
+                    (plan.width, plan.height) = (target_width, target_height);
+                }
+            }
+        }
+
+        let (width, height) = fill.unwrap_or((plan.width, plan.height));
+        let scaled = match (self.width, self.height) {
+            (Some(max_width), Some(max_height)) => {
+                let factor =
+                    (max_width as f64 / width as f64).min(max_height as f64 / height as f64);
+                Some((
+                    snap(even_round(width as f64 * factor), even(max_width)),
+                    snap(even_round(height as f64 * factor), even(max_height)),
+                ))
+            }
+            (Some(target), None) => Some((
+                even(target),
+                even_round(height as f64 * target as f64 / width as f64),
+            )),
+            (None, Some(target)) => Some((
+                even_round(width as f64 * target as f64 / height as f64),
+                even(target),
+            )),
+            (None, None) => None,
+        };
+        let (width, height) = scaled.unwrap_or((width, height));
+        if fill.is_some() {
+            plan.filters
+                .push(blur_fill(plan.width, plan.height, width, height, focus, 0));
+            (plan.width, plan.height) = (width, height);
+        } else if (width, height) != (plan.width, plan.height) {
+            plan.filters.push(format!("scale={}:{}", width, height));
+            (plan.width, plan.height) = (width, height);
+        }
+
+        if let (true, Some(box_width), Some(box_height)) = (self.pad, self.width, self.height) {
+            let (box_width, box_height) = (even(box_width), even(box_height));
+            if (box_width, box_height) != (plan.width, plan.height) {
+                plan.filters.push(match self.fit {
+                    Fit::Blur => {
+                        blur_fill(plan.width, plan.height, box_width, box_height, focus, 1)
+                    }
+                    _ => pad_filter(box_width, box_height, color),
+                });
+                (plan.width, plan.height) = (box_width, box_height);
+            }
+        }
+
+        // Odd sources lose their last row or column
+        if plan.width % 2 == 1 || plan.height % 2 == 1 {
+            let (even_width, even_height) = (even(plan.width), even(plan.height));
+            plan.filters
+                .push(format!("crop={}:{}:0:0", even_width, even_height));
+            (plan.width, plan.height) = (even_width, even_height);
+        }
+        plan
+    }
+}
+
+/// Centered padding up to `width`x`height`
+fn pad_filter(width: u32, height: u32, color: &str) -> String {
+    format!(
+        "pad={}:{}:(ow-iw)/2:(oh-ih)/2:color={}",
+        width, height, color
+    )
+}
+
+/// Place a `width`x`height` picture, scaled to fit, over a blurred copy
+/// scaled to cover a `canvas_width`x`canvas_height` frame
+///
+/// The copy is framed around `focus`, and the picture is moved along the
+/// free side so that `focus` comes as close to the middle as it can.
+/// `index` keeps the pad labels of fills in the same graph apart.
+fn blur_fill(
+    width: u32,
+    height: u32,
+    canvas_width: u32,
+    canvas_height: u32,
+    focus: Focus,
+    index: usize,
+) -> String {
+    let (w, h) = (width as f64, height as f64);
+    let (cw, ch) = (canvas_width as f64, canvas_height as f64);
+
+    let cover = (cw / w).max(ch / h);
+    let background_width = even_up((w * cover).ceil() as u32).max(canvas_width);
+    let background_height = even_up((h * cover).ceil() as u32).max(canvas_height);
+
+    let fit = (cw / w).min(ch / h);
+    let picture_width = snap(even_round(w * fit), canvas_width);
+    let picture_height = snap(even_round(h * fit), canvas_height);
+    let offset = |canvas: u32, size: u32, point: f64| {
+        let centered = canvas as f64 / 2.0 - point * size as f64;
+        (centered.round().max(0.0) as u32).min(canvas - size)
+    };
+
+    format!(
+        "split[fill{i}_bg][fill{i}_fg];\
+         [fill{i}_bg]scale={}:{},crop={}:{}:{}:{},gblur=sigma={}[fill{i}_blur];\
+         [fill{i}_fg]scale={}:{}[fill{i}_sharp];\
+         [fill{i}_blur][fill{i}_sharp]overlay={}:{},setsar=1",
+        background_width,
+        background_height,
+        canvas_width,
+        canvas_height,
+        around(focus.x, background_width, canvas_width),
+        around(focus.y, background_height, canvas_height),
+        (canvas_width.max(canvas_height) / BLUR_SIGMA_DIVISOR).max(1),
+        picture_width,
+        picture_height,
+        offset(canvas_width, picture_width, focus.x),
+        offset(canvas_height, picture_height, focus.y),
+        i = index
+    )
+}
+
+/// Offset of a window of `size` out of `total` centered on the fraction
+/// `point` of it, moved back inside when it would reach past an edge
+fn around(point: f64, total: u32, size: u32) -> u32 {
+    let centered = point * total as f64 - size as f64 / 2.0;
+    (centered.round().max(0.0) as u32).min(total.saturating_sub(size))
+}
+
+/// Offset of a crop of `size` out of `total`, centered for `CENTERED` and
+/// moved back inside the picture when it reaches past it
+fn position(requested: u32, total: u32, size: u32) -> u32 {
+    let max = total.saturating_sub(size);
+    if requested == CENTERED {
+        max / 2
+    } else {
+        requested.min(max)
+    }
+}
+
+/// `size` limited to `limit`, and stretched to it when it falls short by
+/// no more than the even rounding of an earlier crop
+fn snap(size: u32, limit: u32) -> u32 {
+    if size + 2 >= limit {
+        limit
+    } else {
+        size
+    }
+}
+
+/// Largest even value not above `value`, and at least 2
+fn even(value: u32) -> u32 {
+    (value / 2 * 2).max(2)
+}
+
+/// Smallest even value not below `value`
+fn even_up(value: u32) -> u32 {
+    value.div_ceil(2) * 2
+}
+
+/// Nearest even value, and at least 2
+fn even_round(value: f64) -> u32 {
+    ((value / 2.0).round() as u32 * 2).max(2)
+}
+
+fn div_up(numerator: u64, denominator: u32) -> u32 {
+    numerator.div_ceil(denominator as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(geometry: &Geometry, width: u32, height: u32) -> (Vec<String>, u32, u32) {
+        let plan = geometry.plan(width, height);
+        (plan.filters, plan.width, plan.height)
+    }
+
+    #[test]
+    fn test_parsing() {
+        assert_eq!("9:16".parse::<AspectRatio>(), Ok(AspectRatio::Vertical));
+        assert_eq!("4x5".parse::<AspectRatio>(), Ok(AspectRatio::Portrait));
+        assert!("3:2".parse::<AspectRatio>().is_err());
+        assert_eq!(
+            serde_json::to_string(&AspectRatio::Square).unwrap(),
+            "\"1:1\""
+        );
+        assert_eq!("letterbox".parse::<Fit>(), Ok(Fit::Pad));
+        assert_eq!("blur".parse::<Fit>(), Ok(Fit::Blur));
+        assert_eq!("0.25, 0.3".parse::<Focus>(), Ok(Focus { x: 0.25, y: 0.3 }));
+        assert!("0.5".parse::<Focus>().is_err());
+        assert!("1.5,0.5".parse::<Focus>().is_err());
+
+        let crop: CropRect = "640:480:10:20".parse().unwrap();
+        assert_eq!(
+            (crop.x, crop.y, crop.width, crop.height),
+            (10, 20, 640, 480)
+        );
+        let centered: CropRect = "640:480".parse().unwrap();
+        assert_eq!(centered.x, CENTERED);
+        assert!("640".parse::<CropRect>().is_err());
+        assert!("a:b".parse::<CropRect>().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Geometry::default().validate().is_ok());
+        for geometry in [
+            Geometry {
+                crop: Some("1:100".parse().unwrap()),
+                ..Default::default()
+            },
+            Geometry {
+                width: Some(1280),
+                pad: true,
+                ..Default::default()
+            },
+            Geometry {
+                pad_color: Some("black;rm".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(geometry.validate().is_err(), "{:?}", geometry);
+        }
+    }
+
+    #[test]
+    fn test_no_geometry() {
+        assert_eq!(plan(&Geometry::default(), 1920, 1080), (vec![], 1920, 1080));
+        // Odd sizes are trimmed to even ones
+        assert_eq!(
+            plan(&Geometry::default(), 1921, 1081),
+            (vec!["crop=1920:1080:0:0".to_string()], 1920, 1080)
+        );
+    }
+
+    #[test]
+    fn test_crop() {
+        let geometry = Geometry {
+            crop: Some("641:481:1500:10".parse().unwrap()),
+            ..Default::default()
+        };
+        // Even size, moved back inside the picture
+        assert_eq!(
+            plan(&geometry, 1920, 1080),
+            (vec!["crop=640:480:1280:10".to_string()], 640, 480)
+        );
+
+        let centered = Geometry {
+            crop: Some("4000:480".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            plan(&centered, 1920, 1080),
+            (vec!["crop=1920:480:0:300".to_string()], 1920, 480)
+        );
+    }
+
+    #[test]
+    fn test_aspect_presets() {
+        let crop = |aspect: AspectRatio| Geometry {
+            aspect: Some(aspect),
+            ..Default::default()
+        };
+        assert_eq!(
+            plan(&crop(AspectRatio::Vertical), 1920, 1080),
+            (vec!["crop=606:1080".to_string()], 606, 1080)
+        );
+        assert_eq!(
+            plan(&crop(AspectRatio::Square), 1920, 1080),
+            (vec!["crop=1080:1080".to_string()], 1080, 1080)
+        );
+        assert_eq!(
+            plan(&crop(AspectRatio::Portrait), 1080, 1920),
+            (vec!["crop=1080:1350".to_string()], 1080, 1350)
+        );
+        // Already the right shape
+        assert_eq!(
+            plan(&crop(AspectRatio::Landscape), 1920, 1080),
+            (vec![], 1920, 1080)
+        );
+
+        let letterbox = Geometry {
+            aspect: Some(AspectRatio::Landscape),
+            fit: Fit::Pad,
+            ..Default::default()
+        };
+        assert_eq!(
+            plan(&letterbox, 1080, 1080),
+            (
+                vec!["pad=1920:1080:(ow-iw)/2:(oh-ih)/2:color=black".to_string()],
+                1920,
+                1080
+            )
+        );
+    }
+
+    #[test]
+    fn test_target_size() {
+        let width = Geometry {
+            width: Some(1281),
+            ..Default::default()
+        };
+        assert_eq!(
+            plan(&width, 1920, 1080),
+            (vec!["scale=1280:720".to_string()], 1280, 720)
+        );
+
+        // Fits inside the box, keeping the shape
+        let fit = Geometry {
+            width: Some(1080),
+            height: Some(1920),
+            ..Default::default()
+        };
+        assert_eq!(
+            plan(&fit, 1920, 1080),
+            (vec!["scale=1080:608".to_string()], 1080, 608)
+        );
+
+        let boxed = Geometry {
+            pad: true,
+            pad_color: Some("white".to_string()),
+            ..fit.clone()
+        };
+        assert_eq!(
+            plan(&boxed, 1920, 1080),
+            (
+                vec![
+                    "scale=1080:608".to_string(),
+                    "pad=1080:1920:(ow-iw)/2:(oh-ih)/2:color=white".to_string()
+                ],
+                1080,
+                1920
+            )
+        );
+
+        // Crop to the shape first, then scale: a full-frame vertical video
+        let vertical = Geometry {
+            aspect: Some(AspectRatio::Vertical),
+            ..fit
+        };
+        assert_eq!(
+            plan(&vertical, 3840, 2160),
+            (
+                vec!["crop=1214:2160".to_string(), "scale=1080:1920".to_string()],
+                1080,
+                1920
+            )
+        );
+    }
+
+    #[test]
+    fn test_focus_crop() {
+        let geometry = Geometry {
+            aspect: Some(AspectRatio::Square),
+            focus: Some(Focus { x: 0.6, y: 0.5 }),
+            ..Default::default()
+        };
+        assert_eq!(
+            plan(&geometry, 1920, 1080),
+            (vec!["crop=1080:1080:612:0".to_string()], 1080, 1080)
+        );
+        // Kept inside the picture near an edge
+        let edge = Geometry {
+            focus: Some(Focus { x: 1.0, y: 0.5 }),
+            ..geometry
+        };
+        assert_eq!(plan(&edge, 1920, 1080).0, ["crop=1080:1080:840:0"]);
+    }
+
+    #[test]
+    fn test_blur_fill() {
+        let vertical = Geometry {
+            aspect: Some(AspectRatio::Vertical),
+            fit: Fit::Blur,
+            width: Some(1080),
+            height: Some(1920),
+            ..Default::default()
+        };
+        // Drawn once at the final size, with the picture in the middle
+        assert_eq!(
+            plan(&vertical, 1920, 1080),
+            (
+                vec!["split[fill0_bg][fill0_fg];\
+                     [fill0_bg]scale=3414:1920,crop=1080:1920:1167:0,gblur=sigma=40[fill0_blur];\
+                     [fill0_fg]scale=1080:608[fill0_sharp];\
+                     [fill0_blur][fill0_sharp]overlay=0:656,setsar=1"
+                    .to_string()],
+                1080,
+                1920
+            )
+        );
+
+        // The background follows the focus point, and the picture moves
+        // down to bring it towards the middle
+        let focused = Geometry {
+            focus: Some(Focus { x: 0.25, y: 0.3 }),
+            ..vertical.clone()
+        };
+        let filters = plan(&focused, 1920, 1080).0;
+        assert!(
+            filters[0].contains("crop=1080:1920:314:0"),
+            "{}",
+            filters[0]
+        );
+        assert!(filters[0].contains("overlay=0:778"), "{}", filters[0]);
+
+        // Letterboxing to a box can fill with blur too
+        let boxed = Geometry {
+            aspect: None,
+            pad: true,
+            ..vertical
+        };
+        let (filters, width, height) = plan(&boxed, 1920, 1080);
+        assert_eq!((width, height), (1080, 1920));
+        assert_eq!(filters[0], "scale=1080:608");
+        assert!(
+            filters[1].ends_with("overlay=0:656,setsar=1"),
+            "{}",
+            filters[1]
+        );
+
+        // A fill to the aspect ratio followed by a fill to a box of another
+        // shape uses its own labels
+        let square_box = Geometry {
+            aspect: Some(AspectRatio::Vertical),
+            width: Some(1080),
+            height: Some(1080),
+            ..boxed
+        };
+        let (filters, width, height) = plan(&square_box, 1920, 1080);
+        assert_eq!((width, height), (1080, 1080));
+        assert_eq!(filters.len(), 2);
+        let graph = filters.join(",");
+        for label in ["[fill0_bg]", "[fill1_bg]", "[fill0_sharp]", "[fill1_sharp]"] {
+            assert_eq!(graph.matches(label).count(), 2, "{}", graph);
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use crate::video::{get_info_many, is_supported_format};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Filters applied while importing folders
+///
+/// Glob patterns are matched against both the path relative to the imported
+/// folder and the bare file name, case-insensitively.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub min_duration_secs: Option<f64>,
+    pub max_duration_secs: Option<f64>,
+    pub min_size_bytes: Option<u64>,
+    pub max_size_bytes: Option<u64>,
+}
+
+impl ImportFilter {
+    fn needs_probe(&self) -> bool {
+        self.min_duration_secs.is_some() || self.max_duration_secs.is_some()
+    }
+}
+
+/// A file that was found but not imported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+/// Result of a folder import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderImportResult {
+    pub files: Vec<String>,
+    pub count: usize,
+    pub skipped: Vec<SkippedFile>,
+    pub skipped_count: usize,
+}
+
+/// Walk directories recursively and collect supported videos that pass the filter
+///
+/// Matches are sorted and deduplicated, so overlapping folders are fine: a
+/// file found under several of them is imported once, and only listed as
+/// skipped when no folder imported it. The walk runs on a blocking thread.
+pub async fn import_folders(
+    directories: &[String],
+    filter: &ImportFilter,
+) -> Result<FolderImportResult, String> {
+    let include = compile_patterns(&filter.include)?;
+    let exclude = compile_patterns(&filter.exclude)?;
+
+    let walk_directories = directories.to_vec();
+    let walk_filter = filter.clone();
+    let (candidates, mut skipped) = tokio::task::spawn_blocking(move || {
+        walk_folders(&walk_directories, &include, &exclude, &walk_filter)
+    })
+    .await
+    .map_err(|e| format!("Folder import failed: {}", e))??;
+
+    let mut files: Vec<String> = candidates.into_iter().collect();
+
+    if filter.needs_probe() {
+        let infos = get_info_many(files, |_, _| {}).await;
+        files = Vec::new();
+        for info in infos {
+            match check_duration(&info.path, info.valid, info.duration_secs, filter) {
+                Ok(()) => files.push(info.path),
+                Err(reason) => skipped.push(SkippedFile {
+                    path: info.path,
+                    reason: info.error.unwrap_or(reason),
+                }),
+            }
+        }
+    }
+
+    skipped.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(FolderImportResult {
+        count: files.len(),
+        files,
+        skipped_count: skipped.len(),
+        skipped,
+    })
+}
+
+/// Files under `directories` that pass the patterns and size limits, and
+/// the ones that don't, each listed once
+fn walk_folders(
+    directories: &[String],
+    include: &[Pattern],
+    exclude: &[Pattern],
+    filter: &ImportFilter,
+) -> Result<(BTreeSet<String>, Vec<SkippedFile>), String> {
+    let mut candidates = BTreeSet::new();
+    let mut skipped = Vec::new();
+
+    for directory in directories {
+        let root = Path::new(directory);
+        if !root.is_dir() {
+            return Err(format!("'{}' is not a directory", directory));
+        }
+
+        for entry in WalkDir::new(root).follow_links(true) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    skipped.push(SkippedFile {
+                        path: e
+                            .path()
+                            .map(|p| p.to_string_lossy().to_string())
+                            .unwrap_or_else(|| directory.clone()),
+                        reason: format!("Cannot read: {}", e),
+                    });
+                    continue;
+                }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let path = entry.path().to_string_lossy().to_string();
+            let relative = entry
+                .path()
+                .strip_prefix(root)
+                .unwrap_or(entry.path())
+                .to_string_lossy()
+                .to_string();
+            let filename = entry.file_name().to_string_lossy().to_string();
+
+            let reason = if !is_supported_format(&path) {
+                Some("Unsupported format".to_string())
+            } else if !include.is_empty() && !matches_any(include, &relative, &filename) {
+                Some("Not matched by include patterns".to_string())
+            } else if matches_any(exclude, &relative, &filename) {
+                Some("Matched an exclude pattern".to_string())
+            } else {
+                entry
+                    .metadata()
+                    .map_err(|e| format!("Cannot read metadata: {}", e))
+                    .and_then(|m| check_size(m.len(), filter))
+                    .err()
+            };
+
+            match reason {
+                Some(reason) => skipped.push(SkippedFile { path, reason }),
+                None => {
+                    candidates.insert(path);
+                }
+            }
+        }
+    }
+
+    // Nested folders see the same file under different relative paths
+    skipped.retain(|s| !candidates.contains(&s.path));
+    skipped.sort_by(|a, b| a.path.cmp(&b.path));
+    skipped.dedup_by(|a, b| a.path == b.path);
+    Ok((candidates, skipped))
+}
+
+fn compile_patterns(patterns: &[String]) -> Result<Vec<Pattern>, String> {
+    patterns
+        .iter()
+        .map(|p| Pattern::new(p).map_err(|e| format!("Invalid pattern '{}': {}", p, e)))
+        .collect()
+}
+
+fn matches_any(patterns: &[Pattern], relative: &str, filename: &str) -> bool {
+    let options = MatchOptions {
+        case_sensitive: false,
+        require_literal_separator: false,
+        require_literal_leading_dot: false,
+    };
+    // Normalize Windows separators so patterns like "day1/*.mp4" work everywhere
+    let relative = relative.replace('\\', "/");
+    patterns
+        .iter()
+        .any(|p| p.matches_with(&relative, options) || p.matches_with(filename, options))
+}
+
+fn check_size(size: u64, filter: &ImportFilter) -> Result<(), String> {
+    if let Some(min) = filter.min_size_bytes {
+        if size < min {
+            return Err(format!("Smaller than {} bytes", min));
+        }
+    }
+    if let Some(max) = filter.max_size_bytes {
+        if size > max {
+            return Err(format!("Larger than {} bytes", max));
+        }
+    }
+    Ok(())
+}
+
+fn check_duration(
+    path: &str,
+    valid: bool,
+    duration_secs: f64,
+    filter: &ImportFilter,
+) -> Result<(), String> {
+    if !valid {
+        return Err(format!("Could not read duration of '{}'", path));
+    }
+    if let Some(min) = filter.min_duration_secs {
+        if duration_secs < min {
+            return Err(format!("Shorter than {}s", min));
+        }
+    }
+    if let Some(max) = filter.max_duration_secs {
+        if duration_secs > max {
+            return Err(format!("Longer than {}s", max));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("day1/cam1")).unwrap();
+        std::fs::create_dir_all(root.join("day2")).unwrap();
+        std::fs::write(root.join("day1/cam1/b.mp4"), vec![0u8; 100]).unwrap();
+        std::fs::write(root.join("day1/a.MOV"), vec![0u8; 10]).unwrap();
+        std::fs::write(root.join("day1/notes.txt"), b"notes").unwrap();
+        std::fs::write(root.join("day2/c.mkv"), vec![0u8; 1000]).unwrap();
+        std::fs::write(root.join("day2/c_preview.mp4"), vec![0u8; 50]).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_import_folders_recursive_sorted() {
+        let tree = make_tree();
+        let root = tree.path();
+        let dirs = vec![root.to_string_lossy().to_string()];
+        let result = import_folders(&dirs, &ImportFilter::default())
+            .await
+            .unwrap();
+
+        assert_eq!(result.count, 4);
+        let mut sorted = result.files.clone();
+        sorted.sort();
+        assert_eq!(result.files, sorted);
+        assert_eq!(result.skipped_count, 1);
+        assert!(result.skipped[0].path.ends_with("notes.txt"));
+        assert_eq!(result.skipped[0].reason, "Unsupported format");
+    }
+
+    #[tokio::test]
+    async fn test_import_folders_patterns_and_size() {
+        let tree = make_tree();
+        let root = tree.path();
+        let dirs = vec![root.to_string_lossy().to_string()];
+        let filter = ImportFilter {
+            include: vec!["day*/*".to_string()],
+            exclude: vec!["*_preview*".to_string()],
+            min_size_bytes: Some(20),
+            ..Default::default()
+        };
+        let result = import_folders(&dirs, &filter).await.unwrap();
+
+        assert_eq!(result.count, 2);
+        assert!(result.files.iter().any(|f| f.ends_with("b.mp4")));
+        assert!(result.files.iter().any(|f| f.ends_with("c.mkv")));
+        assert!(result
+            .skipped
+            .iter()
+            .any(|s| s.path.ends_with("c_preview.mp4") && s.reason.contains("exclude")));
+        assert!(result
+            .skipped
+            .iter()
+            .any(|s| s.path.ends_with("a.MOV") && s.reason.contains("Smaller")));
+    }
+
+    #[tokio::test]
+    async fn test_import_folders_overlapping_directories_deduplicated() {
+        let tree = make_tree();
+        let root = tree.path();
+        let dirs = vec![
+            root.to_string_lossy().to_string(),
+            root.join("day2").to_string_lossy().to_string(),
+        ];
+        let result = import_folders(&dirs, &ImportFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(result.count, 4);
+
+        // The unsupported file under the outer folder is listed once
+        let nested = vec![
+            root.to_string_lossy().to_string(),
+            root.join("day1").to_string_lossy().to_string(),
+        ];
+        let result = import_folders(&nested, &ImportFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(result.skipped_count, 1);
+
+        // A file imported through one folder isn't also skipped through
+        // the other, where its relative path doesn't match
+        let filter = ImportFilter {
+            include: vec!["cam1/*".to_string()],
+            ..Default::default()
+        };
+        let result = import_folders(&nested, &filter).await.unwrap();
+        assert_eq!(result.count, 1);
+        assert!(result.files[0].ends_with("b.mp4"));
+        assert!(!result.skipped.iter().any(|s| s.path.ends_with("b.mp4")));
+        assert_eq!(result.skipped_count, result.skipped.len());
+    }
+
+    #[tokio::test]
+    async fn test_import_folders_rejects_missing_directory() {
+        let dirs = vec!["/nonexistent/timelapse/folder".to_string()];
+        assert!(import_folders(&dirs, &ImportFilter::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_folders_invalid_pattern() {
+        let filter = ImportFilter {
+            include: vec!["[".to_string()],
+            ..Default::default()
+        };
+        let dirs = vec![std::env::temp_dir().to_string_lossy().to_string()];
+        let err = import_folders(&dirs, &filter).await.unwrap_err();
+        assert!(err.contains("Invalid pattern"));
+    }
+
+    #[test]
+    fn test_check_duration_bounds() {
+        let filter = ImportFilter {
+            min_duration_secs: Some(10.0),
+            max_duration_secs: Some(60.0),
+            ..Default::default()
+        };
+        assert!(check_duration("a.mp4", true, 30.0, &filter).is_ok());
+        assert!(check_duration("a.mp4", true, 5.0, &filter).is_err());
+        assert!(check_duration("a.mp4", true, 61.0, &filter).is_err());
+        assert!(check_duration("a.mp4", false, 30.0, &filter).is_err());
+    }
+}
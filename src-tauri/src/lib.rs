@@ -0,0 +1,81 @@
+//! Timelapse generation with FFmpeg
+//!
+//! This crate holds everything the Timelapse Creator app and CLI do to turn
+//! videos into timelapses, without any dependency on Tauri or a webview:
+//!
+//! - [`video`] probes files with ffprobe and describes them as [`VideoInfo`]
+//! - [`frames`] extracts still frames and poster thumbnails, and [`sheets`]
+//!   tiles them into contact sheets and sprite sheets
+//! - [`import`] collects videos from folders
+//! - [`manifest`] turns JSON or CSV clip lists into conversion jobs
+//! - [`convert`] runs the FFmpeg pipeline for a [`ConversionRequest`] and
+//!   reports progress through a callback
+//! - [`formats`] describes the output formats: MP4, animated GIF, APNG and
+//!   WebP, and image sequences, whose folders [`sequence`] manages
+//! - [`geometry`] crops, reshapes and resizes outputs to even sizes
+//! - [`renditions`] lets one job make several outputs from a single decode
+//! - [`preview`] renders quick low-resolution previews of the same pipeline
+//! - [`preflight`] checks a batch before it starts and [`estimate`]
+//!   predicts output size, duration and encode time
+//! - [`report`] writes JSON and CSV reports of finished batches
+//! - [`presets`] stores named conversion settings
+//! - [`project`] saves and restores whole sessions
+//! - [`settings`] stores application settings and recent files
+//!
+//! Build with `default-features = false` to embed it in other services.
+//!
+//! ```no_run
+//! use timelapse_creator::{convert_batch, ConversionRequest};
+//!
+//! # async fn run() {
+//! let request = ConversionRequest {
+//!     files: vec!["clip.mp4".to_string()],
+//!     speed_multiplier: 100,
+//!     ..Default::default()
+//! };
+//! let result = convert_batch(&request, |event| {
+//!     println!("{}: {}", event.filename, event.status);
+//! })
+//! .await;
+//! assert!(result.success);
+//! # }
+//! ```
+
+pub mod convert;
+pub mod estimate;
+pub mod formats;
+pub mod frames;
+pub mod geometry;
+pub mod import;
+pub mod manifest;
+pub mod preflight;
+pub mod presets;
+pub mod preview;
+pub mod project;
+pub mod renditions;
+pub mod report;
+pub mod sequence;
+pub mod settings;
+pub mod sheets;
+pub mod storage;
+pub mod tools;
+pub mod video;
+
+pub use convert::{
+    convert_batch, convert_jobs, run_ffmpeg_conversion, ConversionRequest, ConversionResult,
+    ProgressEvent,
+};
+pub use formats::{AnimationOptions, OutputFormat};
+pub use frames::{extract_frame, ImageFormat};
+pub use geometry::{AspectRatio, Geometry};
+pub use import::{import_folders, FolderImportResult, ImportFilter};
+pub use manifest::{import_manifest, ManifestImport};
+pub use preflight::{preflight, PreflightReport};
+pub use presets::{Preset, PresetStore};
+pub use preview::{render_preview, Preview, PreviewOptions};
+pub use project::{load_project, save_project, Project};
+pub use renditions::Rendition;
+pub use report::{BatchReport, FileReport};
+pub use settings::{Settings, SettingsStore};
+pub use sheets::{contact_sheet, sprite_sheet, ContactSheet, SpriteSheet};
+pub use video::{get_info, get_info_cached, get_info_many, get_output_path, Provenance, VideoInfo};
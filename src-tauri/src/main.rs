@@ -1,18 +1,84 @@
 // Prevents additional console window on Windows in release
-#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+#![cfg_attr(
+    all(not(debug_assertions), feature = "tauri"),
+    windows_subsystem = "windows"
+)]
 
+#[cfg(feature = "cli")]
+mod cli;
+#[cfg(feature = "tauri")]
 mod commands;
-mod video;
 
-use commands::{convert_videos, get_video_info, open_file_explorer, select_videos};
+#[cfg(feature = "cli")]
+fn main() {
+    // `timelapse-creator convert ...` runs headless without creating a window
+    let args: Vec<String> = std::env::args().collect();
+
+    #[cfg(feature = "tauri")]
+    if !cli::is_cli_invocation(&args) {
+        run_app();
+        return;
+    }
+
+    std::process::exit(cli::run(args));
+}
+
+#[cfg(all(feature = "tauri", not(feature = "cli")))]
+fn main() {
+    run_app();
+}
 
+/// Library-only builds have nothing to run
+#[cfg(not(any(feature = "tauri", feature = "cli")))]
 fn main() {
+    eprintln!("Built without the app and the command-line interface");
+    std::process::exit(1);
+}
+
+#[cfg(feature = "tauri")]
+fn run_app() {
+    use commands::{
+        apply_saved_settings, convert_videos, count_video_frames, create_contact_sheet,
+        create_preset, create_sprite_sheet, delete_preset, estimate_conversion, export_presets,
+        extract_frame, find_project_relinks, get_settings, get_video_info, import_batch_manifest,
+        import_presets, import_video_folders, list_presets, load_project, open_file_explorer,
+        preflight, relink_project_source, render_preview, save_project, select_videos,
+        update_preset, update_settings,
+    };
+
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
+        .setup(|app| {
+            apply_saved_settings(app.handle());
+            // Previews from an earlier session are never shown again
+            timelapse_creator::preview::clear_previews();
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             select_videos,
+            import_video_folders,
+            import_batch_manifest,
             get_video_info,
+            count_video_frames,
+            extract_frame,
+            create_contact_sheet,
+            create_sprite_sheet,
             convert_videos,
+            list_presets,
+            create_preset,
+            update_preset,
+            delete_preset,
+            import_presets,
+            export_presets,
+            get_settings,
+            update_settings,
+            save_project,
+            load_project,
+            relink_project_source,
+            find_project_relinks,
+            estimate_conversion,
+            preflight,
+            render_preview,
             open_file_explorer
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,474 @@
+use crate::convert::ConversionRequest;
+use crate::formats::OutputFormat;
+use crate::video::{get_info_many, is_supported_format, VideoInfo};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// One clip of a manifest
+///
+/// Columns other than `path` are optional and fall back to the defaults
+/// passed to [`import_manifest`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ManifestRow {
+    #[serde(alias = "file")]
+    pub path: String,
+    #[serde(default)]
+    pub speed: Option<u32>,
+    #[serde(default)]
+    pub trim_start: Option<f64>,
+    #[serde(default)]
+    pub trim_end: Option<f64>,
+    #[serde(default)]
+    pub rotate: Option<u32>,
+    #[serde(default)]
+    pub output_dir: Option<String>,
+    #[serde(default)]
+    pub format: Option<OutputFormat>,
+}
+
+/// A manifest row that was left out of the job list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InvalidRow {
+    /// 1-based line of the row in the manifest file
+    pub line: usize,
+    pub path: Option<String>,
+    pub reason: String,
+}
+
+/// Result of importing a manifest
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ManifestImport {
+    /// One job per valid row, in manifest order
+    pub jobs: Vec<ConversionRequest>,
+    /// Probe results matching `jobs`
+    pub infos: Vec<VideoInfo>,
+    pub invalid: Vec<InvalidRow>,
+}
+
+/// Read a JSON or CSV manifest and turn its valid rows into conversion jobs
+///
+/// The format is chosen by extension (`.csv`, anything else is JSON). A JSON
+/// manifest is an array of row objects; a CSV manifest has a header row with
+/// the same names. Relative paths are resolved against the manifest's folder.
+/// `defaults` supplies settings for columns a row leaves empty; a zero
+/// `speed_multiplier` there means there is no default speed, so rows without
+/// a `speed` column are reported instead of failing validation.
+pub async fn import_manifest(
+    path: &Path,
+    defaults: &ConversionRequest,
+) -> Result<ManifestImport, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read manifest '{}': {}", path.display(), e))?;
+    let is_csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    let rows = if is_csv {
+        parse_csv(&text)?
+    } else {
+        parse_json(&text)?
+    };
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    let mut import = ManifestImport::default();
+    let mut candidates = Vec::new();
+    for (line, row) in rows {
+        match row.and_then(|row| to_job(row, base, defaults)) {
+            Ok(job) => candidates.push((line, job)),
+            Err((path, reason)) => import.invalid.push(InvalidRow { line, path, reason }),
+        }
+    }
+
+    let paths = candidates
+        .iter()
+        .map(|(_, job)| job.files[0].clone())
+        .collect();
+    let infos = get_info_many(paths, |_, _| {}).await;
+
+    for ((line, job), info) in candidates.into_iter().zip(infos) {
+        match check_probe(&job, &info) {
+            Ok(()) => {
+                import.jobs.push(job);
+                import.infos.push(info);
+            }
+            Err(reason) => import.invalid.push(InvalidRow {
+                line,
+                path: Some(info.path),
+                reason,
+            }),
+        }
+    }
+    import.invalid.sort_by_key(|row| row.line);
+    Ok(import)
+}
+
+type RowResult = Result<ManifestRow, (Option<String>, String)>;
+
+/// Build the job for a row, checking everything that doesn't need the file
+fn to_job(
+    row: ManifestRow,
+    base: &Path,
+    defaults: &ConversionRequest,
+) -> Result<ConversionRequest, (Option<String>, String)> {
+    let path = row.path.trim();
+    if path.is_empty() {
+        return Err((None, "Missing path".to_string()));
+    }
+    let path = base.join(path).to_string_lossy().to_string();
+    if !is_supported_format(&path) {
+        return Err((Some(path), "Unsupported format".to_string()));
+    }
+
+    let speed_multiplier = match (row.speed, defaults.speed_multiplier) {
+        (Some(speed), _) => speed,
+        (None, 0) => {
+            return Err((
+                Some(path),
+                "No speed in this row and no default speed given".to_string(),
+            ))
+        }
+        (None, speed) => speed,
+    };
+
+    let job = ConversionRequest {
+        files: vec![path.clone()],
+        speed_multiplier,
+        rotation_override: row.rotate.or(defaults.rotation_override),
+        output_dir: row.output_dir.or_else(|| defaults.output_dir.clone()),
+        trim_start_secs: row.trim_start.or(defaults.trim_start_secs),
+        trim_end_secs: row.trim_end.or(defaults.trim_end_secs),
+        output_format: row.format.unwrap_or(defaults.output_format),
+        animation: defaults.animation.clone(),
+        frame_timestamps: defaults.frame_timestamps,
+        max_height: defaults.max_height,
+        geometry: defaults.geometry.clone(),
+        video_codec: defaults.video_codec,
+        renditions: defaults.renditions.clone(),
+    };
+    job.validate().map_err(|e| (Some(path), e))?;
+    Ok(job)
+}
+
+/// Check a job against the probe of its source
+fn check_probe(job: &ConversionRequest, info: &VideoInfo) -> Result<(), String> {
+    if !info.valid {
+        return Err(info
+            .error
+            .clone()
+            .unwrap_or_else(|| "Cannot read video".to_string()));
+    }
+    if job.trimmed_duration(info.duration_secs) <= 0.0 {
+        return Err(format!(
+            "Trim is outside the video ({:.1}s long)",
+            info.duration_secs
+        ));
+    }
+    Ok(())
+}
+
+/// Parse a CSV manifest into rows tagged with their line numbers
+fn parse_csv(text: &str) -> Result<Vec<(usize, RowResult)>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Invalid manifest header: {}", e))?
+        .clone();
+    if !headers.iter().any(|h| h == "path" || h == "file") {
+        return Err("Manifest header needs a 'path' column".to_string());
+    }
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let (line, row) = match record {
+            Ok(record) => {
+                let line = record.position().map_or(0, |p| line_at(text, p.byte()));
+                if record.iter().all(str::is_empty) {
+                    continue;
+                }
+                let row = record
+                    .deserialize::<ManifestRow>(Some(&headers))
+                    .map_err(|e| (record_path(&headers, &record), csv_reason(&e)));
+                (line, row)
+            }
+            Err(e) => {
+                let line = e.position().map_or(0, |p| line_at(text, p.byte()));
+                (line, Err((None, csv_reason(&e))))
+            }
+        };
+        rows.push((line, row));
+    }
+    Ok(rows)
+}
+
+/// 1-based line of the record starting at a byte offset. The CSV reader's
+/// own line count skips blank lines, and its offsets point before them.
+fn line_at(text: &str, byte: u64) -> usize {
+    let bytes = text.as_bytes();
+    let mut end = (byte as usize).min(bytes.len());
+    while end < bytes.len() && matches!(bytes[end], b'\r' | b'\n') {
+        end += 1;
+    }
+    bytes[..end].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+fn record_path(headers: &csv::StringRecord, record: &csv::StringRecord) -> Option<String> {
+    headers
+        .iter()
+        .position(|h| h == "path" || h == "file")
+        .and_then(|i| record.get(i))
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+}
+
+/// Error text without the position, which is reported separately
+fn csv_reason(error: &csv::Error) -> String {
+    match error.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => match err.field() {
+            Some(field) => format!("Invalid value in column {}: {}", field + 1, err.kind()),
+            None => err.kind().to_string(),
+        },
+        _ => error.to_string(),
+    }
+}
+
+/// Parse a JSON manifest into rows tagged with their line numbers
+fn parse_json(text: &str) -> Result<Vec<(usize, RowResult)>, String> {
+    let values: Vec<serde_json::Value> =
+        serde_json::from_str(text).map_err(|e| format!("Invalid manifest: {}", e))?;
+    let lines = element_lines(text);
+
+    Ok(values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let line = lines.get(index).copied().unwrap_or(0);
+            let path = value
+                .get("path")
+                .or_else(|| value.get("file"))
+                .and_then(|p| p.as_str())
+                .map(str::to_string);
+            let row =
+                serde_json::from_value::<ManifestRow>(value).map_err(|e| (path, e.to_string()));
+            (line, row)
+        })
+        .collect())
+}
+
+/// Line on which each element of a top-level JSON array starts
+fn element_lines(text: &str) -> Vec<usize> {
+    let mut lines = Vec::new();
+    let mut line = 1;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut expect_element = false;
+
+    for c in text.chars() {
+        if c == '\n' {
+            line += 1;
+        }
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        if expect_element && !c.is_whitespace() && c != ']' {
+            lines.push(line);
+            expect_element = false;
+        }
+        match c {
+            '"' => in_string = true,
+            '[' | '{' => {
+                depth += 1;
+                expect_element = depth == 1;
+            }
+            ']' | '}' => depth -= 1,
+            ',' if depth == 1 => expect_element = true,
+            _ => {}
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> ConversionRequest {
+        ConversionRequest {
+            speed_multiplier: 10,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_element_lines() {
+        let text =
+            "[\n  {\"path\": \"a,[b].mp4\"},\n\n  {\"path\": \"c\\\"d.mp4\",\n   \"speed\": 5}\n]";
+        assert_eq!(element_lines(text), vec![2, 4]);
+        assert_eq!(element_lines("[]"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_parse_json_rows() {
+        let text = "[\n{\"path\": \"a.mp4\", \"speed\": 100, \"trim_start\": 2.5},\n{\"file\": \"b.mp4\"},\n{\"path\": \"c.mp4\", \"speed\": \"fast\"}\n]";
+        let rows = parse_json(text).unwrap();
+        assert_eq!(rows.len(), 3);
+
+        let (line, row) = &rows[0];
+        assert_eq!(*line, 2);
+        let row = row.as_ref().unwrap();
+        assert_eq!(row.speed, Some(100));
+        assert_eq!(row.trim_start, Some(2.5));
+
+        assert_eq!(rows[1].1.as_ref().unwrap().path, "b.mp4");
+
+        let (line, row) = &rows[2];
+        assert_eq!(*line, 4);
+        let (path, _) = row.as_ref().unwrap_err();
+        assert_eq!(path.as_deref(), Some("c.mp4"));
+
+        assert!(parse_json("{\"path\": \"a.mp4\"}").is_err());
+    }
+
+    #[test]
+    fn test_parse_csv_rows() {
+        let text = "path,speed,trim_start,trim_end\na.mp4,100,,30\n\nb.mp4,,5,\nc.mp4,fast,,\n";
+        let rows = parse_csv(text).unwrap();
+        assert_eq!(rows.len(), 3);
+
+        assert_eq!(rows[0].0, 2);
+        let row = rows[0].1.as_ref().unwrap();
+        assert_eq!(row.speed, Some(100));
+        assert_eq!(row.trim_start, None);
+        assert_eq!(row.trim_end, Some(30.0));
+
+        assert_eq!(rows[1].0, 4);
+        assert_eq!(rows[1].1.as_ref().unwrap().trim_start, Some(5.0));
+
+        assert_eq!(rows[2].0, 5);
+        let (path, reason) = rows[2].1.as_ref().unwrap_err();
+        assert_eq!(path.as_deref(), Some("c.mp4"));
+        assert!(reason.contains("column 2"));
+
+        assert!(parse_csv("name,speed\na.mp4,10\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_csv_format_column() {
+        let text = "path,format\na.mp4,gif\nb.mp4,\n";
+        let rows = parse_csv(text).unwrap();
+        assert_eq!(rows[0].1.as_ref().unwrap().format, Some(OutputFormat::Gif));
+        assert_eq!(rows[1].1.as_ref().unwrap().format, None);
+
+        let defaults = ConversionRequest {
+            speed_multiplier: 10,
+            output_format: OutputFormat::Webp,
+            ..Default::default()
+        };
+        let row = rows[1].1.clone().unwrap();
+        let job = to_job(row, Path::new("/clips"), &defaults).unwrap();
+        assert_eq!(job.output_format, OutputFormat::Webp);
+    }
+
+    #[test]
+    fn test_to_job_applies_defaults_and_validates() {
+        let base = Path::new("/clips");
+        let row = ManifestRow {
+            path: "day1.mp4".to_string(),
+            trim_end: Some(60.0),
+            ..Default::default()
+        };
+        let job = to_job(row, base, &defaults()).unwrap();
+        assert_eq!(job.files, vec![base.join("day1.mp4").to_string_lossy()]);
+        assert_eq!(job.speed_multiplier, 10);
+        assert_eq!(job.trim_end_secs, Some(60.0));
+
+        let absolute = ManifestRow {
+            path: "/elsewhere/a.mov".to_string(),
+            speed: Some(200),
+            ..Default::default()
+        };
+        let job = to_job(absolute, base, &defaults()).unwrap();
+        assert_eq!(job.files, vec!["/elsewhere/a.mov"]);
+        assert_eq!(job.speed_multiplier, 200);
+
+        let unsupported = ManifestRow {
+            path: "notes.txt".to_string(),
+            ..Default::default()
+        };
+        let (_, reason) = to_job(unsupported, base, &defaults()).unwrap_err();
+        assert_eq!(reason, "Unsupported format");
+
+        let bad_speed = ManifestRow {
+            path: "a.mp4".to_string(),
+            speed: Some(1),
+            ..Default::default()
+        };
+        assert!(to_job(bad_speed, base, &defaults()).is_err());
+
+        let (path, _) = to_job(ManifestRow::default(), base, &defaults()).unwrap_err();
+        assert_eq!(path, None);
+    }
+
+    #[test]
+    fn test_to_job_without_any_speed() {
+        let row = ManifestRow {
+            path: "a.mp4".to_string(),
+            ..Default::default()
+        };
+        let no_speed = ConversionRequest::default();
+        let (path, reason) = to_job(row.clone(), Path::new("/clips"), &no_speed).unwrap_err();
+        assert!(path.unwrap().ends_with("a.mp4"));
+        assert_eq!(reason, "No speed in this row and no default speed given");
+
+        let row = ManifestRow {
+            speed: Some(50),
+            ..row
+        };
+        let job = to_job(row, Path::new("/clips"), &no_speed).unwrap();
+        assert_eq!(job.speed_multiplier, 50);
+    }
+
+    #[test]
+    fn test_check_probe() {
+        let job = ConversionRequest {
+            files: vec!["a.mp4".to_string()],
+            speed_multiplier: 10,
+            trim_start_secs: Some(20.0),
+            ..Default::default()
+        };
+        let info = VideoInfo {
+            valid: true,
+            duration_secs: 10.0,
+            ..Default::default()
+        };
+        assert!(check_probe(&job, &info).unwrap_err().contains("outside"));
+
+        let info = VideoInfo::invalid("a.mp4", "a.mp4", "Cannot open".to_string());
+        assert_eq!(check_probe(&job, &info).unwrap_err(), "Cannot open");
+    }
+
+    #[tokio::test]
+    async fn test_import_manifest_reports_invalid_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("clips.csv");
+        std::fs::write(
+            &manifest,
+            "path,speed\nmissing.mp4,50\nreadme.txt,10\n,10\nother.mp4,1\n",
+        )
+        .unwrap();
+
+        let import = import_manifest(&manifest, &defaults()).await.unwrap();
+        assert!(import.jobs.is_empty());
+        let lines: Vec<usize> = import.invalid.iter().map(|row| row.line).collect();
+        assert_eq!(lines, vec![2, 3, 4, 5]);
+        assert_eq!(import.invalid[1].reason, "Unsupported format");
+    }
+}
@@ -0,0 +1,327 @@
+use crate::convert::ConversionRequest;
+use crate::estimate::estimate_output_bytes;
+use crate::renditions::output_requests;
+use crate::video::{get_info_many, is_supported_format, rendition_output_path};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Extra room asked for on top of the estimated output size, since the
+/// estimate is only approximate
+pub const SPACE_MARGIN: f64 = 1.2;
+
+/// Kind of problem that would stop a conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    InvalidSettings,
+    UnsupportedFormat,
+    UnreadableSource,
+    NotWritable,
+    InsufficientSpace,
+}
+
+/// A problem found before converting
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreflightProblem {
+    pub kind: ProblemKind,
+    /// Input file or output directory the problem is about
+    pub path: Option<String>,
+    pub message: String,
+}
+
+/// Expected output of one input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputEstimate {
+    pub input: String,
+    pub output_path: String,
+    pub estimated_bytes: u64,
+}
+
+/// State of one output directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryCheck {
+    pub path: String,
+    pub writable: bool,
+    /// Free space on the directory's volume, when it could be read
+    pub free_bytes: Option<u64>,
+    /// Estimated size of the outputs written to this directory
+    pub required_bytes: u64,
+}
+
+/// Everything found by a preflight check
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PreflightReport {
+    /// True when nothing blocks the batch
+    pub ok: bool,
+    pub problems: Vec<PreflightProblem>,
+    pub outputs: Vec<OutputEstimate>,
+    pub directories: Vec<DirectoryCheck>,
+    pub total_estimated_bytes: u64,
+}
+
+/// Check a batch before it starts: settings, sources, write access to every
+/// output directory and free space for the estimated outputs
+///
+/// Output directories aren't created; write access is tested with a probe
+/// file in the nearest existing folder, which is removed again. All problems
+/// are reported together.
+pub async fn preflight(jobs: &[ConversionRequest]) -> PreflightReport {
+    let mut report = PreflightReport::default();
+
+    let mut inputs = Vec::new();
+    for job in jobs {
+        if let Err(e) = job.validate() {
+            report.problems.push(PreflightProblem {
+                kind: ProblemKind::InvalidSettings,
+                path: None,
+                message: e,
+            });
+            continue;
+        }
+        for file in &job.files {
+            if is_supported_format(file) {
+                inputs.push((file.clone(), job));
+            } else {
+                report.problems.push(PreflightProblem {
+                    kind: ProblemKind::UnsupportedFormat,
+                    path: Some(file.clone()),
+                    message: "Unsupported format".to_string(),
+                });
+            }
+        }
+    }
+
+    let paths = inputs.iter().map(|(file, _)| file.clone()).collect();
+    let infos = get_info_many(paths, |_, _| {}).await;
+
+    let mut required: BTreeMap<PathBuf, u64> = BTreeMap::new();
+    for ((input, job), info) in inputs.into_iter().zip(infos) {
+        // One output per rendition, each estimated on its own
+        for (rendition, settings) in output_requests(job) {
+            let output_path = rendition_output_path(
+                &input,
+                settings.output_dir.as_deref(),
+                settings.output_format,
+                rendition,
+            );
+            let dir = output_path.parent().unwrap_or(Path::new(".")).to_path_buf();
+            // Unreadable sources still need their directory checked
+            let size = required.entry(dir).or_default();
+            if !info.valid {
+                continue;
+            }
+
+            let estimated_bytes = estimate_output_bytes(&info, &settings);
+            *size += estimated_bytes;
+            report.total_estimated_bytes += estimated_bytes;
+            report.outputs.push(OutputEstimate {
+                input: input.clone(),
+                output_path: output_path.to_string_lossy().to_string(),
+                estimated_bytes,
+            });
+        }
+        if !info.valid {
+            report.problems.push(PreflightProblem {
+                kind: ProblemKind::UnreadableSource,
+                path: Some(input),
+                message: info.error.unwrap_or_else(|| "Invalid video".to_string()),
+            });
+        }
+    }
+
+    // Directories on the same volume share its free space
+    let mut volumes: BTreeMap<PathBuf, (Option<u64>, u64, Vec<String>)> = BTreeMap::new();
+    for (dir, required_bytes) in required {
+        let existing = nearest_existing(&dir);
+        let writable = existing.as_deref().is_some_and(is_writable);
+        let free_bytes = existing.as_deref().and_then(free_space);
+        let dir_name = dir.to_string_lossy().to_string();
+
+        if !writable {
+            report.problems.push(PreflightProblem {
+                kind: ProblemKind::NotWritable,
+                path: Some(dir_name.clone()),
+                message: format!("Cannot write to '{}'", dir_name),
+            });
+        }
+        if let Some(existing) = &existing {
+            let volume = volumes
+                .entry(volume_of(existing))
+                .or_insert((free_bytes, 0, Vec::new()));
+            volume.1 += required_bytes;
+            volume.2.push(dir_name.clone());
+        }
+
+        report.directories.push(DirectoryCheck {
+            path: dir_name,
+            writable,
+            free_bytes,
+            required_bytes,
+        });
+    }
+
+    for (free_bytes, required_bytes, dirs) in volumes.into_values() {
+        let needed = (required_bytes as f64 * SPACE_MARGIN) as u64;
+        if let Some(free_bytes) = free_bytes.filter(|&free| free < needed) {
+            report.problems.push(PreflightProblem {
+                kind: ProblemKind::InsufficientSpace,
+                path: Some(dirs.join(", ")),
+                message: format!(
+                    "Outputs need about {} but only {} is free",
+                    format_bytes(needed),
+                    format_bytes(free_bytes)
+                ),
+            });
+        }
+    }
+
+    report.ok = report.problems.is_empty();
+    report
+}
+
+/// The directory itself if it exists, or the closest ancestor that does,
+/// since missing output directories are created when the batch starts
+fn nearest_existing(dir: &Path) -> Option<PathBuf> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    dir.ancestors().find(|p| p.is_dir()).map(Path::to_path_buf)
+}
+
+/// Try creating and removing a file, which is the only reliable test across
+/// platforms, ACLs and read-only mounts
+fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".timelapse_preflight_{}", std::process::id()));
+    match std::fs::File::create(&probe) {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Identifies the volume a path lives on
+#[cfg(unix)]
+fn volume_of(path: &Path) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+    match std::fs::metadata(path) {
+        Ok(metadata) => PathBuf::from(format!("dev:{}", metadata.dev())),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+#[cfg(windows)]
+fn volume_of(path: &Path) -> PathBuf {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    path.components().take(2).collect()
+}
+
+/// Bytes available to the current user on the volume holding `path`
+#[cfg(unix)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    let mut available = 0u64;
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    (ok != 0).then_some(available)
+}
+
+/// Human-readable size such as "1.5 GB"
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(files: &[&str], output_dir: Option<&str>) -> ConversionRequest {
+        ConversionRequest {
+            files: files.iter().map(|f| f.to_string()).collect(),
+            speed_multiplier: 10,
+            output_dir: output_dir.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+
+    #[test]
+    fn test_nearest_existing() {
+        let tmp = std::env::temp_dir();
+        let missing = tmp.join("timelapse_preflight_missing/a/b");
+        assert_eq!(nearest_existing(&missing), Some(tmp));
+    }
+
+    #[test]
+    fn test_free_space_and_writable() {
+        let tmp = std::env::temp_dir();
+        assert!(free_space(&tmp).is_some());
+        assert!(is_writable(&tmp));
+        assert!(!is_writable(&tmp.join("timelapse_preflight_no_such_dir")));
+    }
+
+    #[tokio::test]
+    async fn test_preflight_lists_all_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out").to_string_lossy().to_string();
+
+        let mut bad_speed = job(&["a.mp4"], None);
+        bad_speed.speed_multiplier = 1;
+        let jobs = vec![
+            job(&["notes.txt", "/nonexistent/clip.mp4"], Some(&out)),
+            bad_speed,
+        ];
+        let report = preflight(&jobs).await;
+
+        assert!(!report.ok);
+        let kinds: Vec<ProblemKind> = report.problems.iter().map(|p| p.kind).collect();
+        assert!(kinds.contains(&ProblemKind::InvalidSettings));
+        assert!(kinds.contains(&ProblemKind::UnsupportedFormat));
+        assert!(kinds.contains(&ProblemKind::UnreadableSource));
+        // The output directory doesn't exist yet but can be created
+        assert_eq!(report.directories.len(), 1);
+        assert!(report.directories[0].writable);
+        assert!(!Path::new(&out).exists());
+    }
+}
@@ -0,0 +1,464 @@
+use crate::convert::ConversionRequest;
+use crate::formats::{OutputFormat, VideoCodec};
+use crate::geometry::{AspectRatio, Fit, Geometry};
+use crate::storage::{read_json, write_json};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// File name of the user preset store inside the config directory
+pub const PRESETS_FILE: &str = "presets.json";
+
+/// Current version of the preset file format
+pub const PRESETS_VERSION: u32 = 1;
+
+/// Serializes read-modify-write cycles on the preset file
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Named conversion settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Shipped with the app; can't be changed or deleted
+    #[serde(default)]
+    pub built_in: bool,
+    /// Settings to apply; `files` is ignored and always stored empty
+    pub settings: ConversionRequest,
+}
+
+impl Preset {
+    /// Build a conversion request for `files` from this preset
+    pub fn apply(&self, files: Vec<String>) -> ConversionRequest {
+        ConversionRequest {
+            files,
+            ..self.settings.clone()
+        }
+    }
+}
+
+/// On-disk format, also used for exports
+#[derive(Debug, Serialize, Deserialize)]
+struct PresetFile {
+    version: u32,
+    presets: Vec<Preset>,
+}
+
+/// A preset in an imported file that was left out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InvalidPreset {
+    /// Name in the file, when it has one
+    pub name: Option<String>,
+    pub reason: String,
+}
+
+/// Result of importing presets
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PresetImportResult {
+    /// Imported presets as stored
+    pub presets: Vec<Preset>,
+    pub invalid: Vec<InvalidPreset>,
+}
+
+/// Presets that ship with the app
+pub fn built_in_presets() -> Vec<Preset> {
+    let preset = |name: &str, description: &str, settings: ConversionRequest| Preset {
+        name: name.to_string(),
+        description: description.to_string(),
+        built_in: true,
+        settings,
+    };
+    let speed = |speed_multiplier: u32| ConversionRequest {
+        speed_multiplier,
+        ..Default::default()
+    };
+
+    vec![
+        preset(
+            "Construction 1000x",
+            "Weeks of site footage condensed to minutes",
+            speed(1000),
+        ),
+        preset(
+            "Construction 1000x H.265",
+            "Weeks of site footage in about half the file size",
+            ConversionRequest {
+                video_codec: VideoCodec::H265,
+                ..speed(1000)
+            },
+        ),
+        preset("Sky and clouds 100x", "Cloud and sunset motion", speed(100)),
+        preset(
+            "Quick 10x",
+            "Short clips, events and walkthroughs",
+            speed(10),
+        ),
+        preset(
+            "Vertical 9:16",
+            "Phone-sized 1080x1920 over a blurred background",
+            ConversionRequest {
+                output_format: OutputFormat::Mp4Faststart,
+                geometry: Geometry {
+                    aspect: Some(AspectRatio::Vertical),
+                    fit: Fit::Blur,
+                    width: Some(1080),
+                    height: Some(1920),
+                    ..Default::default()
+                },
+                ..speed(100)
+            },
+        ),
+    ]
+}
+
+/// User presets persisted as JSON in a config directory
+pub struct PresetStore {
+    path: PathBuf,
+}
+
+impl PresetStore {
+    /// Store presets in `dir`/presets.json
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        PresetStore {
+            path: dir.as_ref().join(PRESETS_FILE),
+        }
+    }
+
+    /// Built-in presets followed by user presets sorted by name
+    pub fn list(&self) -> Result<Vec<Preset>, String> {
+        let mut user = self.load()?;
+        user.sort_by_key(|p| p.name.to_lowercase());
+        let mut presets = built_in_presets();
+        presets.extend(user);
+        Ok(presets)
+    }
+
+    /// Look up a preset by name
+    pub fn get(&self, name: &str) -> Result<Preset, String> {
+        self.list()?
+            .into_iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| format!("Preset '{}' not found", name))
+    }
+
+    /// Add a new preset; the name must not be taken
+    pub fn create(&self, preset: Preset) -> Result<Preset, String> {
+        let _guard = STORE_LOCK.lock().map_err(|e| e.to_string())?;
+        let preset = sanitize(preset)?;
+        let mut user = self.load()?;
+        if is_taken(&user, &preset.name) {
+            return Err(format!("A preset named '{}' already exists", preset.name));
+        }
+        user.push(preset.clone());
+        self.save(&user)?;
+        Ok(preset)
+    }
+
+    /// Replace the user preset called `name`; the new preset may rename it
+    pub fn update(&self, name: &str, preset: Preset) -> Result<Preset, String> {
+        let _guard = STORE_LOCK.lock().map_err(|e| e.to_string())?;
+        ensure_not_built_in(name)?;
+        let preset = sanitize(preset)?;
+        let mut user = self.load()?;
+        let index = user
+            .iter()
+            .position(|p| p.name == name)
+            .ok_or_else(|| format!("Preset '{}' not found", name))?;
+        if preset.name != name && is_taken(&user, &preset.name) {
+            return Err(format!("A preset named '{}' already exists", preset.name));
+        }
+        user[index] = preset.clone();
+        self.save(&user)?;
+        Ok(preset)
+    }
+
+    /// Remove the user preset called `name`
+    pub fn delete(&self, name: &str) -> Result<(), String> {
+        let _guard = STORE_LOCK.lock().map_err(|e| e.to_string())?;
+        ensure_not_built_in(name)?;
+        let mut user = self.load()?;
+        let before = user.len();
+        user.retain(|p| p.name != name);
+        if user.len() == before {
+            return Err(format!("Preset '{}' not found", name));
+        }
+        self.save(&user)
+    }
+
+    /// Add presets from a JSON file. Names that are already taken get a
+    /// numbered suffix. Invalid entries are reported and the rest imported.
+    pub fn import(&self, path: &Path) -> Result<PresetImportResult, String> {
+        let entries = match read_json::<Value>(path)? {
+            Some(value) => import_entries(value)?,
+            None => return Err(format!("File '{}' not found", path.display())),
+        };
+
+        let _guard = STORE_LOCK.lock().map_err(|e| e.to_string())?;
+        let mut user = self.load()?;
+        let mut result = PresetImportResult::default();
+        for entry in entries {
+            let name = entry["name"].as_str().map(str::to_string);
+            let preset = serde_json::from_value::<Preset>(entry)
+                .map_err(|e| e.to_string())
+                .and_then(sanitize);
+            match preset {
+                Ok(mut preset) => {
+                    preset.name = unique_name(&user, &preset.name);
+                    user.push(preset.clone());
+                    result.presets.push(preset);
+                }
+                Err(reason) => result.invalid.push(InvalidPreset { name, reason }),
+            }
+        }
+        if !result.presets.is_empty() {
+            self.save(&user)?;
+        }
+        Ok(result)
+    }
+
+    /// Write the named presets (built-in or user) to a JSON file
+    pub fn export(&self, names: &[String], path: &Path) -> Result<usize, String> {
+        let presets = names
+            .iter()
+            .map(|name| self.get(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let count = presets.len();
+        write_json(
+            path,
+            &PresetFile {
+                version: PRESETS_VERSION,
+                presets,
+            },
+        )?;
+        Ok(count)
+    }
+
+    fn load(&self) -> Result<Vec<Preset>, String> {
+        match read_json::<PresetFile>(&self.path)? {
+            Some(file) if file.version > PRESETS_VERSION => Err(format!(
+                "Presets file version {} is newer than supported version {}",
+                file.version, PRESETS_VERSION
+            )),
+            Some(file) => Ok(file.presets),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn save(&self, presets: &[Preset]) -> Result<(), String> {
+        write_json(
+            &self.path,
+            &PresetFile {
+                version: PRESETS_VERSION,
+                presets: presets.to_vec(),
+            },
+        )
+    }
+}
+
+/// Presets in anything an import accepts: an export file, a bare list or a
+/// single preset
+fn import_entries(value: Value) -> Result<Vec<Value>, String> {
+    match value {
+        Value::Array(list) => Ok(list),
+        Value::Object(mut file) if file.contains_key("presets") => {
+            // Files written before versioning carry no version field
+            let version = file.get("version").and_then(Value::as_u64).unwrap_or(1);
+            if version > PRESETS_VERSION as u64 {
+                return Err(format!(
+                    "Presets file version {} is newer than supported version {}",
+                    version, PRESETS_VERSION
+                ));
+            }
+            match file.remove("presets") {
+                Some(Value::Array(list)) => Ok(list),
+                _ => Err("'presets' must be a list".to_string()),
+            }
+        }
+        Value::Object(_) => Ok(vec![value]),
+        _ => Err("Not a preset file".to_string()),
+    }
+}
+
+/// Validate a preset and normalize it for storage
+fn sanitize(mut preset: Preset) -> Result<Preset, String> {
+    preset.name = preset.name.trim().to_string();
+    if preset.name.is_empty() {
+        return Err("Preset name cannot be empty".to_string());
+    }
+    preset
+        .settings
+        .validate()
+        .map_err(|e| format!("Preset '{}': {}", preset.name, e))?;
+    preset.built_in = false;
+    preset.settings.files.clear();
+    Ok(preset)
+}
+
+fn ensure_not_built_in(name: &str) -> Result<(), String> {
+    if built_in_presets().iter().any(|p| p.name == name) {
+        Err(format!("Built-in preset '{}' cannot be changed", name))
+    } else {
+        Ok(())
+    }
+}
+
+fn is_taken(user: &[Preset], name: &str) -> bool {
+    user.iter().any(|p| p.name == name) || built_in_presets().iter().any(|p| p.name == name)
+}
+
+fn unique_name(user: &[Preset], name: &str) -> String {
+    if !is_taken(user, name) {
+        return name.to_string();
+    }
+    (2..)
+        .map(|n| format!("{} ({})", name, n))
+        .find(|candidate| !is_taken(user, candidate))
+        .unwrap_or_else(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn temp_store() -> (PresetStore, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        (PresetStore::new(dir.path()), dir)
+    }
+
+    fn user_preset(name: &str, speed_multiplier: u32) -> Preset {
+        Preset {
+            name: name.to_string(),
+            description: String::new(),
+            built_in: false,
+            settings: ConversionRequest {
+                files: vec!["/leftover/file.mp4".to_string()],
+                speed_multiplier,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_built_in_presets_are_valid() {
+        for preset in built_in_presets() {
+            assert!(preset.built_in);
+            assert!(preset.settings.validate().is_ok(), "{}", preset.name);
+        }
+    }
+
+    #[test]
+    fn test_list_starts_with_built_ins() {
+        let (store, _dir) = temp_store();
+        let presets = store.list().unwrap();
+        assert_eq!(presets.len(), built_in_presets().len());
+    }
+
+    #[test]
+    fn test_create_update_delete() {
+        let (store, _dir) = temp_store();
+
+        let created = store.create(user_preset("  Garden 200x ", 200)).unwrap();
+        assert_eq!(created.name, "Garden 200x");
+        assert!(created.settings.files.is_empty());
+        assert!(store.create(user_preset("Garden 200x", 300)).is_err());
+
+        let updated = store
+            .update("Garden 200x", user_preset("Garden 300x", 300))
+            .unwrap();
+        assert_eq!(updated.settings.speed_multiplier, 300);
+        assert!(store.get("Garden 200x").is_err());
+        assert_eq!(
+            store.get("Garden 300x").unwrap().settings.speed_multiplier,
+            300
+        );
+
+        store.delete("Garden 300x").unwrap();
+        assert!(store.delete("Garden 300x").is_err());
+    }
+
+    #[test]
+    fn test_built_ins_are_read_only() {
+        let (store, _dir) = temp_store();
+        let name = built_in_presets()[0].name.clone();
+        assert!(store.delete(&name).is_err());
+        assert!(store.update(&name, user_preset("Other", 10)).is_err());
+        assert!(store.create(user_preset(&name, 10)).is_err());
+    }
+
+    #[test]
+    fn test_create_rejects_invalid_settings() {
+        let (store, _dir) = temp_store();
+        assert!(store.create(user_preset("Too slow", 1)).is_err());
+        assert!(store.create(user_preset("   ", 10)).is_err());
+    }
+
+    #[test]
+    fn test_export_import_roundtrip() {
+        let (store, dir) = temp_store();
+        store.create(user_preset("Harbor 50x", 50)).unwrap();
+
+        let export_path = dir.path().join("export.json");
+        let names = vec!["Harbor 50x".to_string(), "Quick 10x".to_string()];
+        assert_eq!(store.export(&names, &export_path).unwrap(), 2);
+
+        let imported = store.import(&export_path).unwrap().presets;
+        let imported_names: Vec<&str> = imported.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(imported_names, vec!["Harbor 50x (2)", "Quick 10x (2)"]);
+        assert!(imported.iter().all(|p| !p.built_in));
+    }
+
+    #[test]
+    fn test_import_single_preset() {
+        let (store, dir) = temp_store();
+        let path = dir.path().join("single.json");
+        std::fs::write(
+            &path,
+            r#"{"name":"Shared","settings":{"files":[],"speed_multiplier":20}}"#,
+        )
+        .unwrap();
+        let imported = store.import(&path).unwrap();
+        assert_eq!(imported.presets[0].name, "Shared");
+        assert_eq!(store.get("Shared").unwrap().settings.speed_multiplier, 20);
+    }
+
+    #[test]
+    fn test_import_skips_invalid_presets() {
+        let (store, dir) = temp_store();
+        let path = dir.path().join("partial.json");
+        std::fs::write(
+            &path,
+            r#"{"version":1,"presets":[
+                {"name":"Good","settings":{"files":[],"speed_multiplier":20}},
+                {"name":"Too slow","settings":{"files":[],"speed_multiplier":1}},
+                {"name":"No settings"}
+            ]}"#,
+        )
+        .unwrap();
+        let imported = store.import(&path).unwrap();
+        assert_eq!(imported.presets.len(), 1);
+        let invalid: Vec<Option<&str>> =
+            imported.invalid.iter().map(|p| p.name.as_deref()).collect();
+        assert_eq!(invalid, [Some("Too slow"), Some("No settings")]);
+        assert!(store.get("Good").is_ok());
+    }
+
+    #[test]
+    fn test_import_rejects_newer_version() {
+        let (store, dir) = temp_store();
+        let path = dir.path().join("newer.json");
+        std::fs::write(&path, r#"{"version":99,"presets":[]}"#).unwrap();
+        let err = store.import(&path).unwrap_err();
+        assert!(err.contains("newer"));
+    }
+
+    #[test]
+    fn test_apply_sets_files() {
+        let preset = built_in_presets().remove(0);
+        let request = preset.apply(vec!["a.mp4".to_string()]);
+        assert_eq!(request.files, vec!["a.mp4"]);
+        assert_eq!(request.speed_multiplier, 1000);
+    }
+}
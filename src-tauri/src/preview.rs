@@ -0,0 +1,271 @@
+use crate::convert::{run_ffmpeg_preview, ConversionRequest};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Folder inside the system temp directory that holds previews
+pub const PREVIEW_DIR_NAME: &str = "timelapse-creator-previews";
+
+/// Height previews are scaled down to unless the options say otherwise
+pub const DEFAULT_PREVIEW_HEIGHT: u32 = 360;
+
+/// Previews older than this are deleted whenever a new one is rendered
+pub const PREVIEW_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// How to render a preview
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreviewOptions {
+    /// Maximum output height [default: `DEFAULT_PREVIEW_HEIGHT`]
+    pub max_height: Option<u32>,
+    /// Source position the preview starts at [default: the trim start]
+    pub window_start_secs: Option<f64>,
+    /// Length of the preview itself, in output seconds; the source span is
+    /// this times the speed multiplier [default: the whole trimmed range]
+    pub window_secs: Option<f64>,
+}
+
+/// A rendered preview
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preview {
+    pub output_path: String,
+    /// Part of the source the preview covers
+    pub source_start_secs: f64,
+    pub source_end_secs: Option<f64>,
+}
+
+/// Folder previews are written to
+pub fn preview_dir() -> PathBuf {
+    std::env::temp_dir().join(PREVIEW_DIR_NAME)
+}
+
+/// The request a preview of `input` is rendered with: the settings of
+/// `request` with the trim narrowed to the preview window
+pub fn preview_request(
+    input: &str,
+    request: &ConversionRequest,
+    options: &PreviewOptions,
+) -> Result<ConversionRequest, String> {
+    let trim_start = request.trim_start_secs.unwrap_or(0.0);
+    let start = options
+        .window_start_secs
+        .unwrap_or(trim_start)
+        .max(trim_start);
+    if request.trim_end_secs.is_some_and(|end| start >= end) {
+        return Err("Preview window starts after the trim end".to_string());
+    }
+
+    let end = match options.window_secs {
+        Some(secs) if secs <= 0.0 => {
+            return Err("Preview window must be longer than zero".to_string())
+        }
+        Some(secs) => {
+            let end = start + secs * request.speed_multiplier as f64;
+            Some(
+                request
+                    .trim_end_secs
+                    .map_or(end, |trim_end| end.min(trim_end)),
+            )
+        }
+        None => request.trim_end_secs,
+    };
+
+    Ok(ConversionRequest {
+        files: vec![input.to_string()],
+        trim_start_secs: (start > 0.0).then_some(start),
+        trim_end_secs: end,
+        ..request.clone()
+    })
+}
+
+/// Render a fast, low-resolution preview of one input into the temp folder
+///
+/// Previews of the same input with other settings are replaced, and previews
+/// older than `PREVIEW_MAX_AGE` are deleted.
+pub async fn render_preview<F>(
+    input: &str,
+    request: &ConversionRequest,
+    options: &PreviewOptions,
+    on_progress: F,
+) -> Result<Preview, String>
+where
+    F: FnMut(f64),
+{
+    let max_height = options.max_height.unwrap_or(DEFAULT_PREVIEW_HEIGHT);
+    if max_height < 2 {
+        return Err("Preview height must be at least 2".to_string());
+    }
+    let preview = preview_request(input, request, options)?;
+    preview.validate()?;
+
+    let dir = preview_dir();
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+    cleanup_previews(PREVIEW_MAX_AGE);
+
+    let input_key = format!("{:016x}", hash_of(&input));
+    let settings_key = format!("{:016x}", hash_of(&(&preview, max_height)));
+    remove_previews(&dir, |name| {
+        name.starts_with(&input_key) && !name.contains(&settings_key)
+    });
+    let output_path = dir.join(format!("{}_{}.mp4", input_key, settings_key));
+    let output = output_path.to_string_lossy().to_string();
+
+    if let Err(e) = run_ffmpeg_preview(input, &output, &preview, max_height, on_progress).await {
+        let _ = std::fs::remove_file(&output_path);
+        return Err(e);
+    }
+
+    Ok(Preview {
+        output_path: output,
+        source_start_secs: preview.trim_start_secs.unwrap_or(0.0),
+        source_end_secs: preview.trim_end_secs,
+    })
+}
+
+/// Delete previews older than `max_age` and return how many were removed
+pub fn cleanup_previews(max_age: Duration) -> usize {
+    let now = SystemTime::now();
+    remove_previews_where(&preview_dir(), |path| {
+        std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age >= max_age)
+    })
+}
+
+/// Delete every preview, e.g. the leftovers of an earlier session
+pub fn clear_previews() -> usize {
+    remove_previews_where(&preview_dir(), |_| true)
+}
+
+fn remove_previews<P>(dir: &Path, mut matches: P) -> usize
+where
+    P: FnMut(&str) -> bool,
+{
+    remove_previews_where(dir, |path| {
+        path.file_name()
+            .is_some_and(|name| matches(&name.to_string_lossy()))
+    })
+}
+
+fn remove_previews_where<P>(dir: &Path, mut matches: P) -> usize
+where
+    P: FnMut(&Path) -> bool,
+{
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "mp4"))
+        .filter(|path| matches(path))
+        .filter(|path| std::fs::remove_file(path).is_ok())
+        .count()
+}
+
+/// Stable key for file names; serialized through JSON since requests hold
+/// floats, which aren't `Hash`
+fn hash_of<T: Serialize>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(value)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> ConversionRequest {
+        ConversionRequest {
+            files: vec!["a.mp4".to_string(), "b.mp4".to_string()],
+            speed_multiplier: 100,
+            trim_start_secs: Some(60.0),
+            trim_end_secs: Some(3600.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_preview_request_whole_range() {
+        let preview = preview_request("a.mp4", &request(), &PreviewOptions::default()).unwrap();
+        assert_eq!(preview.files, vec!["a.mp4"]);
+        assert_eq!(preview.speed_multiplier, 100);
+        assert_eq!(preview.trim_start_secs, Some(60.0));
+        assert_eq!(preview.trim_end_secs, Some(3600.0));
+    }
+
+    #[test]
+    fn test_preview_request_window() {
+        let options = PreviewOptions {
+            window_start_secs: Some(600.0),
+            window_secs: Some(5.0),
+            ..Default::default()
+        };
+        let preview = preview_request("a.mp4", &request(), &options).unwrap();
+        // Five output seconds at 100x cover 500 source seconds
+        assert_eq!(preview.trim_start_secs, Some(600.0));
+        assert_eq!(preview.trim_end_secs, Some(1100.0));
+
+        // The window never leaves the trimmed range
+        let late = PreviewOptions {
+            window_start_secs: Some(3500.0),
+            window_secs: Some(5.0),
+            ..Default::default()
+        };
+        let preview = preview_request("a.mp4", &request(), &late).unwrap();
+        assert_eq!(preview.trim_end_secs, Some(3600.0));
+
+        let early = PreviewOptions {
+            window_start_secs: Some(0.0),
+            ..Default::default()
+        };
+        let preview = preview_request("a.mp4", &request(), &early).unwrap();
+        assert_eq!(preview.trim_start_secs, Some(60.0));
+    }
+
+    #[test]
+    fn test_preview_request_rejects_bad_windows() {
+        let after_end = PreviewOptions {
+            window_start_secs: Some(4000.0),
+            ..Default::default()
+        };
+        assert!(preview_request("a.mp4", &request(), &after_end).is_err());
+
+        let empty = PreviewOptions {
+            window_secs: Some(0.0),
+            ..Default::default()
+        };
+        assert!(preview_request("a.mp4", &request(), &empty).is_err());
+    }
+
+    #[test]
+    fn test_remove_previews() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["aaaa_1.mp4", "aaaa_2.mp4", "bbbb_1.mp4", "notes.txt"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let removed = remove_previews(dir.path(), |name| {
+            name.starts_with("aaaa") && !name.contains("_2")
+        });
+        assert_eq!(removed, 1);
+        assert!(dir.path().join("aaaa_2.mp4").exists());
+
+        // Only previews are ever deleted
+        assert_eq!(remove_previews_where(dir.path(), |_| true), 2);
+        assert!(dir.path().join("notes.txt").exists());
+    }
+
+    #[test]
+    fn test_hash_of_is_stable() {
+        assert_eq!(hash_of(&request()), hash_of(&request()));
+        assert_ne!(hash_of(&(&request(), 360)), hash_of(&(&request(), 240)));
+    }
+}
@@ -0,0 +1,339 @@
+use crate::convert::ConversionRequest;
+use crate::storage::{read_json, write_json};
+use crate::video::{is_supported_format, VideoInfo};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Current version of the project file format
+pub const PROJECT_VERSION: u32 = 1;
+
+/// File extension of project files
+pub const PROJECT_EXTENSION: &str = "tlproj";
+
+/// A saved session: the job list and what the sources looked like when saved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub version: u32,
+    pub name: String,
+    /// Jobs in the order they run; each carries its own files and settings
+    pub jobs: Vec<ConversionRequest>,
+    /// Probe results for every source used by the jobs
+    #[serde(default)]
+    pub sources: Vec<ProjectSource>,
+}
+
+/// A source video of a project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectSource {
+    pub path: String,
+    /// Path relative to the project file, used to find sources that were
+    /// moved together with the project
+    #[serde(default)]
+    pub relative_path: Option<String>,
+    pub info: VideoInfo,
+}
+
+/// A project as loaded, with the sources that couldn't be found
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadedProject {
+    pub project: Project,
+    pub missing: Vec<String>,
+}
+
+/// A file that looks like a missing source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RelinkCandidate {
+    pub missing_path: String,
+    pub candidate_path: String,
+}
+
+impl Project {
+    /// Create a project from jobs and the probe results of their sources
+    pub fn new(name: &str, jobs: Vec<ConversionRequest>, infos: Vec<VideoInfo>) -> Self {
+        Project {
+            version: PROJECT_VERSION,
+            name: name.to_string(),
+            jobs,
+            sources: infos
+                .into_iter()
+                .map(|info| ProjectSource {
+                    path: info.path.clone(),
+                    relative_path: None,
+                    info,
+                })
+                .collect(),
+        }
+    }
+
+    /// Every file used by the jobs, in job order and without duplicates
+    pub fn source_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = Vec::new();
+        for file in self.jobs.iter().flat_map(|job| &job.files) {
+            if !paths.contains(file) {
+                paths.push(file.clone());
+            }
+        }
+        paths
+    }
+
+    /// Sources that don't exist on disk
+    pub fn missing_sources(&self) -> Vec<String> {
+        self.source_paths()
+            .into_iter()
+            .filter(|path| !Path::new(path).is_file())
+            .collect()
+    }
+
+    /// Point every use of `old_path` at `new_path`
+    pub fn relink(&mut self, old_path: &str, new_path: &str) -> Result<(), String> {
+        if !Path::new(new_path).is_file() {
+            return Err(format!("'{}' does not exist", new_path));
+        }
+        if !is_supported_format(new_path) {
+            return Err(format!("'{}' is not a supported video", new_path));
+        }
+        if !self.source_paths().iter().any(|p| p == old_path) {
+            return Err(format!("'{}' is not part of this project", old_path));
+        }
+
+        for file in self.jobs.iter_mut().flat_map(|job| job.files.iter_mut()) {
+            if file == old_path {
+                *file = new_path.to_string();
+            }
+        }
+        for source in self.sources.iter_mut().filter(|s| s.path == old_path) {
+            source.path = new_path.to_string();
+            source.relative_path = None;
+            source.info.path = new_path.to_string();
+            if let Some(name) = Path::new(new_path).file_name() {
+                source.info.filename = name.to_string_lossy().to_string();
+            }
+        }
+        Ok(())
+    }
+
+    /// Search `directory` recursively for files matching missing sources by
+    /// file name and, when it was recorded, file size
+    pub fn find_relink_candidates(&self, directory: &str) -> Vec<RelinkCandidate> {
+        let missing = self.missing_sources();
+        if missing.is_empty() {
+            return Vec::new();
+        }
+
+        let mut candidates = Vec::new();
+        for entry in WalkDir::new(directory)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_file())
+        {
+            let size = entry.metadata().ok().map(|m| m.len());
+            for missing_path in &missing {
+                let same_name = Path::new(missing_path).file_name() == Some(entry.file_name());
+                let recorded_size = self
+                    .sources
+                    .iter()
+                    .find(|s| &s.path == missing_path)
+                    .and_then(|s| s.info.file_size);
+                let same_size = recorded_size.is_none() || recorded_size == size;
+                if same_name && same_size {
+                    candidates.push(RelinkCandidate {
+                        missing_path: missing_path.clone(),
+                        candidate_path: entry.path().to_string_lossy().to_string(),
+                    });
+                }
+            }
+        }
+        candidates.sort_by(|a, b| {
+            (&a.missing_path, &a.candidate_path).cmp(&(&b.missing_path, &b.candidate_path))
+        });
+        candidates
+    }
+}
+
+/// Save a project, recording source paths relative to the project file
+pub fn save_project(path: &Path, project: &Project) -> Result<(), String> {
+    let mut project = project.clone();
+    project.version = PROJECT_VERSION;
+
+    let base = project_dir(path);
+    let known: Vec<String> = project.sources.iter().map(|s| s.path.clone()).collect();
+    for source in &mut project.sources {
+        source.relative_path = relative_to(&base, Path::new(&source.path));
+    }
+    // Keep a record of sources that were never probed so they can be relinked
+    for file in project.source_paths() {
+        if !known.contains(&file) {
+            project.sources.push(ProjectSource {
+                relative_path: relative_to(&base, Path::new(&file)),
+                info: VideoInfo {
+                    path: file.clone(),
+                    ..Default::default()
+                },
+                path: file,
+            });
+        }
+    }
+
+    write_json(path, &project)
+}
+
+/// Load a project. Sources that moved along with the project file are
+/// relinked automatically; the rest are reported as missing.
+pub fn load_project(path: &Path) -> Result<LoadedProject, String> {
+    let mut project: Project =
+        read_json(path)?.ok_or_else(|| format!("Project '{}' not found", path.display()))?;
+    if project.version > PROJECT_VERSION {
+        return Err(format!(
+            "Project version {} is newer than supported version {}",
+            project.version, PROJECT_VERSION
+        ));
+    }
+
+    let base = project_dir(path);
+    let moved: Vec<(String, String)> = project
+        .sources
+        .iter()
+        .filter(|s| !Path::new(&s.path).is_file())
+        .filter_map(|s| {
+            let candidate = base.join(s.relative_path.as_ref()?);
+            candidate
+                .is_file()
+                .then(|| (s.path.clone(), candidate.to_string_lossy().to_string()))
+        })
+        .collect();
+    for (old_path, new_path) in moved {
+        project.relink(&old_path, &new_path)?;
+    }
+
+    let missing = project.missing_sources();
+    Ok(LoadedProject { project, missing })
+}
+
+fn project_dir(path: &Path) -> PathBuf {
+    path.parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Express `path` relative to `base` when it lies inside it
+fn relative_to(base: &Path, path: &Path) -> Option<String> {
+    path.strip_prefix(base)
+        .ok()
+        .map(|p| p.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(files: &[&str], speed_multiplier: u32) -> ConversionRequest {
+        ConversionRequest {
+            files: files.iter().map(|f| f.to_string()).collect(),
+            speed_multiplier,
+            ..Default::default()
+        }
+    }
+
+    fn info(path: &str, file_size: u64) -> VideoInfo {
+        VideoInfo {
+            path: path.to_string(),
+            filename: Path::new(path)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string(),
+            file_size: Some(file_size),
+            valid: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_save_and_load_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let clip = dir.path().join("clip.mp4");
+        std::fs::write(&clip, b"data").unwrap();
+        let clip = clip.to_string_lossy().to_string();
+
+        let mut first = job(&[&clip], 100);
+        first.trim_start_secs = Some(5.0);
+        let project = Project::new("Site", vec![first, job(&[&clip], 10)], vec![info(&clip, 4)]);
+
+        let path = dir.path().join("site.tlproj");
+        save_project(&path, &project).unwrap();
+        let loaded = load_project(&path).unwrap();
+
+        assert!(loaded.missing.is_empty());
+        assert_eq!(loaded.project.name, "Site");
+        assert_eq!(loaded.project.jobs.len(), 2);
+        assert_eq!(loaded.project.jobs[0].trim_start_secs, Some(5.0));
+        assert_eq!(loaded.project.jobs[1].speed_multiplier, 10);
+        assert_eq!(
+            loaded.project.sources[0].relative_path.as_deref(),
+            Some("clip.mp4")
+        );
+    }
+
+    #[test]
+    fn test_load_relinks_sources_moved_with_project() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("old/footage")).unwrap();
+        let clip = dir.path().join("old/footage/a.mp4");
+        std::fs::write(&clip, b"data").unwrap();
+        let clip = clip.to_string_lossy().to_string();
+
+        let project = Project::new("Moved", vec![job(&[&clip], 50)], vec![info(&clip, 4)]);
+        save_project(&dir.path().join("old/p.tlproj"), &project).unwrap();
+        std::fs::rename(dir.path().join("old"), dir.path().join("new")).unwrap();
+
+        let loaded = load_project(&dir.path().join("new/p.tlproj")).unwrap();
+        assert!(loaded.missing.is_empty());
+        let relinked = &loaded.project.jobs[0].files[0];
+        assert!(relinked.ends_with("a.mp4"));
+        assert!(relinked.contains("new"));
+    }
+
+    #[test]
+    fn test_missing_sources_and_relink() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("archive")).unwrap();
+        let moved = dir.path().join("archive/b.mp4");
+        std::fs::write(&moved, b"12345").unwrap();
+        std::fs::write(dir.path().join("archive/b_other.mp4"), b"1").unwrap();
+
+        let old = "/gone/b.mp4";
+        let mut project = Project::new("Lost", vec![job(&[old], 20)], vec![info(old, 5)]);
+        assert_eq!(project.missing_sources(), vec![old.to_string()]);
+
+        let candidates = project.find_relink_candidates(&dir.path().to_string_lossy());
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].candidate_path, moved.to_string_lossy());
+
+        project.relink(old, &candidates[0].candidate_path).unwrap();
+        assert!(project.missing_sources().is_empty());
+        assert_eq!(project.sources[0].info.filename, "b.mp4");
+    }
+
+    #[test]
+    fn test_relink_rejects_bad_targets() {
+        let mut project = Project::new("P", vec![job(&["/gone/c.mp4"], 20)], vec![]);
+        assert!(project.relink("/gone/c.mp4", "/nonexistent/c.mp4").is_err());
+        assert!(project.relink("/other.mp4", "/tmp").is_err());
+    }
+
+    #[test]
+    fn test_load_rejects_newer_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("future.tlproj");
+        std::fs::write(
+            &path,
+            format!(
+                r#"{{"version":{},"name":"F","jobs":[]}}"#,
+                PROJECT_VERSION + 1
+            ),
+        )
+        .unwrap();
+        assert!(load_project(&path).unwrap_err().contains("newer"));
+    }
+}
@@ -0,0 +1,284 @@
+use crate::convert::ConversionRequest;
+use crate::formats::{AnimationOptions, OutputFormat, VideoCodec};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// Most renditions a single job may declare
+pub const MAX_RENDITIONS: usize = 8;
+
+/// One of several outputs made from a single decode of each input, e.g. a
+/// full-size master, a smaller web copy and a GIF teaser
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rendition {
+    /// Short label added to the output name: `clip_timelapse_<name>.mp4`
+    pub name: String,
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    /// Scale down to at most this many lines [default: the request's]
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    /// [default: the request's]
+    #[serde(default)]
+    pub video_codec: Option<VideoCodec>,
+    /// Size, frame rate and loop settings when the format is animated
+    /// [default: the request's, without its target size]
+    #[serde(default)]
+    pub animation: Option<AnimationOptions>,
+}
+
+impl Rendition {
+    /// Check the settings without touching any files
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("Rendition name cannot be empty".to_string());
+        }
+        if !self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "Rendition name '{}' may only use letters, digits, '-' and '_'",
+                self.name
+            ));
+        }
+        if self.max_height.is_some_and(|h| h < 2) {
+            return Err(format!(
+                "Rendition '{}' must be at least 2 lines high",
+                self.name
+            ));
+        }
+        if let Some(animation) = self
+            .animation
+            .as_ref()
+            .filter(|_| self.output_format.is_animation())
+        {
+            animation.validate()?;
+            // Fitting a size means encoding again, which would decode again
+            if animation.target_bytes.is_some() {
+                return Err(format!(
+                    "Rendition '{}' cannot have a target size",
+                    self.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Settings of this output: `request` with the rendition's format, size,
+    /// codec and, when it has its own, animation settings
+    pub fn apply(&self, request: &ConversionRequest) -> ConversionRequest {
+        ConversionRequest {
+            output_format: self.output_format,
+            max_height: self.max_height.or(request.max_height),
+            video_codec: self.video_codec.unwrap_or(request.video_codec),
+            animation: self.animation.clone().unwrap_or_else(|| AnimationOptions {
+                target_bytes: None,
+                ..request.animation.clone()
+            }),
+            renditions: Vec::new(),
+            ..request.clone()
+        }
+    }
+}
+
+/// Parses `name:format[:height[:codec]]`, e.g. `web:mp4_faststart:720:h264`
+impl FromStr for Rendition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split(':').map(str::trim);
+        let name = parts.next().unwrap_or_default().to_string();
+        let output_format = match parts.next().filter(|p| !p.is_empty()) {
+            Some(format) => format.parse()?,
+            None => OutputFormat::default(),
+        };
+        let max_height = match parts.next().filter(|p| !p.is_empty()) {
+            Some(height) => Some(
+                height
+                    .trim_end_matches('p')
+                    .parse()
+                    .map_err(|_| format!("Invalid rendition height '{}'", height))?,
+            ),
+            None => None,
+        };
+        let video_codec = parts
+            .next()
+            .filter(|p| !p.is_empty())
+            .map(str::parse)
+            .transpose()?;
+        if parts.next().is_some() {
+            return Err(format!(
+                "Invalid rendition '{}' (expected name:format[:height[:codec]])",
+                s
+            ));
+        }
+
+        let rendition = Rendition {
+            name,
+            output_format,
+            max_height,
+            video_codec,
+            animation: None,
+        };
+        rendition.validate()?;
+        Ok(rendition)
+    }
+}
+
+/// Check the renditions of a job: each on its own, and that their names,
+/// and with them their output paths, differ
+pub fn validate_renditions(renditions: &[Rendition]) -> Result<(), String> {
+    if renditions.len() > MAX_RENDITIONS {
+        return Err(format!(
+            "A job can have at most {} renditions",
+            MAX_RENDITIONS
+        ));
+    }
+    let mut names = HashSet::new();
+    for rendition in renditions {
+        rendition.validate()?;
+        if !names.insert(rendition.name.to_lowercase()) {
+            return Err(format!("Rendition name '{}' is used twice", rendition.name));
+        }
+    }
+    Ok(())
+}
+
+/// Every output a request makes from each input, with the rendition name:
+/// one per rendition, or the request itself when it has none
+pub fn output_requests(request: &ConversionRequest) -> Vec<(Option<&str>, ConversionRequest)> {
+    if request.renditions.is_empty() {
+        return vec![(None, request.clone())];
+    }
+    request
+        .renditions
+        .iter()
+        .map(|rendition| (Some(rendition.name.as_str()), rendition.apply(request)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rendition() {
+        let web: Rendition = "web:mp4_faststart:720p".parse().unwrap();
+        assert_eq!(web.name, "web");
+        assert_eq!(web.output_format, OutputFormat::Mp4Faststart);
+        assert_eq!(web.max_height, Some(720));
+        assert_eq!(web.video_codec, None);
+
+        let master: Rendition = "master::1080:h265".parse().unwrap();
+        assert_eq!(master.output_format, OutputFormat::Mp4);
+        assert_eq!(master.video_codec, Some(VideoCodec::H265));
+
+        let teaser: Rendition = "teaser:gif".parse().unwrap();
+        assert_eq!(teaser.max_height, None);
+        assert_eq!(teaser.animation, None);
+
+        for invalid in [
+            "",
+            ":mp4",
+            "web:avi",
+            "web:mp4:tall",
+            "web:mp4:720:vp9:x",
+            "a b:mp4",
+        ] {
+            assert!(invalid.parse::<Rendition>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_validate_renditions() {
+        let rendition = |name: &str| Rendition {
+            name: name.to_string(),
+            output_format: OutputFormat::Mp4,
+            max_height: None,
+            video_codec: None,
+            animation: None,
+        };
+        assert!(validate_renditions(&[rendition("master"), rendition("web")]).is_ok());
+        assert!(validate_renditions(&[rendition("web"), rendition("Web")]).is_err());
+        assert!(validate_renditions(&vec![rendition("x"); MAX_RENDITIONS + 1]).is_err());
+
+        let sized = Rendition {
+            output_format: OutputFormat::Gif,
+            animation: Some(AnimationOptions {
+                target_bytes: Some(1_000_000),
+                ..Default::default()
+            }),
+            ..rendition("teaser")
+        };
+        assert!(validate_renditions(&[sized]).is_err());
+    }
+
+    #[test]
+    fn test_output_requests() {
+        let request = ConversionRequest {
+            speed_multiplier: 100,
+            max_height: Some(1080),
+            video_codec: VideoCodec::H265,
+            ..Default::default()
+        };
+        let single = output_requests(&request);
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].0, None);
+
+        let request = ConversionRequest {
+            renditions: vec![
+                "master:mp4".parse().unwrap(),
+                "web:mp4_faststart:720".parse().unwrap(),
+                "teaser:gif".parse().unwrap(),
+                "small:mp4:480:h264".parse().unwrap(),
+            ],
+            ..request
+        };
+        let outputs = output_requests(&request);
+        let names: Vec<Option<&str>> = outputs.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            [Some("master"), Some("web"), Some("teaser"), Some("small")]
+        );
+        // Renditions inherit everything else from the request
+        assert_eq!(outputs[0].1.max_height, Some(1080));
+        assert_eq!(outputs[1].1.max_height, Some(720));
+        assert_eq!(outputs[2].1.output_format, OutputFormat::Gif);
+        assert_eq!(outputs[0].1.video_codec, VideoCodec::H265);
+        assert_eq!(outputs[1].1.video_codec, VideoCodec::H265);
+        assert_eq!(outputs[3].1.video_codec, VideoCodec::H264);
+        assert!(outputs.iter().all(|(_, settings)| {
+            settings.speed_multiplier == 100 && settings.renditions.is_empty()
+        }));
+    }
+
+    #[test]
+    fn test_rendition_animation_falls_back_to_request() {
+        let request = ConversionRequest {
+            speed_multiplier: 100,
+            animation: AnimationOptions {
+                fps: Some(10.0),
+                loops: Some(3),
+                target_bytes: Some(2_000_000),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let teaser: Rendition = "teaser:gif".parse().unwrap();
+        let settings = teaser.apply(&request);
+        assert_eq!(settings.animation.fps, Some(10.0));
+        assert_eq!(settings.animation.loops, Some(3));
+        assert_eq!(settings.animation.target_bytes, None);
+
+        let own = Rendition {
+            animation: Some(AnimationOptions {
+                fps: Some(5.0),
+                ..Default::default()
+            }),
+            ..teaser
+        };
+        assert_eq!(own.apply(&request).animation.fps, Some(5.0));
+    }
+}
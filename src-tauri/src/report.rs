@@ -0,0 +1,388 @@
+use crate::convert::ConversionRequest;
+use crate::formats::OutputFormat;
+use crate::sequence::folder_size;
+use crate::storage::write_json;
+use crate::video::VideoInfo;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Current version of the batch report format
+pub const REPORT_VERSION: u32 = 1;
+
+/// Number of FFmpeg stderr lines kept for a failed file
+pub const STDERR_EXCERPT_LINES: usize = 20;
+
+/// Why a file could not be converted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    UnsupportedFormat,
+    InvalidSettings,
+    ProbeFailed,
+    OutputUnavailable,
+    FfmpegUnavailable,
+    EncodeFailed,
+    /// FFmpeg finished but the output didn't pass the checks
+    VerificationFailed,
+}
+
+impl ErrorCategory {
+    /// Name used in reports
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCategory::UnsupportedFormat => "unsupported_format",
+            ErrorCategory::InvalidSettings => "invalid_settings",
+            ErrorCategory::ProbeFailed => "probe_failed",
+            ErrorCategory::OutputUnavailable => "output_unavailable",
+            ErrorCategory::FfmpegUnavailable => "ffmpeg_unavailable",
+            ErrorCategory::EncodeFailed => "encode_failed",
+            ErrorCategory::VerificationFailed => "verification_failed",
+        }
+    }
+}
+
+/// Error of a single file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileError {
+    pub category: ErrorCategory,
+    pub message: String,
+    /// Last lines FFmpeg wrote to stderr, when it got to run
+    #[serde(default)]
+    pub stderr: Option<String>,
+}
+
+impl FileError {
+    pub fn new(category: ErrorCategory, message: impl Into<String>) -> Self {
+        FileError {
+            category,
+            message: message.into(),
+            stderr: None,
+        }
+    }
+}
+
+/// What happened to one input of a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileReport {
+    pub input: String,
+    /// Probe of the input, missing when it failed before probing
+    #[serde(default)]
+    pub info: Option<VideoInfo>,
+    /// Settings used for this file; `files` holds just the input
+    pub settings: ConversionRequest,
+    /// Rendition this output is, for jobs with several; each rendition of
+    /// an input has its own report
+    #[serde(default)]
+    pub rendition: Option<String>,
+    #[serde(default)]
+    pub output_path: Option<String>,
+    #[serde(default)]
+    pub output_size: Option<u64>,
+    /// Wall-clock time spent on the file, in seconds
+    pub encode_secs: f64,
+    /// Seconds of source converted per second of encoding
+    #[serde(default)]
+    pub effective_speed: Option<f64>,
+    #[serde(default)]
+    pub error: Option<FileError>,
+}
+
+impl FileReport {
+    /// Start the report of one input converted with `request`
+    pub fn new(input: &str, request: &ConversionRequest) -> Self {
+        FileReport {
+            input: input.to_string(),
+            info: None,
+            settings: ConversionRequest {
+                files: vec![input.to_string()],
+                ..request.clone()
+            },
+            rendition: None,
+            output_path: None,
+            output_size: None,
+            encode_secs: 0.0,
+            effective_speed: None,
+            error: None,
+        }
+    }
+
+    /// Record the time spent, the size of whatever output was written and,
+    /// for a finished file, the effective speed
+    pub fn finish(&mut self, elapsed: Duration) {
+        self.encode_secs = elapsed.as_secs_f64();
+        // Image sequences are folders, and an HLS playlist sits in a folder
+        // with its segments; their size is that of the whole folder
+        let format = self.settings.output_format;
+        self.output_size = self.output_path.as_ref().and_then(|path| {
+            let metadata = std::fs::metadata(path).ok()?;
+            if metadata.is_dir() {
+                folder_size(Path::new(path))
+            } else if format == OutputFormat::Hls {
+                folder_size(Path::new(path).parent()?)
+            } else {
+                Some(metadata.len())
+            }
+        });
+        if self.error.is_some() {
+            return;
+        }
+        if let Some(info) = &self.info {
+            let source_secs = self.settings.trimmed_duration(info.duration_secs);
+            if self.encode_secs > 0.0 && source_secs > 0.0 {
+                self.effective_speed = Some(source_secs / self.encode_secs);
+            }
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Report of a whole batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchReport {
+    pub version: u32,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    pub converted_count: usize,
+    pub failed_count: usize,
+    pub files: Vec<FileReport>,
+}
+
+impl BatchReport {
+    pub fn new(files: Vec<FileReport>) -> Self {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let converted_count = files.iter().filter(|f| f.succeeded()).count();
+        BatchReport {
+            version: REPORT_VERSION,
+            created_at,
+            converted_count,
+            failed_count: files.len() - converted_count,
+            files,
+        }
+    }
+}
+
+/// One CSV line of a report
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    input: &'a str,
+    status: &'static str,
+    duration_secs: Option<f64>,
+    width: Option<u32>,
+    height: Option<u32>,
+    fps: Option<f64>,
+    codec: Option<&'a str>,
+    input_size: Option<u64>,
+    speed_multiplier: u32,
+    rotation_override: Option<u32>,
+    trim_start_secs: Option<f64>,
+    trim_end_secs: Option<f64>,
+    output_format: OutputFormat,
+    rendition: Option<&'a str>,
+    output_path: Option<&'a str>,
+    output_size: Option<u64>,
+    encode_secs: f64,
+    effective_speed: Option<f64>,
+    error_category: Option<&'static str>,
+    error_message: Option<&'a str>,
+    stderr: Option<&'a str>,
+}
+
+impl<'a> CsvRow<'a> {
+    fn from_file(file: &'a FileReport) -> Self {
+        let info = file.info.as_ref().filter(|info| info.valid);
+        CsvRow {
+            input: &file.input,
+            status: match &file.error {
+                None => "completed",
+                Some(e) if e.category == ErrorCategory::VerificationFailed => "verification_failed",
+                Some(_) => "failed",
+            },
+            duration_secs: info.map(|i| i.duration_secs),
+            width: info.map(|i| i.width),
+            height: info.map(|i| i.height),
+            fps: info.map(|i| i.fps),
+            codec: info.and_then(|i| i.codec.as_deref()),
+            input_size: info.and_then(|i| i.file_size),
+            speed_multiplier: file.settings.speed_multiplier,
+            rotation_override: file.settings.rotation_override,
+            trim_start_secs: file.settings.trim_start_secs,
+            trim_end_secs: file.settings.trim_end_secs,
+            output_format: file.settings.output_format,
+            rendition: file.rendition.as_deref(),
+            output_path: file.output_path.as_deref(),
+            output_size: file.output_size,
+            encode_secs: file.encode_secs,
+            effective_speed: file.effective_speed,
+            error_category: file.error.as_ref().map(|e| e.category.as_str()),
+            error_message: file.error.as_ref().map(|e| e.message.as_str()),
+            stderr: file.error.as_ref().and_then(|e| e.stderr.as_deref()),
+        }
+    }
+}
+
+/// Folder the report of a batch goes to: the requested output folder, or
+/// the folder of the first output
+pub fn report_dir(files: &[FileReport]) -> Option<PathBuf> {
+    files
+        .iter()
+        .find_map(|f| f.settings.output_dir.as_ref().map(PathBuf::from))
+        .or_else(|| {
+            files
+                .iter()
+                .find_map(|f| {
+                    let dir = Path::new(f.output_path.as_ref()?).parent()?;
+                    // An HLS playlist sits inside its package folder
+                    if f.settings.output_format == OutputFormat::Hls {
+                        dir.parent()
+                    } else {
+                        Some(dir)
+                    }
+                })
+                .map(Path::to_path_buf)
+        })
+}
+
+/// Write `timelapse_report_<time>.json` and `.csv` into `dir` and return the
+/// path of the JSON file
+pub fn write_report(dir: &Path, report: &BatchReport) -> Result<PathBuf, String> {
+    let stem = format!("timelapse_report_{}", report.created_at);
+    let json_path = dir.join(format!("{}.json", stem));
+    write_json(&json_path, report)?;
+
+    let csv_path = dir.join(format!("{}.csv", stem));
+    let mut writer = csv::Writer::from_path(&csv_path)
+        .map_err(|e| format!("Cannot write '{}': {}", csv_path.display(), e))?;
+    for file in &report.files {
+        writer
+            .serialize(CsvRow::from_file(file))
+            .map_err(|e| format!("Cannot write '{}': {}", csv_path.display(), e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Cannot write '{}': {}", csv_path.display(), e))?;
+
+    Ok(json_path)
+}
+
+/// Keep the last `STDERR_EXCERPT_LINES` non-empty lines of FFmpeg's stderr
+pub fn stderr_excerpt(stderr: &str) -> Option<String> {
+    let lines: Vec<&str> = stderr.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.is_empty() {
+        return None;
+    }
+    let start = lines.len().saturating_sub(STDERR_EXCERPT_LINES);
+    Some(lines[start..].join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> ConversionRequest {
+        ConversionRequest {
+            files: vec!["a.mp4".to_string(), "b.mp4".to_string()],
+            speed_multiplier: 10,
+            trim_end_secs: Some(50.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_file_report_finish() {
+        let mut report = FileReport::new("a.mp4", &request());
+        assert_eq!(report.settings.files, vec!["a.mp4"]);
+        report.info = Some(VideoInfo {
+            valid: true,
+            duration_secs: 100.0,
+            ..Default::default()
+        });
+        report.finish(Duration::from_secs(10));
+        assert_eq!(report.encode_secs, 10.0);
+        // Only the 50 trimmed seconds were converted
+        assert_eq!(report.effective_speed, Some(5.0));
+
+        let mut failed = FileReport::new("b.mp4", &request());
+        failed.error = Some(FileError::new(ErrorCategory::ProbeFailed, "No video"));
+        failed.finish(Duration::from_secs(1));
+        assert_eq!(failed.effective_speed, None);
+        assert!(!failed.succeeded());
+    }
+
+    #[test]
+    fn test_batch_report_counts() {
+        let mut failed = FileReport::new("b.mp4", &request());
+        failed.error = Some(FileError::new(ErrorCategory::EncodeFailed, "x"));
+        let report = BatchReport::new(vec![FileReport::new("a.mp4", &request()), failed]);
+        assert_eq!(report.converted_count, 1);
+        assert_eq!(report.failed_count, 1);
+    }
+
+    #[test]
+    fn test_report_dir() {
+        let mut file = FileReport::new("/in/a.mp4", &request());
+        assert_eq!(report_dir(std::slice::from_ref(&file)), None);
+
+        file.output_path = Some("/in/outputs/a_timelapse.mp4".to_string());
+        assert_eq!(
+            report_dir(std::slice::from_ref(&file)),
+            Some(PathBuf::from("/in/outputs"))
+        );
+
+        let mut hls = file.clone();
+        hls.settings.output_format = OutputFormat::Hls;
+        hls.output_path = Some("/in/outputs/a_timelapse_hls/index.m3u8".to_string());
+        assert_eq!(report_dir(&[hls]), Some(PathBuf::from("/in/outputs")));
+
+        file.settings.output_dir = Some("/out".to_string());
+        assert_eq!(report_dir(&[file]), Some(PathBuf::from("/out")));
+    }
+
+    #[test]
+    fn test_stderr_excerpt() {
+        assert_eq!(stderr_excerpt(""), None);
+        let stderr: String = (1..=30).map(|i| format!("line {}\n\n", i)).collect();
+        let excerpt = stderr_excerpt(&stderr).unwrap();
+        assert_eq!(excerpt.lines().count(), STDERR_EXCERPT_LINES);
+        assert!(excerpt.starts_with("line 11"));
+        assert!(excerpt.ends_with("line 30"));
+    }
+
+    #[test]
+    fn test_write_report() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut failed = FileReport::new("b.mp4", &request());
+        let mut error = FileError::new(ErrorCategory::EncodeFailed, "FFmpeg conversion failed");
+        error.stderr = Some("Invalid data, found \"x\"".to_string());
+        failed.error = Some(error);
+        let report = BatchReport::new(vec![FileReport::new("a.mp4", &request()), failed]);
+
+        let json_path = write_report(dir.path(), &report).unwrap();
+        let saved: BatchReport =
+            serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(saved.files.len(), 2);
+        assert_eq!(
+            saved.files[1].error.as_ref().unwrap().category,
+            ErrorCategory::EncodeFailed
+        );
+
+        let csv = std::fs::read_to_string(json_path.with_extension("csv")).unwrap();
+        let mut lines = csv.lines();
+        let header = lines.next().unwrap();
+        assert!(header.starts_with("input,status,"));
+        assert!(header.contains(",output_format,"));
+        let completed = lines.next().unwrap();
+        assert!(completed.starts_with("a.mp4,completed,"));
+        assert!(completed.contains(",mp4,"));
+        let failed_line = lines.next().unwrap();
+        assert!(failed_line.contains("encode_failed"));
+        assert!(failed_line.contains("\"Invalid data, found \"\"x\"\"\""));
+    }
+}
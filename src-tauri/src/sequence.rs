@@ -0,0 +1,207 @@
+use crate::convert::ConversionRequest;
+use crate::formats::OutputFormat;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Name of each frame inside a sequence folder, numbered from 1
+pub const FRAME_FILE_PATTERN: &str = "frame_%06d";
+
+/// Prefix shared by every frame file
+const FRAME_FILE_PREFIX: &str = "frame_";
+
+/// CSV written beside the frames when the request asks for timestamps
+pub const TIMESTAMPS_FILE_NAME: &str = "timestamps.csv";
+
+/// One row of the timestamps CSV
+#[derive(Debug, Serialize)]
+struct TimestampRow {
+    frame: usize,
+    file: String,
+    output_time_secs: String,
+    source_time_secs: String,
+}
+
+/// Output pattern FFmpeg's image2 muxer writes the frames of `dir` with
+pub fn frame_pattern(dir: &str, format: OutputFormat) -> String {
+    Path::new(dir)
+        .join(format!("{}.{}", FRAME_FILE_PATTERN, format.extension()))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Encoder and muxer arguments of an image sequence
+///
+/// JPEG frames get near-lossless quality and TIFF frames lossless
+/// compression, since they are meant for grading.
+pub fn sequence_output_args(format: OutputFormat) -> Vec<String> {
+    let encoder: &[&str] = match format {
+        OutputFormat::JpegSequence => &["-q:v", "2"],
+        OutputFormat::TiffSequence => &["-compression_algo", "lzw"],
+        _ => &[],
+    };
+    ["-f", "image2"]
+        .iter()
+        .chain(encoder)
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Frames of `format` in a sequence folder, in order
+pub fn frame_paths(dir: &Path, format: OutputFormat) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut frames: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| is_frame(path, format))
+        .collect();
+    frames.sort();
+    frames
+}
+
+/// Delete the frames and timestamps of an earlier export into `dir`, so a
+/// shorter sequence doesn't leave stale frames behind
+pub fn clear_sequence(dir: &Path, format: OutputFormat) -> usize {
+    let _ = std::fs::remove_file(dir.join(TIMESTAMPS_FILE_NAME));
+    frame_paths(dir, format)
+        .iter()
+        .filter(|path| std::fs::remove_file(path).is_ok())
+        .count()
+}
+
+/// Position in the source each of `frames` output frames was taken from,
+/// with the output running at `fps`
+pub fn source_timestamps(frames: usize, fps: f64, request: &ConversionRequest) -> Vec<f64> {
+    if fps <= 0.0 {
+        return Vec::new();
+    }
+    let start = request.trim_start_secs.unwrap_or(0.0);
+    (0..frames)
+        .map(|index| start + index as f64 / fps * request.speed_multiplier as f64)
+        .collect()
+}
+
+/// Write `timestamps.csv` into a sequence folder: the file, output time and
+/// source time of every frame
+pub fn write_timestamps(
+    dir: &Path,
+    format: OutputFormat,
+    fps: f64,
+    request: &ConversionRequest,
+) -> Result<PathBuf, String> {
+    let frames = frame_paths(dir, format);
+    let source_times = source_timestamps(frames.len(), fps, request);
+
+    let path = dir.join(TIMESTAMPS_FILE_NAME);
+    let mut writer = csv::Writer::from_path(&path)
+        .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))?;
+    for (index, (frame, source_secs)) in frames.iter().zip(source_times).enumerate() {
+        let row = TimestampRow {
+            frame: index + 1,
+            file: frame
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            output_time_secs: format!("{:.3}", index as f64 / fps),
+            source_time_secs: format!("{:.3}", source_secs),
+        };
+        writer
+            .serialize(row)
+            .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// Total size of the files directly inside `dir`
+pub fn folder_size(dir: &Path) -> Option<u64> {
+    let entries = std::fs::read_dir(dir).ok()?;
+    Some(
+        entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum(),
+    )
+}
+
+fn is_frame(path: &Path, format: OutputFormat) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == format.extension())
+        && path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(FRAME_FILE_PREFIX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_pattern() {
+        let pattern = frame_pattern("/exports/clip_timelapse_frames", OutputFormat::TiffSequence);
+        assert!(pattern.ends_with("frame_%06d.tif"));
+        assert!(pattern.starts_with("/exports/clip_timelapse_frames"));
+    }
+
+    #[test]
+    fn test_sequence_output_args() {
+        assert_eq!(
+            sequence_output_args(OutputFormat::PngSequence),
+            vec!["-f", "image2"]
+        );
+        assert!(sequence_output_args(OutputFormat::JpegSequence).contains(&"-q:v".to_string()));
+        assert!(sequence_output_args(OutputFormat::TiffSequence).contains(&"lzw".to_string()));
+    }
+
+    #[test]
+    fn test_source_timestamps() {
+        let request = ConversionRequest {
+            speed_multiplier: 100,
+            trim_start_secs: Some(60.0),
+            ..Default::default()
+        };
+        // At 25 output frames per second, each frame covers 4 source seconds
+        assert_eq!(source_timestamps(3, 25.0, &request), vec![60.0, 64.0, 68.0]);
+        assert!(source_timestamps(3, 0.0, &request).is_empty());
+    }
+
+    #[test]
+    fn test_write_timestamps_and_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "frame_000002.png",
+            "frame_000001.png",
+            "frame_000001.jpg",
+            "notes.txt",
+        ] {
+            std::fs::write(dir.path().join(name), b"1234").unwrap();
+        }
+        let request = ConversionRequest {
+            speed_multiplier: 10,
+            ..Default::default()
+        };
+
+        let path = write_timestamps(dir.path(), OutputFormat::PngSequence, 30.0, &request).unwrap();
+        let csv = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "frame,file,output_time_secs,source_time_secs",
+                "1,frame_000001.png,0.000,0.000",
+                "2,frame_000002.png,0.033,0.333",
+            ]
+        );
+        assert!(folder_size(dir.path()).unwrap() >= 16);
+
+        assert_eq!(clear_sequence(dir.path(), OutputFormat::PngSequence), 2);
+        assert!(!dir.path().join(TIMESTAMPS_FILE_NAME).exists());
+        assert!(dir.path().join("frame_000001.jpg").exists());
+        assert!(dir.path().join("notes.txt").exists());
+    }
+}
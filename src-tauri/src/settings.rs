@@ -0,0 +1,263 @@
+use crate::storage::{read_json, write_json};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File name of the settings store inside the config directory
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// Current version of the settings schema
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// Number of entries kept in the recent-files history
+pub const MAX_RECENT_FILES: usize = 20;
+
+/// Upper bound for the configurable worker count
+pub const MAX_WORKERS: usize = 32;
+
+/// Serializes read-modify-write cycles on the settings file
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Persisted application settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Schema version the file was written with
+    pub version: u32,
+    /// Output directory used when a request doesn't name one
+    pub default_output_dir: Option<String>,
+    pub ffmpeg_path: Option<String>,
+    pub ffprobe_path: Option<String>,
+    /// Parallel workers; `None` follows the CPU count
+    pub worker_count: Option<usize>,
+    pub last_preset: Option<String>,
+    /// Most recently processed files, newest first
+    pub recent_files: Vec<RecentFile>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: SETTINGS_VERSION,
+            default_output_dir: None,
+            ffmpeg_path: None,
+            ffprobe_path: None,
+            worker_count: None,
+            last_preset: None,
+            recent_files: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Make FFmpeg paths and the worker count take effect for this process
+    pub fn apply(&self) {
+        crate::tools::set_ffmpeg_path(self.ffmpeg_path.clone());
+        crate::tools::set_ffprobe_path(self.ffprobe_path.clone());
+        crate::tools::set_worker_count(self.worker_count);
+    }
+}
+
+/// A file that was converted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecentFile {
+    pub path: String,
+    pub output_path: Option<String>,
+    /// Seconds since the Unix epoch
+    pub processed_at: u64,
+}
+
+/// Settings persisted as JSON in a config directory
+pub struct SettingsStore {
+    path: PathBuf,
+}
+
+impl SettingsStore {
+    /// Store settings in `dir`/settings.json
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        SettingsStore {
+            path: dir.as_ref().join(SETTINGS_FILE),
+        }
+    }
+
+    /// Load settings, migrating files written by older versions.
+    /// A missing file yields the defaults.
+    pub fn load(&self) -> Result<Settings, String> {
+        let Some(value) = read_json::<serde_json::Value>(&self.path)? else {
+            return Ok(Settings::default());
+        };
+        let value = migrate(value)?;
+        serde_json::from_value(value)
+            .map_err(|e| format!("Failed to parse '{}': {}", self.path.display(), e))
+    }
+
+    /// Validate and save new settings, returning them as stored
+    pub fn update(&self, settings: Settings) -> Result<Settings, String> {
+        let _guard = STORE_LOCK.lock().map_err(|e| e.to_string())?;
+        let settings = sanitize(settings)?;
+        write_json(&self.path, &settings)?;
+        Ok(settings)
+    }
+
+    /// Add converted files to the front of the recent-files history
+    pub fn record_recent(&self, files: Vec<RecentFile>) -> Result<Settings, String> {
+        let _guard = STORE_LOCK.lock().map_err(|e| e.to_string())?;
+        let mut settings = self.load()?;
+        for file in files {
+            settings.recent_files.retain(|f| f.path != file.path);
+            settings.recent_files.insert(0, file);
+        }
+        settings.recent_files.truncate(MAX_RECENT_FILES);
+        write_json(&self.path, &settings)?;
+        Ok(settings)
+    }
+}
+
+impl RecentFile {
+    /// Record a file converted just now
+    pub fn now(path: String, output_path: Option<String>) -> Self {
+        let processed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        RecentFile {
+            path,
+            output_path,
+            processed_at,
+        }
+    }
+}
+
+/// Bring a settings document up to `SETTINGS_VERSION`
+///
+/// Each schema change adds a step here that rewrites the JSON of the previous
+/// version, so old files keep loading after an upgrade.
+fn migrate(mut value: serde_json::Value) -> Result<serde_json::Value, String> {
+    // Files written before versioning carry no version field
+    let mut version = value["version"].as_u64().unwrap_or(1) as u32;
+    if version > SETTINGS_VERSION {
+        return Err(format!(
+            "Settings file version {} is newer than supported version {}",
+            version, SETTINGS_VERSION
+        ));
+    }
+
+    while version < SETTINGS_VERSION {
+        // No migrations yet; future steps go here, e.g.
+        // 1 => rename a field, then version = 2
+        version += 1;
+    }
+
+    if let Some(object) = value.as_object_mut() {
+        object.insert("version".to_string(), version.into());
+    }
+    Ok(value)
+}
+
+/// Validate settings and normalize them for storage
+fn sanitize(mut settings: Settings) -> Result<Settings, String> {
+    settings.version = SETTINGS_VERSION;
+    if let Some(count) = settings.worker_count {
+        if count == 0 || count > MAX_WORKERS {
+            return Err(format!(
+                "Worker count must be between 1 and {}, got {}",
+                MAX_WORKERS, count
+            ));
+        }
+    }
+    for path in [
+        &mut settings.default_output_dir,
+        &mut settings.ffmpeg_path,
+        &mut settings.ffprobe_path,
+        &mut settings.last_preset,
+    ] {
+        if path.as_deref().is_some_and(|p| p.trim().is_empty()) {
+            *path = None;
+        }
+    }
+    settings.recent_files.truncate(MAX_RECENT_FILES);
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn temp_store() -> (SettingsStore, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        (SettingsStore::new(dir.path()), dir)
+    }
+
+    #[test]
+    fn test_load_missing_file_gives_defaults() {
+        let (store, _dir) = temp_store();
+        let settings = store.load().unwrap();
+        assert_eq!(settings, Settings::default());
+        assert_eq!(settings.version, SETTINGS_VERSION);
+    }
+
+    #[test]
+    fn test_update_roundtrip() {
+        let (store, _dir) = temp_store();
+        let settings = Settings {
+            default_output_dir: Some("/videos/out".to_string()),
+            ffmpeg_path: Some("".to_string()),
+            worker_count: Some(4),
+            last_preset: Some("Quick 10x".to_string()),
+            ..Default::default()
+        };
+        let saved = store.update(settings).unwrap();
+        assert!(saved.ffmpeg_path.is_none());
+        assert_eq!(store.load().unwrap(), saved);
+    }
+
+    #[test]
+    fn test_update_rejects_invalid_worker_count() {
+        let (store, _dir) = temp_store();
+        let settings = Settings {
+            worker_count: Some(0),
+            ..Default::default()
+        };
+        assert!(store.update(settings).is_err());
+    }
+
+    #[test]
+    fn test_record_recent_dedupes_and_caps() {
+        let (store, _dir) = temp_store();
+        for i in 0..MAX_RECENT_FILES + 5 {
+            store
+                .record_recent(vec![RecentFile::now(format!("/v/{}.mp4", i), None)])
+                .unwrap();
+        }
+        let settings = store
+            .record_recent(vec![RecentFile::now("/v/10.mp4".to_string(), None)])
+            .unwrap();
+        assert_eq!(settings.recent_files.len(), MAX_RECENT_FILES);
+        assert_eq!(settings.recent_files[0].path, "/v/10.mp4");
+        assert_eq!(
+            settings
+                .recent_files
+                .iter()
+                .filter(|f| f.path == "/v/10.mp4")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_migrate_unversioned_file() {
+        let value = serde_json::json!({ "default_output_dir": "/out" });
+        let migrated = migrate(value).unwrap();
+        assert_eq!(migrated["version"], SETTINGS_VERSION);
+        let settings: Settings = serde_json::from_value(migrated).unwrap();
+        assert_eq!(settings.default_output_dir.as_deref(), Some("/out"));
+    }
+
+    #[test]
+    fn test_migrate_rejects_newer_version() {
+        let value = serde_json::json!({ "version": SETTINGS_VERSION + 1 });
+        assert!(migrate(value).unwrap_err().contains("newer"));
+    }
+}
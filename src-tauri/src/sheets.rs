@@ -0,0 +1,522 @@
+use crate::frames::{extract_frame, ImageFormat};
+use crate::tools;
+use crate::video::{output_path_with, probe_concurrency, VideoInfo};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Most frames a single sheet may hold
+pub const MAX_SHEET_FRAMES: usize = 400;
+
+/// Gap between contact sheet tiles, in pixels
+const TILE_PADDING: u32 = 4;
+
+/// Fonts tried for tile labels; without one the tiles are left unlabeled
+#[cfg(target_os = "windows")]
+const LABEL_FONTS: &[&str] = &["C:/Windows/Fonts/arial.ttf", "C:/Windows/Fonts/segoeui.ttf"];
+#[cfg(target_os = "macos")]
+const LABEL_FONTS: &[&str] = &[
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "/System/Library/Fonts/Helvetica.ttc",
+];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const LABEL_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/usr/share/fonts/liberation-sans/LiberationSans-Regular.ttf",
+];
+
+/// Settings of a contact sheet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContactSheetOptions {
+    /// Number of evenly spaced frames
+    pub frames: usize,
+    /// Tiles per row [default: a roughly square grid]
+    pub columns: Option<usize>,
+    pub tile_width: u32,
+    /// Print each frame's source time on its tile, when a font is found
+    pub timestamps: bool,
+    pub format: ImageFormat,
+}
+
+impl Default for ContactSheetOptions {
+    fn default() -> Self {
+        ContactSheetOptions {
+            frames: 24,
+            columns: None,
+            tile_width: 320,
+            timestamps: true,
+            format: ImageFormat::Jpeg,
+        }
+    }
+}
+
+/// Settings of a sprite sheet for web players
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpriteSheetOptions {
+    /// Number of thumbnails, each covering an equal part of the video
+    pub frames: usize,
+    pub columns: usize,
+    pub tile_width: u32,
+    pub format: ImageFormat,
+}
+
+impl Default for SpriteSheetOptions {
+    fn default() -> Self {
+        SpriteSheetOptions {
+            frames: 100,
+            columns: 10,
+            tile_width: 160,
+            format: ImageFormat::Jpeg,
+        }
+    }
+}
+
+/// A written contact sheet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContactSheet {
+    pub image_path: String,
+    /// Source time of each tile, in reading order
+    pub timestamps: Vec<f64>,
+}
+
+/// A written sprite sheet and the WebVTT track pointing into it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpriteSheet {
+    pub image_path: String,
+    pub vtt_path: String,
+    pub columns: usize,
+    pub rows: usize,
+    pub tile_width: u32,
+    pub tile_height: u32,
+}
+
+/// Grid layout shared by both kinds of sheet
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Layout {
+    columns: usize,
+    rows: usize,
+    tile_width: u32,
+    tile_height: u32,
+}
+
+impl Layout {
+    fn new(info: &VideoInfo, frames: usize, columns: usize, tile_width: u32) -> Self {
+        let columns = columns.clamp(1, frames);
+        let tile_width = tile_width.max(2) / 2 * 2;
+        let (width, height) = (info.display_width.max(1), info.display_height.max(1));
+        let tile_height =
+            ((tile_width as f64 * height as f64 / width as f64).round() as u32).max(2) / 2 * 2;
+        Layout {
+            columns,
+            rows: frames.div_ceil(columns),
+            tile_width,
+            tile_height,
+        }
+    }
+}
+
+/// Write a grid of evenly spaced frames of a video to `{stem}_contact_sheet`
+/// in the output directory
+///
+/// Frames come from `extract_frame`, so each one is the keyframe at or before
+/// its tile's timestamp.
+pub fn contact_sheet(
+    info: &VideoInfo,
+    output_dir: Option<&str>,
+    options: &ContactSheetOptions,
+) -> Result<ContactSheet, String> {
+    check_sheet(info, options.frames)?;
+    let columns = options
+        .columns
+        .unwrap_or_else(|| (options.frames as f64).sqrt().ceil() as usize);
+    let layout = Layout::new(info, options.frames, columns, options.tile_width);
+
+    let timestamps = sample_timestamps(info.duration_secs, options.frames);
+    let images = extract_frames(&info.path, &timestamps, layout.tile_width)?;
+    let labels: Vec<Option<String>> = timestamps
+        .iter()
+        .map(|&t| options.timestamps.then(|| format_clock(t)))
+        .collect();
+
+    let suffix = format!("_contact_sheet.{}", options.format.extension());
+    let output = output_path_with(&info.path, output_dir, &suffix);
+    compose(&images, &labels, layout, TILE_PADDING, &output)?;
+
+    Ok(ContactSheet {
+        image_path: output.to_string_lossy().to_string(),
+        timestamps,
+    })
+}
+
+/// Write a sprite sheet of thumbnails and a WebVTT track mapping each part
+/// of the video to its thumbnail, as `{stem}_sprites` and
+/// `{stem}_thumbnails.vtt` in the output directory
+pub fn sprite_sheet(
+    info: &VideoInfo,
+    output_dir: Option<&str>,
+    options: &SpriteSheetOptions,
+) -> Result<SpriteSheet, String> {
+    check_sheet(info, options.frames)?;
+    let layout = Layout::new(info, options.frames, options.columns, options.tile_width);
+
+    let timestamps = sample_timestamps(info.duration_secs, options.frames);
+    let images = extract_frames(&info.path, &timestamps, layout.tile_width)?;
+    let suffix = format!("_sprites.{}", options.format.extension());
+    let image = output_path_with(&info.path, output_dir, &suffix);
+    compose(&images, &vec![None; images.len()], layout, 0, &image)?;
+
+    let image_name = image
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let vtt = output_path_with(&info.path, output_dir, "_thumbnails.vtt");
+    std::fs::write(
+        &vtt,
+        build_vtt(&image_name, info.duration_secs, options.frames, layout),
+    )
+    .map_err(|e| format!("Failed to write '{}': {}", vtt.display(), e))?;
+
+    Ok(SpriteSheet {
+        image_path: image.to_string_lossy().to_string(),
+        vtt_path: vtt.to_string_lossy().to_string(),
+        columns: layout.columns,
+        rows: layout.rows,
+        tile_width: layout.tile_width,
+        tile_height: layout.tile_height,
+    })
+}
+
+fn check_sheet(info: &VideoInfo, frames: usize) -> Result<(), String> {
+    if !info.valid {
+        return Err(info
+            .error
+            .clone()
+            .unwrap_or_else(|| "Invalid video".to_string()));
+    }
+    if info.duration_secs <= 0.0 {
+        return Err("Video has no duration".to_string());
+    }
+    if frames == 0 || frames > MAX_SHEET_FRAMES {
+        return Err(format!(
+            "A sheet holds between 1 and {} frames",
+            MAX_SHEET_FRAMES
+        ));
+    }
+    Ok(())
+}
+
+/// Middle of each of `count` equal parts of the video
+pub fn sample_timestamps(duration_secs: f64, count: usize) -> Vec<f64> {
+    (0..count)
+        .map(|i| duration_secs * (i as f64 + 0.5) / count as f64)
+        .collect()
+}
+
+/// Extract frames on a few threads at once, keeping their order
+fn extract_frames(path: &str, timestamps: &[f64], width: u32) -> Result<Vec<String>, String> {
+    let chunk = timestamps.len().div_ceil(probe_concurrency()).max(1);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = timestamps
+            .chunks(chunk)
+            .map(|part| {
+                scope.spawn(move || {
+                    part.iter()
+                        .map(|&t| extract_frame(path, t, width, ImageFormat::Png))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+            })
+            .collect();
+        let mut images = Vec::with_capacity(timestamps.len());
+        for worker in workers {
+            let part = worker
+                .join()
+                .map_err(|_| "Frame extraction failed".to_string())??;
+            images.extend(part);
+        }
+        Ok(images)
+    })
+}
+
+/// Tile the images into one picture with FFmpeg
+///
+/// The images are read through a concat list and the filter graph from a
+/// script file, so the command line stays short however many tiles there are.
+fn compose(
+    images: &[String],
+    labels: &[Option<String>],
+    layout: Layout,
+    padding: u32,
+    output: &Path,
+) -> Result<(), String> {
+    if let Some(dir) = output.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create '{}': {}", dir.display(), e))?;
+    }
+
+    let font = if labels.iter().any(Option::is_some) {
+        label_font()
+    } else {
+        None
+    };
+    let list = PathBuf::from(format!("{}.tiles.txt", output.display()));
+    let script = PathBuf::from(format!("{}.filter.txt", output.display()));
+    let written = std::fs::write(&list, build_concat_list(images)).and_then(|_| {
+        std::fs::write(
+            &script,
+            build_sheet_filter(labels, layout, padding, font.as_deref()),
+        )
+    });
+    let result = match written {
+        Ok(()) => Command::new(tools::ffmpeg())
+            .args(build_compose_args(&list, &script, output))
+            .output()
+            .map_err(|e| format!("Failed to run FFmpeg: {}", e)),
+        Err(e) => Err(format!("Failed to write '{}': {}", list.display(), e)),
+    };
+    let _ = std::fs::remove_file(&list);
+    let _ = std::fs::remove_file(&script);
+
+    let result = result?;
+    if !result.status.success() {
+        return Err(format!(
+            "FFmpeg failed to write '{}': {}",
+            output.display(),
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// First label font present on this system
+fn label_font() -> Option<PathBuf> {
+    LABEL_FONTS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.is_file())
+}
+
+fn build_compose_args(list: &Path, script: &Path, output: &Path) -> Vec<String> {
+    vec![
+        "-y".to_string(),
+        "-loglevel".to_string(),
+        "error".to_string(),
+        "-f".to_string(),
+        "concat".to_string(),
+        "-safe".to_string(),
+        "0".to_string(),
+        "-i".to_string(),
+        list.to_string_lossy().to_string(),
+        "-filter_complex_script".to_string(),
+        script.to_string_lossy().to_string(),
+        "-map".to_string(),
+        "[sheet]".to_string(),
+        "-frames:v".to_string(),
+        "1".to_string(),
+        output.to_string_lossy().to_string(),
+    ]
+}
+
+/// Concat demuxer list reading each image as one frame, in order
+fn build_concat_list(images: &[String]) -> String {
+    let mut list = String::from("ffconcat version 1.0\n");
+    for image in images {
+        list.push_str(&format!("file '{}'\n", image.replace('\'', "'\\''")));
+    }
+    list
+}
+
+/// Filter graph fitting every frame into an equal tile, labelling it when
+/// there is a font and laying the tiles out in a grid
+fn build_sheet_filter(
+    labels: &[Option<String>],
+    layout: Layout,
+    padding: u32,
+    font: Option<&Path>,
+) -> String {
+    let (w, h) = (layout.tile_width, layout.tile_height);
+    let mut graph = format!(
+        "[0:v]scale={w}:{h}:force_original_aspect_ratio=decrease,\
+         pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1"
+    );
+    if let Some(font) = font {
+        let font = escape_filter_value(&font.to_string_lossy().replace('\\', "/"));
+        let font_size = (h / 10).max(12);
+        for (i, label) in labels.iter().enumerate() {
+            let Some(label) = label else { continue };
+            graph.push_str(&format!(
+                ",drawtext=fontfile='{}':text='{}':enable='eq(n,{})':x=6:y=h-th-6:\
+                 fontsize={}:fontcolor=white:box=1:boxcolor=black@0.6:boxborderw=4",
+                font,
+                escape_filter_value(label),
+                i,
+                font_size
+            ));
+        }
+    }
+    graph.push_str(&format!(
+        ",tile={}x{}:padding={p}:margin={p}[sheet]",
+        layout.columns,
+        layout.rows,
+        p = padding
+    ));
+    graph
+}
+
+/// Escape a value for use inside single quotes in a filter option
+fn escape_filter_value(value: &str) -> String {
+    value.replace('\'', "").replace(':', "\\:")
+}
+
+/// WebVTT track with one cue per thumbnail, each pointing at its tile with a
+/// media fragment
+fn build_vtt(image_name: &str, duration_secs: f64, frames: usize, layout: Layout) -> String {
+    let mut vtt = String::from("WEBVTT\n");
+    let step = duration_secs / frames as f64;
+    for i in 0..frames {
+        let x = (i % layout.columns) as u32 * layout.tile_width;
+        let y = (i / layout.columns) as u32 * layout.tile_height;
+        vtt.push_str(&format!(
+            "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+            format_vtt_time(step * i as f64),
+            format_vtt_time((step * (i + 1) as f64).min(duration_secs)),
+            image_name,
+            x,
+            y,
+            layout.tile_width,
+            layout.tile_height
+        ));
+    }
+    vtt
+}
+
+/// `HH:MM:SS`, as printed on contact sheet tiles
+fn format_clock(secs: f64) -> String {
+    let total = secs.max(0.0) as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        total / 3600,
+        total / 60 % 60,
+        total % 60
+    )
+}
+
+/// `HH:MM:SS.mmm`, as WebVTT cue times are written
+fn format_vtt_time(secs: f64) -> String {
+    let millis = (secs.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(width: u32, height: u32, duration_secs: f64) -> VideoInfo {
+        VideoInfo {
+            path: "/videos/day.mp4".to_string(),
+            valid: true,
+            duration_secs,
+            width,
+            height,
+            display_width: width,
+            display_height: height,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sample_timestamps() {
+        assert_eq!(
+            sample_timestamps(86400.0, 4),
+            vec![10800.0, 32400.0, 54000.0, 75600.0]
+        );
+        assert!(sample_timestamps(10.0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_layout() {
+        let layout = Layout::new(&info(1920, 1080, 60.0), 24, 5, 321);
+        assert_eq!(layout.columns, 5);
+        assert_eq!(layout.rows, 5);
+        assert_eq!((layout.tile_width, layout.tile_height), (320, 180));
+
+        // Portrait video, fewer frames than columns
+        let layout = Layout::new(&info(1080, 1920, 60.0), 3, 10, 160);
+        assert_eq!((layout.columns, layout.rows), (3, 1));
+        assert_eq!(layout.tile_height, 284);
+    }
+
+    #[test]
+    fn test_check_sheet() {
+        assert!(check_sheet(&info(1920, 1080, 60.0), 24).is_ok());
+        assert!(check_sheet(&info(1920, 1080, 0.0), 24).is_err());
+        assert!(check_sheet(&info(1920, 1080, 60.0), 0).is_err());
+        assert!(check_sheet(&info(1920, 1080, 60.0), MAX_SHEET_FRAMES + 1).is_err());
+        let invalid = VideoInfo::invalid("a.mp4", "a.mp4", "Broken".to_string());
+        assert_eq!(check_sheet(&invalid, 24), Err("Broken".to_string()));
+    }
+
+    #[test]
+    fn test_build_sheet_filter() {
+        let layout = Layout::new(&info(1920, 1080, 60.0), 2, 2, 320);
+        let labels = vec![None, Some("00:00:45".to_string())];
+        let graph = build_sheet_filter(&labels, layout, 4, Some(Path::new("C:\\Fonts\\a.ttf")));
+        assert!(graph.starts_with("[0:v]scale=320:180:force_original_aspect_ratio=decrease"));
+        assert!(graph
+            .contains("drawtext=fontfile='C\\:/Fonts/a.ttf':text='00\\:00\\:45':enable='eq(n,1)'"));
+        assert_eq!(graph.matches("drawtext").count(), 1);
+        assert!(graph.ends_with(",tile=2x1:padding=4:margin=4[sheet]"));
+
+        // Without a font the tiles are left unlabeled
+        let graph = build_sheet_filter(&labels, layout, 4, None);
+        assert!(!graph.contains("drawtext"));
+    }
+
+    #[test]
+    fn test_compose_inputs() {
+        let list = build_concat_list(&["/cache/a.png".to_string(), "/it's/b.png".to_string()]);
+        assert_eq!(
+            list,
+            "ffconcat version 1.0\nfile '/cache/a.png'\nfile '/it'\\''s/b.png'\n"
+        );
+
+        // One input however many tiles there are
+        let args = build_compose_args(
+            Path::new("/out/a.jpg.tiles.txt"),
+            Path::new("/out/a.jpg.filter.txt"),
+            Path::new("/out/a.jpg"),
+        );
+        assert_eq!(args.iter().filter(|a| *a == "-i").count(), 1);
+        assert!(args.contains(&"-filter_complex_script".to_string()));
+        assert_eq!(args.last().unwrap(), "/out/a.jpg");
+    }
+
+    #[test]
+    fn test_build_vtt() {
+        let layout = Layout::new(&info(1920, 1080, 100.0), 3, 2, 160);
+        let vtt = build_vtt("day_sprites.jpg", 100.0, 3, layout);
+        let expected = "WEBVTT\n\
+            \n00:00:00.000 --> 00:00:33.333\nday_sprites.jpg#xywh=0,0,160,90\n\
+            \n00:00:33.333 --> 00:01:06.667\nday_sprites.jpg#xywh=160,0,160,90\n\
+            \n00:01:06.667 --> 00:01:40.000\nday_sprites.jpg#xywh=0,90,160,90\n";
+        assert_eq!(vtt, expected);
+    }
+
+    #[test]
+    fn test_format_times() {
+        assert_eq!(format_clock(3725.9), "01:02:05");
+        assert_eq!(format_clock(90000.0), "25:00:00");
+        assert_eq!(format_vtt_time(3725.25), "01:02:05.250");
+    }
+}
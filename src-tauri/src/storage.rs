@@ -0,0 +1,63 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+
+/// Read a JSON document, returning `None` when the file doesn't exist yet
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+    };
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| format!("Failed to parse '{}': {}", path.display(), e))
+}
+
+/// Write a JSON document atomically: the data goes to a temporary file that
+/// then replaces the target, so a crash never leaves a half-written file
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create '{}': {}", parent.display(), e))?;
+    }
+
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize '{}': {}", path.display(), e))?;
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, json)
+        .map_err(|e| format!("Failed to write '{}': {}", tmp_path.display(), e))?;
+    std::fs::rename(&tmp_path, path)
+        .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_json_missing_file() {
+        let value: Option<Vec<u32>> =
+            read_json(Path::new("/nonexistent/timelapse/file.json")).unwrap();
+        assert!(value.is_none());
+    }
+
+    #[test]
+    fn test_write_then_read_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("values.json");
+        write_json(&path, &vec![1u32, 2, 3]).unwrap();
+        let value: Option<Vec<u32>> = read_json(&path).unwrap();
+        assert_eq!(value, Some(vec![1, 2, 3]));
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn test_read_json_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("invalid.json");
+        std::fs::write(&path, "not json").unwrap();
+        let result: Result<Option<Vec<u32>>, String> = read_json(&path);
+        assert!(result.unwrap_err().contains("Failed to parse"));
+    }
+}
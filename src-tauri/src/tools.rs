@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+static FFMPEG_PATH: RwLock<Option<String>> = RwLock::new(None);
+static FFPROBE_PATH: RwLock<Option<String>> = RwLock::new(None);
+/// Configured worker count, 0 when it should follow the CPU count
+static WORKER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// FFmpeg executable to run, `ffmpeg` from PATH unless configured
+pub fn ffmpeg() -> String {
+    configured(&FFMPEG_PATH).unwrap_or_else(|| "ffmpeg".to_string())
+}
+
+/// FFprobe executable to run, `ffprobe` from PATH unless configured
+pub fn ffprobe() -> String {
+    configured(&FFPROBE_PATH).unwrap_or_else(|| "ffprobe".to_string())
+}
+
+/// Use a specific FFmpeg executable; `None` or an empty path restores the default
+pub fn set_ffmpeg_path(path: Option<String>) {
+    configure(&FFMPEG_PATH, path);
+}
+
+/// Use a specific FFprobe executable; `None` or an empty path restores the default
+pub fn set_ffprobe_path(path: Option<String>) {
+    configure(&FFPROBE_PATH, path);
+}
+
+/// Configured number of parallel workers, if any
+pub fn worker_count() -> Option<usize> {
+    match WORKER_COUNT.load(Ordering::Relaxed) {
+        0 => None,
+        n => Some(n),
+    }
+}
+
+/// Limit the number of parallel workers; `None` follows the CPU count
+pub fn set_worker_count(count: Option<usize>) {
+    WORKER_COUNT.store(count.unwrap_or(0), Ordering::Relaxed);
+}
+
+fn configured(lock: &RwLock<Option<String>>) -> Option<String> {
+    lock.read().ok().and_then(|path| path.clone())
+}
+
+fn configure(lock: &RwLock<Option<String>>, path: Option<String>) {
+    if let Ok(mut current) = lock.write() {
+        *current = path.filter(|p| !p.trim().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configure_ignores_empty_paths() {
+        let lock = RwLock::new(None);
+        configure(&lock, Some("/opt/ffmpeg/bin/ffmpeg".to_string()));
+        assert_eq!(configured(&lock).as_deref(), Some("/opt/ffmpeg/bin/ffmpeg"));
+        configure(&lock, Some("  ".to_string()));
+        assert!(configured(&lock).is_none());
+    }
+}
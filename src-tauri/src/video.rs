@@ -1,6 +1,11 @@
+use crate::formats::{OutputFormat, HLS_PLAYLIST_NAME};
+use crate::tools;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
 
 /// Supported video formats for timelapse conversion
 pub const SUPPORTED_FORMATS: &[&str] = &[
@@ -8,7 +13,8 @@ pub const SUPPORTED_FORMATS: &[&str] = &[
 ];
 
 /// Video information structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct VideoInfo {
     pub path: String,
     pub filename: String,
@@ -16,9 +22,153 @@ pub struct VideoInfo {
     pub width: u32,
     pub height: u32,
     pub fps: f64,
+    /// Average frame rate (`avg_frame_rate`), 0 when ffprobe doesn't report it
+    pub avg_fps: f64,
+    /// True when the average frame rate differs from the nominal one
+    pub is_vfr: bool,
     pub total_frames: u64,
+    /// True when `total_frames` comes from an exact count rather than an estimate
+    pub frames_exact: bool,
     pub valid: bool,
     pub error: Option<String>,
+    /// Video codec name (e.g. "h264", "hevc")
+    pub codec: Option<String>,
+    /// Codec profile (e.g. "High", "Main 10")
+    pub profile: Option<String>,
+    /// Pixel format (e.g. "yuv420p", "yuv420p10le")
+    pub pix_fmt: Option<String>,
+    /// Bits per color component
+    pub bit_depth: Option<u32>,
+    /// Overall bitrate in bits per second
+    pub bit_rate: Option<u64>,
+    /// File size in bytes
+    pub file_size: Option<u64>,
+    pub has_audio: bool,
+    pub audio_codec: Option<String>,
+    /// Clockwise rotation in degrees to apply for display (0, 90, 180 or 270)
+    pub rotation: u32,
+    /// Width after applying `rotation`, i.e. as the video is meant to be viewed
+    pub display_width: u32,
+    /// Height after applying `rotation`
+    pub display_height: u32,
+    pub color_primaries: Option<String>,
+    pub color_transfer: Option<String>,
+    /// True when the transfer characteristics are PQ or HLG
+    pub is_hdr: bool,
+    pub interlaced: bool,
+    /// Container or stream creation time as reported by ffprobe (ISO 8601)
+    pub creation_time: Option<String>,
+    /// Frame count stored in the container, if any
+    pub nb_frames: Option<u64>,
+    /// Container `title` tag
+    pub title: Option<String>,
+    /// Container `comment` tag
+    pub comment: Option<String>,
+    /// How the file was made, when it is an output of this app
+    pub provenance: Option<Provenance>,
+    /// Cached poster thumbnail, when one has been extracted
+    pub poster_path: Option<String>,
+}
+
+/// Prefix of the custom container tags written to converted files
+pub const PROVENANCE_TAG_PREFIX: &str = "timelapse_";
+
+/// How a timelapse was made, stored as custom container tags
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Provenance {
+    /// File name of the source video
+    pub source: Option<String>,
+    pub speed_multiplier: Option<u32>,
+    pub trim_start_secs: Option<f64>,
+    pub trim_end_secs: Option<f64>,
+    /// Version of the app that made the file
+    pub app_version: Option<String>,
+    /// `creation_time` of the source video
+    pub source_creation_time: Option<String>,
+}
+
+impl Provenance {
+    /// Tag names and values, ready for `-metadata key=value`
+    pub fn tags(&self) -> Vec<(String, String)> {
+        let values = [
+            ("source", self.source.clone()),
+            ("speed", self.speed_multiplier.map(|s| s.to_string())),
+            (
+                "trim_start",
+                self.trim_start_secs.map(|s| format!("{:.3}", s)),
+            ),
+            ("trim_end", self.trim_end_secs.map(|s| format!("{:.3}", s))),
+            ("app_version", self.app_version.clone()),
+            ("source_creation_time", self.source_creation_time.clone()),
+        ];
+        values
+            .into_iter()
+            .filter_map(|(key, value)| Some((format!("{}{}", PROVENANCE_TAG_PREFIX, key), value?)))
+            .collect()
+    }
+
+    /// Read provenance from ffprobe's `tags` object; `None` when the file
+    /// carries none of our tags
+    fn from_tags(tags: &serde_json::Value) -> Option<Self> {
+        let tags = tags.as_object()?;
+        let mut provenance = Provenance::default();
+        let mut found = false;
+        for (key, value) in tags {
+            // Some muxers change the case of custom keys
+            let key = key.to_lowercase();
+            let Some(key) = key.strip_prefix(PROVENANCE_TAG_PREFIX) else {
+                continue;
+            };
+            let Some(value) = value.as_str() else {
+                continue;
+            };
+            found = true;
+            match key {
+                "source" => provenance.source = Some(value.to_string()),
+                "speed" => provenance.speed_multiplier = value.parse().ok(),
+                "trim_start" => provenance.trim_start_secs = value.parse().ok(),
+                "trim_end" => provenance.trim_end_secs = value.parse().ok(),
+                "app_version" => provenance.app_version = Some(value.to_string()),
+                "source_creation_time" => provenance.source_creation_time = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        found.then_some(provenance)
+    }
+}
+
+impl VideoInfo {
+    /// Create an invalid entry carrying an error message
+    pub fn invalid(path: &str, filename: &str, error: String) -> Self {
+        VideoInfo {
+            path: path.to_string(),
+            filename: filename.to_string(),
+            valid: false,
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    /// Return a copy with a different display rotation, e.g. a manual override.
+    /// The rotation is normalized to clockwise degrees in 0..360.
+    pub fn with_rotation(mut self, rotation: u32) -> Self {
+        self.rotation = rotation % 360;
+        let (display_width, display_height) =
+            display_dimensions(self.width, self.height, self.rotation);
+        self.display_width = display_width;
+        self.display_height = display_height;
+        self
+    }
+
+    /// True when the pixel format is anything other than 8-bit 4:2:0,
+    /// which is what most players expect from an H.264 file
+    pub fn needs_pixel_format_conversion(&self) -> bool {
+        match self.pix_fmt.as_deref() {
+            Some(fmt) => fmt != "yuv420p" && fmt != "yuvj420p",
+            None => false,
+        }
+    }
 }
 
 /// Check if a file extension is a supported video format
@@ -39,7 +189,7 @@ pub fn get_info(path: &str) -> VideoInfo {
         .unwrap_or_else(|| "Unknown".to_string());
 
     // Run ffprobe to get video information
-    let output = Command::new("ffprobe")
+    let output = Command::new(tools::ffprobe())
         .args([
             "-v",
             "quiet",
@@ -54,32 +204,117 @@ pub fn get_info(path: &str) -> VideoInfo {
     match output {
         Ok(output) => {
             if output.status.success() {
-                parse_ffprobe_output(path, &filename, &output.stdout)
+                let mut info = parse_ffprobe_output(path, &filename, &output.stdout);
+                apply_cached_frame_count(&mut info);
+                info
             } else {
-                VideoInfo {
-                    path: path.to_string(),
-                    filename,
-                    duration_secs: 0.0,
-                    width: 0,
-                    height: 0,
-                    fps: 0.0,
-                    total_frames: 0,
-                    valid: false,
-                    error: Some("FFprobe failed to analyze video".to_string()),
-                }
+                VideoInfo::invalid(
+                    path,
+                    &filename,
+                    "FFprobe failed to analyze video".to_string(),
+                )
             }
         }
-        Err(e) => VideoInfo {
-            path: path.to_string(),
-            filename,
-            duration_secs: 0.0,
-            width: 0,
-            height: 0,
-            fps: 0.0,
-            total_frames: 0,
-            valid: false,
-            error: Some(format!("Failed to run ffprobe: {}", e)),
-        },
+        Err(e) => VideoInfo::invalid(path, &filename, format!("Failed to run ffprobe: {}", e)),
+    }
+}
+
+/// Get video information, reusing an earlier probe of the same file
+///
+/// Probes are cached by path, size and modification time, so re-adding an
+/// unchanged file is free. Only valid results are cached.
+pub fn get_info_cached(path: &str) -> VideoInfo {
+    let stamp = FileStamp::of(path);
+    if let Some(mut info) = stamp.as_ref().and_then(lookup_probe) {
+        apply_cached_frame_count(&mut info);
+        return info;
+    }
+
+    let info = get_info(path);
+    if let Some(stamp) = stamp {
+        if info.valid {
+            store_probe(stamp, &info);
+        }
+    }
+    info
+}
+
+/// Probe many files concurrently on blocking threads
+///
+/// At most `probe_concurrency()` ffprobe processes run at once. `on_ready` is
+/// called with the input index as each probe finishes; the returned list is
+/// in input order.
+pub async fn get_info_many<F>(paths: Vec<String>, mut on_ready: F) -> Vec<VideoInfo>
+where
+    F: FnMut(usize, &VideoInfo),
+{
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(probe_concurrency()));
+    let mut tasks = tokio::task::JoinSet::new();
+
+    for (index, path) in paths.iter().cloned().enumerate() {
+        let semaphore = semaphore.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let probe_path = path.clone();
+            let info = tokio::task::spawn_blocking(move || get_info_cached(&probe_path))
+                .await
+                .unwrap_or_else(|e| {
+                    VideoInfo::invalid(&path, &path, format!("Probe task failed: {}", e))
+                });
+            (index, info)
+        });
+    }
+
+    let mut results: Vec<Option<VideoInfo>> = vec![None; paths.len()];
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((index, info)) = joined {
+            on_ready(index, &info);
+            results[index] = Some(info);
+        }
+    }
+
+    results
+        .into_iter()
+        .zip(paths)
+        .map(|(info, path)| {
+            info.unwrap_or_else(|| {
+                VideoInfo::invalid(&path, &path, "Probe task failed".to_string())
+            })
+        })
+        .collect()
+}
+
+/// Number of ffprobe processes allowed to run at the same time:
+/// the configured worker count, or a value based on the CPU count
+pub fn probe_concurrency() -> usize {
+    tools::worker_count().unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4)
+            .clamp(2, 8)
+    })
+}
+
+fn probe_cache() -> &'static Mutex<HashMap<FileStamp, VideoInfo>> {
+    static CACHE: OnceLock<Mutex<HashMap<FileStamp, VideoInfo>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn lookup_probe(stamp: &FileStamp) -> Option<VideoInfo> {
+    probe_cache().lock().ok()?.get(stamp).cloned()
+}
+
+fn store_probe(stamp: FileStamp, info: &VideoInfo) {
+    if let Ok(mut cache) = probe_cache().lock() {
+        cache.insert(stamp, info.clone());
+    }
+}
+
+/// Replace the estimated frame count with an exact one if it was counted before
+fn apply_cached_frame_count(info: &mut VideoInfo) {
+    if let Some(count) = best_cached_frame_count(&info.path) {
+        info.total_frames = count;
+        info.frames_exact = true;
     }
 }
 
@@ -88,17 +323,11 @@ fn parse_ffprobe_output(path: &str, filename: &str, stdout: &[u8]) -> VideoInfo
     let json: serde_json::Value = match serde_json::from_str(&json_str) {
         Ok(v) => v,
         Err(e) => {
-            return VideoInfo {
-                path: path.to_string(),
-                filename: filename.to_string(),
-                duration_secs: 0.0,
-                width: 0,
-                height: 0,
-                fps: 0.0,
-                total_frames: 0,
-                valid: false,
-                error: Some(format!("Failed to parse ffprobe output: {}", e)),
-            }
+            return VideoInfo::invalid(
+                path,
+                filename,
+                format!("Failed to parse ffprobe output: {}", e),
+            )
         }
     };
 
@@ -106,57 +335,280 @@ fn parse_ffprobe_output(path: &str, filename: &str, stdout: &[u8]) -> VideoInfo
     let streams = json["streams"].as_array();
     let format = &json["format"];
 
-    let mut width = 0u32;
-    let mut height = 0u32;
-    let mut fps = 0.0f64;
+    let mut info = VideoInfo {
+        path: path.to_string(),
+        filename: filename.to_string(),
+        ..Default::default()
+    };
 
     if let Some(streams) = streams {
-        for stream in streams {
-            if stream["codec_type"].as_str() == Some("video") {
-                width = stream["width"].as_u64().unwrap_or(0) as u32;
-                height = stream["height"].as_u64().unwrap_or(0) as u32;
-
-                // Parse frame rate (can be "30/1" or "29.97")
-                if let Some(fps_str) = stream["r_frame_rate"].as_str() {
-                    fps = parse_fps(fps_str);
-                }
-                break;
+        let video = streams
+            .iter()
+            .find(|s| s["codec_type"].as_str() == Some("video"));
+        let audio = streams
+            .iter()
+            .find(|s| s["codec_type"].as_str() == Some("audio"));
+
+        if let Some(stream) = video {
+            info.width = stream["width"].as_u64().unwrap_or(0) as u32;
+            info.height = stream["height"].as_u64().unwrap_or(0) as u32;
+
+            // Parse frame rate (can be "30/1" or "29.97")
+            if let Some(fps_str) = stream["r_frame_rate"].as_str() {
+                info.fps = parse_fps(fps_str);
             }
+            info.avg_fps = stream["avg_frame_rate"]
+                .as_str()
+                .filter(|s| *s != "0/0")
+                .map(parse_fps)
+                .unwrap_or(0.0);
+            info.is_vfr = is_variable_frame_rate(info.fps, info.avg_fps);
+
+            info.codec = json_string(&stream["codec_name"]);
+            info.profile = json_string(&stream["profile"]);
+            info.pix_fmt = json_string(&stream["pix_fmt"]);
+            info.bit_depth = json_u64(&stream["bits_per_raw_sample"])
+                .map(|b| b as u32)
+                .or_else(|| info.pix_fmt.as_deref().map(bit_depth_from_pix_fmt));
+            info = info.with_rotation(parse_rotation(stream));
+            info.color_primaries = json_string(&stream["color_primaries"]);
+            info.color_transfer = json_string(&stream["color_transfer"]);
+            info.is_hdr = matches!(
+                info.color_transfer.as_deref(),
+                Some("smpte2084") | Some("arib-std-b67")
+            );
+            info.interlaced = matches!(
+                stream["field_order"].as_str(),
+                Some("tt") | Some("bb") | Some("tb") | Some("bt")
+            );
+            info.nb_frames = json_u64(&stream["nb_frames"]).filter(|&n| n > 0);
+            info.creation_time = json_string(&stream["tags"]["creation_time"]);
+        }
+
+        if let Some(stream) = audio {
+            info.has_audio = true;
+            info.audio_codec = json_string(&stream["codec_name"]);
         }
     }
 
     // Extract duration
-    let duration_secs = format["duration"]
+    info.duration_secs = format["duration"]
         .as_str()
         .and_then(|s| s.parse::<f64>().ok())
         .unwrap_or(0.0);
 
-    let total_frames = (duration_secs * fps).round() as u64;
+    info.bit_rate = json_u64(&format["bit_rate"]);
+    info.file_size = json_u64(&format["size"]);
+    if let Some(creation_time) = json_string(&format["tags"]["creation_time"]) {
+        info.creation_time = Some(creation_time);
+    }
+    info.title = json_string(&format["tags"]["title"]);
+    info.comment = json_string(&format["tags"]["comment"]);
+    info.provenance = Provenance::from_tags(&format["tags"]);
 
-    VideoInfo {
-        path: path.to_string(),
-        filename: filename.to_string(),
-        duration_secs,
-        width,
-        height,
-        fps,
-        total_frames,
-        valid: width > 0 && height > 0 && duration_secs > 0.0,
-        error: if width > 0 && height > 0 && duration_secs > 0.0 {
-            None
-        } else {
-            Some(format!(
-                "Invalid video metadata: {}",
-                if width == 0 || height == 0 {
-                    "missing resolution"
-                } else {
-                    "missing duration"
-                }
-            ))
-        },
+    // For VFR sources the nominal rate is usually a ceiling (or a timebase),
+    // so the average rate gives a far better estimate
+    let estimate_fps = if info.is_vfr { info.avg_fps } else { info.fps };
+    info.total_frames = info
+        .nb_frames
+        .unwrap_or_else(|| (info.duration_secs * estimate_fps).round() as u64);
+
+    let has_resolution = info.width > 0 && info.height > 0;
+    info.valid = has_resolution && info.duration_secs > 0.0;
+    if !info.valid {
+        info.error = Some(format!(
+            "Invalid video metadata: {}",
+            if !has_resolution {
+                "missing resolution"
+            } else {
+                "missing duration"
+            }
+        ));
+    }
+
+    info
+}
+
+/// Read a non-empty string field from ffprobe JSON
+fn json_string(value: &serde_json::Value) -> Option<String> {
+    value
+        .as_str()
+        .filter(|s| !s.is_empty() && *s != "unknown")
+        .map(|s| s.to_string())
+}
+
+/// Read an unsigned integer that ffprobe may report as a number or a string
+fn json_u64(value: &serde_json::Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+/// Infer the bit depth from a pixel format name (e.g. "yuv420p10le" -> 10)
+fn bit_depth_from_pix_fmt(pix_fmt: &str) -> u32 {
+    let trimmed = pix_fmt.trim_end_matches("le").trim_end_matches("be");
+    let head = trimmed.trim_end_matches(|c: char| c.is_ascii_digit());
+    let digits = &trimmed[head.len()..];
+    if head.ends_with('p') {
+        digits.parse().ok().filter(|&d| d > 8).unwrap_or(8)
+    } else {
+        8
+    }
+}
+
+/// Swap width and height for quarter-turn rotations
+pub fn display_dimensions(width: u32, height: u32, rotation: u32) -> (u32, u32) {
+    if rotation % 180 == 90 {
+        (height, width)
+    } else {
+        (width, height)
+    }
+}
+
+/// Check that a manual rotation is a multiple of 90 degrees and normalize it
+pub fn normalize_rotation(rotation: u32) -> Result<u32, String> {
+    if rotation.is_multiple_of(90) {
+        Ok(rotation % 360)
+    } else {
+        Err(format!(
+            "Rotation must be a multiple of 90 degrees, got {}",
+            rotation
+        ))
+    }
+}
+
+/// Parse the display rotation of a video stream
+///
+/// Newer ffprobe versions report a display matrix in `side_data_list` with a
+/// counter-clockwise `rotation`, older ones a clockwise `rotate` tag.
+/// The result is normalized to clockwise degrees in 0..360.
+fn parse_rotation(stream: &serde_json::Value) -> u32 {
+    let side_data_rotation = stream["side_data_list"].as_array().and_then(|list| {
+        list.iter()
+            .filter(|d| d["side_data_type"].as_str() == Some("Display Matrix"))
+            .find_map(|d| d["rotation"].as_f64())
+    });
+
+    let clockwise = match side_data_rotation {
+        Some(rotation) => -rotation,
+        None => stream["tags"]["rotate"]
+            .as_str()
+            .and_then(|s| s.parse::<f64>().ok())
+            .unwrap_or(0.0),
+    };
+
+    // Snap to the nearest quarter turn
+    let quarter_turns = (clockwise / 90.0).round() as i64;
+    (quarter_turns.rem_euclid(4) * 90) as u32
+}
+
+/// Compare nominal and average frame rates, allowing for rounding in the
+/// rational representation (e.g. 30000/1001 vs 2997/100)
+fn is_variable_frame_rate(fps: f64, avg_fps: f64) -> bool {
+    if fps <= 0.0 || avg_fps <= 0.0 {
+        return false;
+    }
+    (fps - avg_fps).abs() / fps > 0.005
+}
+
+/// How to count frames exactly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameCountMode {
+    /// Count demuxed packets; fast, matches the frame count for nearly all codecs
+    Packets,
+    /// Decode every frame; slow but always exact
+    Decode,
+}
+
+/// Identity of a file on disk, used to invalidate cached results
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileStamp {
+    pub path: String,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    /// Read the current size and modification time of a file
+    pub fn of(path: &str) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(FileStamp {
+            path: path.to_string(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
     }
 }
 
+fn frame_count_cache() -> &'static Mutex<HashMap<(FileStamp, FrameCountMode), u64>> {
+    static CACHE: OnceLock<Mutex<HashMap<(FileStamp, FrameCountMode), u64>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Look up an exact frame count from an earlier `count_frames` call
+pub fn cached_frame_count(path: &str, mode: FrameCountMode) -> Option<u64> {
+    let stamp = FileStamp::of(path)?;
+    let cache = frame_count_cache().lock().ok()?;
+    cache.get(&(stamp, mode)).copied()
+}
+
+/// Best cached frame count for a file, preferring a decoded count
+fn best_cached_frame_count(path: &str) -> Option<u64> {
+    cached_frame_count(path, FrameCountMode::Decode)
+        .or_else(|| cached_frame_count(path, FrameCountMode::Packets))
+}
+
+/// Count the video frames of a file exactly using ffprobe
+///
+/// This reads the whole file and can take a long time for large inputs.
+/// Results are cached until the file's size or modification time changes.
+pub fn count_frames(path: &str, mode: FrameCountMode) -> Result<u64, String> {
+    let stamp = FileStamp::of(path).ok_or_else(|| format!("Cannot read file '{}'", path))?;
+    if let Some(count) = cached_frame_count(path, mode) {
+        return Ok(count);
+    }
+
+    let (count_flag, entry) = match mode {
+        FrameCountMode::Packets => ("-count_packets", "stream=nb_read_packets"),
+        FrameCountMode::Decode => ("-count_frames", "stream=nb_read_frames"),
+    };
+
+    let output = Command::new(tools::ffprobe())
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            count_flag,
+            "-show_entries",
+            entry,
+            "-of",
+            "csv=p=0",
+            path,
+        ])
+        .output()
+        .map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        return Err("FFprobe failed to count frames".to_string());
+    }
+
+    let count = parse_frame_count(&output.stdout)?;
+    if let Ok(mut cache) = frame_count_cache().lock() {
+        cache.insert((stamp, mode), count);
+    }
+    Ok(count)
+}
+
+fn parse_frame_count(stdout: &[u8]) -> Result<u64, String> {
+    let text = String::from_utf8_lossy(stdout);
+    text.trim()
+        .trim_end_matches(',')
+        .parse::<u64>()
+        .map_err(|_| format!("Unexpected frame count output: '{}'", text.trim()))
+}
+
 /// Parse FPS from ffprobe format (e.g., "30/1" or "30000/1001")
 fn parse_fps(fps_str: &str) -> f64 {
     if fps_str.contains('/') {
@@ -175,17 +627,37 @@ fn parse_fps(fps_str: &str) -> f64 {
 /// Generate output path for converted video
 /// Returns an error if the output directory cannot be created
 pub fn get_output_path(input_path: &str) -> Result<String, String> {
-    let path = Path::new(input_path);
-    let parent = path.parent().unwrap_or(Path::new("."));
-    let stem = path
-        .file_stem()
-        .map(|s| s.to_string_lossy().to_string())
-        .unwrap_or_else(|| "output".to_string());
+    get_output_path_in(input_path, None, OutputFormat::Mp4)
+}
+
+/// Generate output path for a conversion to `format` inside `output_dir`,
+/// or in an `outputs` folder next to the input when no directory is given
+pub fn get_output_path_in(
+    input_path: &str,
+    output_dir: Option<&str>,
+    format: OutputFormat,
+) -> Result<String, String> {
+    get_rendition_output_path(input_path, output_dir, format, None)
+}
 
-    let output_dir = parent.join("outputs");
+/// Like `get_output_path_in`, with the rendition's name in the output name
+/// when one is given
+pub fn get_rendition_output_path(
+    input_path: &str,
+    output_dir: Option<&str>,
+    format: OutputFormat,
+    rendition: Option<&str>,
+) -> Result<String, String> {
+    let output_path = rendition_output_path(input_path, output_dir, format, rendition);
+    // Image sequences are written into a folder of their own
+    let output_dir = if format.is_sequence() {
+        output_path.as_path()
+    } else {
+        output_path.parent().unwrap_or(Path::new("."))
+    };
 
     // Create output directory if it doesn't exist
-    std::fs::create_dir_all(&output_dir).map_err(|e| {
+    std::fs::create_dir_all(output_dir).map_err(|e| {
         format!(
             "Failed to create output directory '{}': {}",
             output_dir.display(),
@@ -193,10 +665,63 @@ pub fn get_output_path(input_path: &str) -> Result<String, String> {
         )
     })?;
 
-    Ok(output_dir
-        .join(format!("{}_timelapse.mp4", stem))
-        .to_string_lossy()
-        .to_string())
+    Ok(output_path.to_string_lossy().to_string())
+}
+
+/// Output path `get_output_path_in` would use, without creating anything;
+/// for image sequences this is the folder the frames go into, and for HLS the
+/// playlist inside the package folder
+pub fn output_path_for(
+    input_path: &str,
+    output_dir: Option<&str>,
+    format: OutputFormat,
+) -> PathBuf {
+    rendition_output_path(input_path, output_dir, format, None)
+}
+
+/// Output path `get_rendition_output_path` would use, without creating
+/// anything: `clip_timelapse_<rendition>.mp4` and so on
+pub fn rendition_output_path(
+    input_path: &str,
+    output_dir: Option<&str>,
+    format: OutputFormat,
+    rendition: Option<&str>,
+) -> PathBuf {
+    let name = match rendition {
+        Some(rendition) => format!("_timelapse_{}", rendition),
+        None => "_timelapse".to_string(),
+    };
+    match format {
+        OutputFormat::Hls => output_path_with(input_path, output_dir, &format!("{}_hls", name))
+            .join(HLS_PLAYLIST_NAME),
+        OutputFormat::FragmentedMp4 => {
+            output_path_with(input_path, output_dir, &format!("{}_fragmented.mp4", name))
+        }
+        _ if format.is_sequence() => {
+            output_path_with(input_path, output_dir, &format!("{}_frames", name))
+        }
+        _ => {
+            let suffix = format!("{}.{}", name, format.extension());
+            output_path_with(input_path, output_dir, &suffix)
+        }
+    }
+}
+
+/// Path of an output named after the input's stem followed by `suffix`,
+/// in the same directory `output_path_for` uses
+pub fn output_path_with(input_path: &str, output_dir: Option<&str>, suffix: &str) -> PathBuf {
+    let path = Path::new(input_path);
+    let parent = path.parent().unwrap_or(Path::new("."));
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+
+    let output_dir = match output_dir {
+        Some(dir) => Path::new(dir).to_path_buf(),
+        None => parent.join("outputs"),
+    };
+    output_dir.join(format!("{}{}", stem, suffix))
 }
 
 #[cfg(test)]
@@ -286,6 +811,41 @@ mod tests {
         assert!(output_path.contains("test_timelapse.mp4"));
     }
 
+    #[test]
+    fn test_output_path_for_creates_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("clip.mov").to_string_lossy().to_string();
+
+        let output = output_path_for(&input, None, OutputFormat::Mp4);
+        assert_eq!(
+            output,
+            dir.path().join("outputs").join("clip_timelapse.mp4")
+        );
+        assert_eq!(
+            output_path_for(&input, Some("/exports"), OutputFormat::Gif),
+            Path::new("/exports").join("clip_timelapse.gif")
+        );
+        assert_eq!(
+            output_path_for(&input, Some("/exports"), OutputFormat::Mp4Faststart),
+            Path::new("/exports").join("clip_timelapse.mp4")
+        );
+        assert_eq!(
+            output_path_for(&input, Some("/exports"), OutputFormat::FragmentedMp4),
+            Path::new("/exports").join("clip_timelapse_fragmented.mp4")
+        );
+        assert_eq!(
+            rendition_output_path(&input, Some("/exports"), OutputFormat::Mp4, Some("web")),
+            Path::new("/exports").join("clip_timelapse_web.mp4")
+        );
+        assert_eq!(
+            rendition_output_path(&input, Some("/exports"), OutputFormat::Hls, Some("web")),
+            Path::new("/exports")
+                .join("clip_timelapse_web_hls")
+                .join("index.m3u8")
+        );
+        assert!(!dir.path().join("outputs").exists());
+    }
+
     #[test]
     fn test_get_output_path_preserves_stem() {
         let input = "/tmp/my_video_file.avi";
@@ -307,6 +867,7 @@ mod tests {
             total_frames: 3615,
             valid: true,
             error: None,
+            ..Default::default()
         };
 
         assert_eq!(info.path, "/test/video.mp4");
@@ -331,6 +892,7 @@ mod tests {
             total_frames: 0,
             valid: false,
             error: Some("Test error".to_string()),
+            ..Default::default()
         };
 
         assert!(!info.valid);
@@ -462,4 +1024,353 @@ mod tests {
         assert!(info.error.is_some());
         assert!(info.error.unwrap().contains("missing duration"));
     }
+
+    #[test]
+    fn test_parse_ffprobe_output_rich_metadata() {
+        let json_str = r#"{
+            "streams": [
+                {
+                    "codec_type": "video",
+                    "codec_name": "hevc",
+                    "profile": "Main 10",
+                    "pix_fmt": "yuv420p10le",
+                    "width": 3840,
+                    "height": 2160,
+                    "r_frame_rate": "30/1",
+                    "color_primaries": "bt2020",
+                    "color_transfer": "smpte2084",
+                    "field_order": "progressive",
+                    "nb_frames": "1800",
+                    "side_data_list": [
+                        { "side_data_type": "Display Matrix", "rotation": -90 }
+                    ]
+                },
+                {
+                    "codec_type": "audio",
+                    "codec_name": "aac"
+                }
+            ],
+            "format": {
+                "duration": "60.5",
+                "size": "123456789",
+                "bit_rate": "16324000",
+                "tags": { "creation_time": "2024-05-01T12:00:00.000000Z" }
+            }
+        }"#;
+        let info = parse_ffprobe_output("/path/test.mov", "test.mov", json_str.as_bytes());
+        assert!(info.valid);
+        assert_eq!(info.codec.as_deref(), Some("hevc"));
+        assert_eq!(info.profile.as_deref(), Some("Main 10"));
+        assert_eq!(info.pix_fmt.as_deref(), Some("yuv420p10le"));
+        assert_eq!(info.bit_depth, Some(10));
+        assert_eq!(info.bit_rate, Some(16324000));
+        assert_eq!(info.file_size, Some(123456789));
+        assert!(info.has_audio);
+        assert_eq!(info.audio_codec.as_deref(), Some("aac"));
+        assert_eq!(info.rotation, 90);
+        assert!(info.is_hdr);
+        assert!(!info.interlaced);
+        assert_eq!(
+            info.creation_time.as_deref(),
+            Some("2024-05-01T12:00:00.000000Z")
+        );
+        assert_eq!(info.nb_frames, Some(1800));
+        assert_eq!(info.total_frames, 1800);
+        assert!(info.needs_pixel_format_conversion());
+    }
+
+    #[test]
+    fn test_parse_ffprobe_output_interlaced_without_audio() {
+        let json_str = r#"{
+            "streams": [
+                {
+                    "codec_type": "video",
+                    "codec_name": "mpeg2video",
+                    "pix_fmt": "yuv420p",
+                    "width": 720,
+                    "height": 576,
+                    "r_frame_rate": "25/1",
+                    "field_order": "tt",
+                    "nb_frames": "0"
+                }
+            ],
+            "format": { "duration": "10.0" }
+        }"#;
+        let info = parse_ffprobe_output("/path/tape.mpg", "tape.mpg", json_str.as_bytes());
+        assert!(info.valid);
+        assert!(info.interlaced);
+        assert!(!info.has_audio);
+        assert!(!info.is_hdr);
+        assert_eq!(info.bit_depth, Some(8));
+        assert_eq!(info.nb_frames, None);
+        assert_eq!(info.total_frames, 250);
+        assert!(!info.needs_pixel_format_conversion());
+    }
+
+    #[test]
+    fn test_parse_rotation_legacy_tag() {
+        let stream = serde_json::json!({ "tags": { "rotate": "270" } });
+        assert_eq!(parse_rotation(&stream), 270);
+        let stream = serde_json::json!({ "tags": { "rotate": "-90" } });
+        assert_eq!(parse_rotation(&stream), 270);
+        assert_eq!(parse_rotation(&serde_json::json!({})), 0);
+    }
+
+    #[test]
+    fn test_bit_depth_from_pix_fmt() {
+        assert_eq!(bit_depth_from_pix_fmt("yuv420p"), 8);
+        assert_eq!(bit_depth_from_pix_fmt("yuv420p10le"), 10);
+        assert_eq!(bit_depth_from_pix_fmt("yuv444p12be"), 12);
+        assert_eq!(bit_depth_from_pix_fmt("p010le"), 10);
+        assert_eq!(bit_depth_from_pix_fmt("nv12"), 8);
+    }
+
+    #[test]
+    fn test_video_info_deserialize_legacy_json() {
+        // Older payloads without the extended metadata still deserialize
+        let json = r#"{"path":"/a.mp4","filename":"a.mp4","duration_secs":1.0,
+            "width":2,"height":2,"fps":30.0,"total_frames":30,"valid":true,"error":null}"#;
+        let info: VideoInfo = serde_json::from_str(json).unwrap();
+        assert!(info.valid);
+        assert_eq!(info.rotation, 0);
+        assert!(info.codec.is_none());
+    }
+
+    #[test]
+    fn test_parse_ffprobe_output_portrait_phone_clip() {
+        let json_str = r#"{
+            "streams": [
+                {
+                    "codec_type": "video",
+                    "width": 1920,
+                    "height": 1080,
+                    "r_frame_rate": "30/1",
+                    "side_data_list": [
+                        { "side_data_type": "Display Matrix", "rotation": 90 }
+                    ]
+                }
+            ],
+            "format": { "duration": "12.0" }
+        }"#;
+        let info = parse_ffprobe_output("/path/phone.mp4", "phone.mp4", json_str.as_bytes());
+        assert_eq!(info.rotation, 270);
+        assert_eq!((info.width, info.height), (1920, 1080));
+        assert_eq!((info.display_width, info.display_height), (1080, 1920));
+    }
+
+    #[test]
+    fn test_parse_ffprobe_output_provenance_tags() {
+        let json_str = r#"{
+            "streams": [
+                { "codec_type": "video", "width": 1920, "height": 1080, "r_frame_rate": "30/1" }
+            ],
+            "format": {
+                "duration": "6.0",
+                "tags": {
+                    "title": "site timelapse",
+                    "comment": "Timelapse of site.mov at 100x",
+                    "creation_time": "2024-05-01T08:00:00.000000Z",
+                    "timelapse_source": "site.mov",
+                    "timelapse_speed": "100",
+                    "TIMELAPSE_TRIM_START": "5.000",
+                    "timelapse_app_version": "1.2.0",
+                    "timelapse_source_creation_time": "2024-05-01T08:00:00.000000Z"
+                }
+            }
+        }"#;
+        let info = parse_ffprobe_output(
+            "/out/site_timelapse.mp4",
+            "site_timelapse.mp4",
+            json_str.as_bytes(),
+        );
+        assert_eq!(info.title.as_deref(), Some("site timelapse"));
+        assert_eq!(
+            info.comment.as_deref(),
+            Some("Timelapse of site.mov at 100x")
+        );
+
+        let provenance = info.provenance.unwrap();
+        assert_eq!(provenance.source.as_deref(), Some("site.mov"));
+        assert_eq!(provenance.speed_multiplier, Some(100));
+        assert_eq!(provenance.trim_start_secs, Some(5.0));
+        assert_eq!(provenance.trim_end_secs, None);
+        assert_eq!(provenance.app_version.as_deref(), Some("1.2.0"));
+        assert_eq!(
+            provenance.source_creation_time.as_deref(),
+            Some("2024-05-01T08:00:00.000000Z")
+        );
+    }
+
+    #[test]
+    fn test_provenance_tags_roundtrip() {
+        let provenance = Provenance {
+            source: Some("a.mp4".to_string()),
+            speed_multiplier: Some(10),
+            trim_end_secs: Some(30.0),
+            ..Default::default()
+        };
+        let tags = provenance.tags();
+        assert_eq!(
+            tags,
+            vec![
+                ("timelapse_source".to_string(), "a.mp4".to_string()),
+                ("timelapse_speed".to_string(), "10".to_string()),
+                ("timelapse_trim_end".to_string(), "30.000".to_string()),
+            ]
+        );
+
+        let json: serde_json::Map<String, serde_json::Value> = tags
+            .into_iter()
+            .map(|(k, v)| (k, serde_json::Value::String(v)))
+            .collect();
+        let parsed = Provenance::from_tags(&serde_json::Value::Object(json));
+        assert_eq!(parsed, Some(provenance));
+        assert_eq!(
+            Provenance::from_tags(&serde_json::json!({ "title": "x" })),
+            None
+        );
+    }
+
+    #[test]
+    fn test_with_rotation_override() {
+        let info = VideoInfo {
+            width: 1920,
+            height: 1080,
+            ..Default::default()
+        }
+        .with_rotation(90);
+        assert_eq!((info.display_width, info.display_height), (1080, 1920));
+
+        let info = info.with_rotation(540);
+        assert_eq!(info.rotation, 180);
+        assert_eq!((info.display_width, info.display_height), (1920, 1080));
+    }
+
+    #[test]
+    fn test_normalize_rotation() {
+        assert_eq!(normalize_rotation(0), Ok(0));
+        assert_eq!(normalize_rotation(270), Ok(270));
+        assert_eq!(normalize_rotation(450), Ok(90));
+        assert!(normalize_rotation(45).is_err());
+    }
+
+    #[test]
+    fn test_parse_ffprobe_output_vfr() {
+        let json_str = r#"{
+            "streams": [
+                {
+                    "codec_type": "video",
+                    "width": 1280,
+                    "height": 720,
+                    "r_frame_rate": "60/1",
+                    "avg_frame_rate": "2400/100"
+                }
+            ],
+            "format": { "duration": "100.0" }
+        }"#;
+        let info = parse_ffprobe_output("/path/screen.mp4", "screen.mp4", json_str.as_bytes());
+        assert!(info.is_vfr);
+        assert_eq!(info.avg_fps, 24.0);
+        assert_eq!(info.total_frames, 2400);
+        assert!(!info.frames_exact);
+    }
+
+    #[test]
+    fn test_is_variable_frame_rate() {
+        assert!(!is_variable_frame_rate(30000.0 / 1001.0, 29.97));
+        assert!(!is_variable_frame_rate(30.0, 30.0));
+        assert!(!is_variable_frame_rate(30.0, 0.0));
+        assert!(is_variable_frame_rate(90000.0, 29.5));
+        assert!(is_variable_frame_rate(30.0, 27.3));
+    }
+
+    #[test]
+    fn test_parse_frame_count() {
+        assert_eq!(parse_frame_count(b"1800\n"), Ok(1800));
+        assert_eq!(parse_frame_count(b"42,\n"), Ok(42));
+        assert!(parse_frame_count(b"N/A").is_err());
+    }
+
+    #[test]
+    fn test_file_stamp_missing_file() {
+        assert!(FileStamp::of("/nonexistent/path/video.mp4").is_none());
+        assert!(count_frames("/nonexistent/path/video.mp4", FrameCountMode::Packets).is_err());
+    }
+
+    #[test]
+    fn test_probe_cache_invalidated_by_modification() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clip.mp4");
+        std::fs::write(&path, b"first").unwrap();
+        let path_str = path.to_string_lossy().to_string();
+
+        let stamp = FileStamp::of(&path_str).unwrap();
+        let info = VideoInfo {
+            path: path_str.clone(),
+            valid: true,
+            ..Default::default()
+        };
+        store_probe(stamp.clone(), &info);
+        assert!(lookup_probe(&stamp).is_some());
+        assert!(get_info_cached(&path_str).valid);
+
+        // A different size produces a different stamp, so the entry is stale
+        std::fs::write(&path, b"second version").unwrap();
+        let new_stamp = FileStamp::of(&path_str).unwrap();
+        assert_ne!(stamp, new_stamp);
+        assert!(lookup_probe(&new_stamp).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_info_many_preserves_order() {
+        let paths = vec![
+            "/nonexistent/a.mp4".to_string(),
+            "/nonexistent/b.mp4".to_string(),
+            "/nonexistent/c.mp4".to_string(),
+        ];
+        let mut ready = Vec::new();
+        let infos = get_info_many(paths.clone(), |index, _| ready.push(index)).await;
+
+        assert_eq!(infos.len(), 3);
+        for (info, path) in infos.iter().zip(&paths) {
+            assert_eq!(&info.path, path);
+            assert!(!info.valid);
+        }
+        ready.sort();
+        assert_eq!(ready, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_probe_concurrency_bounds() {
+        let n = probe_concurrency();
+        assert!((2..=8).contains(&n));
+    }
+
+    #[test]
+    fn test_get_output_path_in_custom_directory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("outputs");
+        let dir_str = dir.to_string_lossy().to_string();
+        let output =
+            get_output_path_in("/some/where/clip.mov", Some(&dir_str), OutputFormat::Mp4).unwrap();
+        assert!(output.starts_with(&dir_str));
+        assert!(output.ends_with("clip_timelapse.mp4"));
+        assert!(dir.is_dir());
+
+        // Image sequences get a folder of their own
+        let frames = get_output_path_in(
+            "/some/where/clip.mov",
+            Some(&dir_str),
+            OutputFormat::PngSequence,
+        )
+        .unwrap();
+        assert!(frames.ends_with("clip_timelapse_frames"));
+        assert!(Path::new(&frames).is_dir());
+
+        // HLS packages too, with the playlist as the output
+        let playlist =
+            get_output_path_in("/some/where/clip.mov", Some(&dir_str), OutputFormat::Hls).unwrap();
+        assert!(playlist.ends_with("index.m3u8"));
+        assert!(dir.join("clip_timelapse_hls").is_dir());
+    }
 }